@@ -0,0 +1,390 @@
+use crate::elf::elf::ElfError;
+use crate::utils::mapped::MappedFile;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::ops::{Deref, Range};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::{CompressionMethod, ZipArchive};
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_SIZE: usize = 60;
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+// separates a zip file from the path of a member inside it, e.g., torch.whl!/torch/lib/libc10.so
+const ZIP_MEMBER_SEPARATOR: &str = "!/";
+
+/// The path of an object to analyze: a plain file, or a member of an ar archive or a zip file such as a wheel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectPath {
+    File(String),
+    /// A member of an ar archive, written `archive.a(member.o)`
+    ArMember {
+        archive: String,
+        member: String,
+    },
+    /// A member of a zip file, written `wheel.whl!/path/in/wheel`
+    ZipMember {
+        archive: String,
+        member: String,
+    },
+}
+
+impl ObjectPath {
+    /// Parse an object path, an existing file is always a plain file even if its name looks like a member
+    pub fn parse(path: &str) -> ObjectPath {
+        if Path::new(path).is_file() {
+            return ObjectPath::File(path.to_string());
+        }
+        if let Some((archive, member)) = path.split_once(ZIP_MEMBER_SEPARATOR) {
+            return ObjectPath::ZipMember {
+                archive: archive.to_string(),
+                member: member.to_string(),
+            };
+        }
+        // the archive name ends at the first '(' of the last path component
+        let file_name_start = path.rfind('/').map_or(0, |i| i + 1);
+        if let Some(inner) = path.strip_suffix(')') {
+            if let Some(open) = inner[file_name_start..].find('(') {
+                let open = file_name_start + open;
+                return ObjectPath::ArMember {
+                    archive: inner[..open].to_string(),
+                    member: inner[open + 1..].to_string(),
+                };
+            }
+        }
+        ObjectPath::File(path.to_string())
+    }
+
+    /// Check if the object is a member of an archive, i.e., has no file of its own
+    pub fn is_member(&self) -> bool {
+        !matches!(self, ObjectPath::File(_))
+    }
+}
+
+impl fmt::Display for ObjectPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectPath::File(path) => write!(f, "{}", path),
+            ObjectPath::ArMember { archive, member } => write!(f, "{}({})", archive, member),
+            ObjectPath::ZipMember { archive, member } => {
+                write!(f, "{}{}{}", archive, ZIP_MEMBER_SEPARATOR, member)
+            }
+        }
+    }
+}
+
+/// The content of an object
+///
+/// Plain files, ar members and stored zip members borrow the mapping of the file they are in,
+/// only compressed zip members are inflated into memory.
+pub enum ObjectData {
+    Mapped(MappedFile, Range<usize>),
+    Inflated(Vec<u8>),
+}
+
+impl Deref for ObjectData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ObjectData::Mapped(mapped, range) => &mapped[range.clone()],
+            ObjectData::Inflated(data) => data,
+        }
+    }
+}
+
+/// Open the object at the given path, see `ObjectPath` for the accepted forms
+pub fn open_object(path: &str) -> Result<ObjectData, ElfError> {
+    match ObjectPath::parse(path) {
+        ObjectPath::File(path) => {
+            let mapped = MappedFile::open(&path)?;
+            let range = 0..mapped.len();
+            Ok(ObjectData::Mapped(mapped, range))
+        }
+        ObjectPath::ArMember { archive, member } => {
+            let mapped = MappedFile::open(&archive)?;
+            let range = ar_members(&mapped)?
+                .into_iter()
+                .find(|(name, _)| *name == member)
+                .map(|(_, range)| range)
+                .ok_or_else(|| ElfError::MissingMember(path.to_string()))?;
+            Ok(ObjectData::Mapped(mapped, range))
+        }
+        ObjectPath::ZipMember { archive, member } => {
+            let mapped = MappedFile::open(&archive)?;
+            let mut zip = ZipArchive::new(Cursor::new(&mapped[..])).map_err(zip_error)?;
+            let mut file = match zip.by_name(&member) {
+                Err(ZipError::FileNotFound) => {
+                    return Err(ElfError::MissingMember(path.to_string()))
+                }
+                result => result.map_err(zip_error)?,
+            };
+            if file.compression() == CompressionMethod::Stored {
+                let start = file.data_start() as usize;
+                let range = start..start + file.size() as usize;
+                drop(file);
+                drop(zip);
+                if range.end > mapped.len() {
+                    return Err(ElfError::BadArchive("zip member out of file"));
+                }
+                return Ok(ObjectData::Mapped(mapped, range));
+            }
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data)?;
+            Ok(ObjectData::Inflated(data))
+        }
+    }
+}
+
+/// List the ELF objects in the file at the given path
+///
+/// Archives and zip files are expanded to the paths of their ELF members, in archive order,
+/// any other file is returned as is.
+pub fn list_objects(path: &str) -> Result<Vec<String>, ElfError> {
+    let mapped = MappedFile::open(path)?;
+    if mapped.starts_with(AR_MAGIC) {
+        return Ok(ar_members(&mapped)?
+            .into_iter()
+            .filter(|(_, range)| mapped[range.clone()].starts_with(ELF_MAGIC))
+            .map(|(member, _)| {
+                ObjectPath::ArMember {
+                    archive: path.to_string(),
+                    member,
+                }
+                .to_string()
+            })
+            .collect());
+    }
+    if mapped.starts_with(ZIP_MAGIC) {
+        let mut zip = ZipArchive::new(Cursor::new(&mapped[..])).map_err(zip_error)?;
+        let mut objects = vec![];
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(zip_error)?;
+            if file.is_dir() {
+                continue;
+            }
+            // only the magic is inflated, the member is inflated in full once opened
+            let mut magic = [0u8; 4];
+            if file.read_exact(&mut magic).is_ok() && magic == ELF_MAGIC {
+                objects.push(
+                    ObjectPath::ZipMember {
+                        archive: path.to_string(),
+                        member: file.name().to_string(),
+                    }
+                    .to_string(),
+                );
+            }
+        }
+        return Ok(objects);
+    }
+    Ok(vec![path.to_string()])
+}
+
+// get the name and data range of each member of an ar archive, symbol tables excluded
+// GNU archives store long names in the // member and refer to them as /offset,
+// BSD archives store them at the start of the member data as #1/length
+fn ar_members(data: &[u8]) -> Result<Vec<(String, Range<usize>)>, ElfError> {
+    if !data.starts_with(AR_MAGIC) {
+        return Err(ElfError::BadArchive("missing ar magic"));
+    }
+    let mut members = vec![];
+    let mut long_names: &[u8] = &[];
+    let mut offset = AR_MAGIC.len();
+    while offset + AR_HEADER_SIZE <= data.len() {
+        let header = &data[offset..offset + AR_HEADER_SIZE];
+        if &header[58..60] != b"`\n" {
+            return Err(ElfError::BadArchive("bad ar member header"));
+        }
+        let size: usize = std::str::from_utf8(&header[48..58])
+            .ok()
+            .and_then(|size| size.trim().parse().ok())
+            .ok_or(ElfError::BadArchive("bad ar member size"))?;
+        let start = offset + AR_HEADER_SIZE;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= data.len())
+            .ok_or(ElfError::BadArchive("ar member out of file"))?;
+        // members are 2-byte aligned
+        offset = end + end % 2;
+
+        let raw_name = std::str::from_utf8(&header[0..16])
+            .map_err(|_| ElfError::BadArchive("bad ar member name"))?
+            .trim_end();
+        match raw_name {
+            "/" | "/SYM64/" | "__.SYMDEF" | "__.SYMDEF SORTED" => continue,
+            "//" => {
+                long_names = &data[start..end];
+                continue;
+            }
+            _ => {}
+        }
+        let (name, start) = if let Some(length) = raw_name.strip_prefix("#1/") {
+            let length: usize = length
+                .parse()
+                .ok()
+                .filter(|&length| length <= size)
+                .ok_or(ElfError::BadArchive("bad BSD ar member name"))?;
+            let name = &data[start..start + length];
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            (String::from_utf8_lossy(name).into_owned(), start + length)
+        } else if let Some(name_offset) = raw_name.strip_prefix('/') {
+            let name = name_offset
+                .parse::<usize>()
+                .ok()
+                .and_then(|name_offset| long_names.get(name_offset..))
+                .and_then(|names| names.split(|&b| b == b'\n').next())
+                .ok_or(ElfError::BadArchive("bad GNU ar long name"))?;
+            let name = name.strip_suffix(b"/").unwrap_or(name);
+            (String::from_utf8_lossy(name).into_owned(), start)
+        } else {
+            (raw_name.trim_end_matches('/').to_string(), start)
+        };
+        members.push((name, start..end));
+    }
+    Ok(members)
+}
+
+fn zip_error(e: ZipError) -> ElfError {
+    match e {
+        ZipError::Io(e) => ElfError::Io(e),
+        _ => ElfError::BadArchive("bad zip file"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::elf::{Elf, FatbinSource};
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_object_path() {
+        let archive = fixture("libsynth.a");
+        assert_eq!(
+            ObjectPath::parse(&format!("{}(synth.o)", archive)),
+            ObjectPath::ArMember {
+                archive: archive.clone(),
+                member: "synth.o".to_string(),
+            }
+        );
+        assert_eq!(
+            ObjectPath::parse("/wheels/torch-2.3.whl!/torch/lib/libc10(x).so"),
+            ObjectPath::ZipMember {
+                archive: "/wheels/torch-2.3.whl".to_string(),
+                member: "torch/lib/libc10(x).so".to_string(),
+            }
+        );
+        // parentheses in directory names are not archive members
+        assert_eq!(
+            ObjectPath::parse("/opt/cuda(12)/lib/libcudart.so"),
+            ObjectPath::File("/opt/cuda(12)/lib/libcudart.so".to_string())
+        );
+        assert!(!ObjectPath::parse(&archive).is_member());
+
+        for path in [
+            "/lib/libfoo.a(foo.o)",
+            "/wheels/foo.whl!/foo/libfoo.so",
+            "/lib/libfoo.so",
+        ] {
+            assert_eq!(ObjectPath::parse(path).to_string(), path);
+        }
+    }
+
+    #[test]
+    fn test_ar_members() {
+        let archive = fixture("libsynth.a");
+        assert_eq!(
+            list_objects(&archive).unwrap(),
+            vec![
+                format!("{}(synth.o)", archive),
+                format!("{}(fatbins_with_long_name.o)", archive),
+            ]
+        );
+
+        // members are read in place, without a copy
+        let synth = std::fs::read(fixture("libsynth.64le.so")).unwrap();
+        let member = open_object(&format!("{}(synth.o)", archive)).unwrap();
+        assert!(matches!(member, ObjectData::Mapped(..)));
+        assert_eq!(&member[..], &synth[..]);
+
+        let member = open_object(&format!("{}(fatbins_with_long_name.o)", archive)).unwrap();
+        let elf = Elf::new(&member).unwrap();
+        assert_eq!(elf.find_fatbins().unwrap().len(), 4);
+
+        let err = open_object(&format!("{}(missing.o)", archive))
+            .err()
+            .unwrap();
+        assert!(matches!(err, ElfError::MissingMember(_)));
+    }
+
+    #[test]
+    fn test_bsd_ar_members() {
+        let mut data = AR_MAGIC.to_vec();
+        data.extend_from_slice(b"#1/20           0           0     0     644     25        `\n");
+        data.extend_from_slice(b"a_long_bsd_name.o\0\0\0");
+        data.extend_from_slice(b"\x7fELF\n\n");
+        let members = ar_members(&data).unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].0, "a_long_bsd_name.o");
+        assert_eq!(&data[members[0].1.clone()], b"\x7fELF\n");
+
+        assert!(matches!(
+            ar_members(b"!<arch>\nshort"),
+            Ok(members) if members.is_empty()
+        ));
+        let mut truncated = AR_MAGIC.to_vec();
+        truncated
+            .extend_from_slice(b"foo.o/          0           0     0     644     99        `\n");
+        assert!(matches!(
+            ar_members(&truncated),
+            Err(ElfError::BadArchive(_))
+        ));
+    }
+
+    #[test]
+    fn test_zip_members() {
+        let wheel = fixture("synth-0.1-py3-none-any.whl");
+        let objects = list_objects(&wheel).unwrap();
+        assert_eq!(
+            objects,
+            vec![
+                format!("{}!/synth/lib/libsynth.so", wheel),
+                format!("{}!/synth/lib/libfatbins.so", wheel),
+            ]
+        );
+
+        // stored members are read in place, deflated ones are inflated
+        let synth = open_object(&objects[0]).unwrap();
+        assert!(matches!(synth, ObjectData::Mapped(..)));
+        assert_eq!(
+            &synth[..],
+            &std::fs::read(fixture("libsynth.64le.so")).unwrap()[..]
+        );
+        let fatbins = open_object(&objects[1]).unwrap();
+        assert!(matches!(fatbins, ObjectData::Inflated(_)));
+        let elf = Elf::new(&fatbins).unwrap();
+        assert_eq!(
+            elf.find_fatbins().unwrap()[0].source,
+            FatbinSource::Section(".nv_fatbin")
+        );
+
+        let err = open_object(&format!("{}!/synth/missing.so", wheel))
+            .err()
+            .unwrap();
+        assert!(matches!(err, ElfError::MissingMember(_)));
+        assert_eq!(
+            list_objects(&fixture("libdemo.so")).unwrap(),
+            vec![fixture("libdemo.so")]
+        );
+    }
+}
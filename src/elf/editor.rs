@@ -0,0 +1,636 @@
+use crate::elf::elf::ElfError;
+use elf::abi::{
+    DT_NEEDED, DT_NULL, DT_RPATH, DT_RUNPATH, DT_STRSZ, DT_STRTAB, PF_R, PF_W, PT_DYNAMIC, PT_LOAD,
+    PT_PHDR, SHT_DYNAMIC, SHT_STRTAB,
+};
+use elf::endian::AnyEndian;
+use elf::file::Class;
+use elf::segment::ProgramHeader;
+use elf::ElfBytes;
+
+// the smallest page size the new segment is aligned to, larger PT_LOAD alignments win
+const MIN_PAGE_SIZE: u64 = 0x1000;
+// e_phnum values from PN_XNUM on are stored in the first section header, which is not supported
+const PN_XNUM: usize = 0xffff;
+
+/// The value of a dynamic entry being edited, string values are placed in .dynstr when the edits are applied
+#[derive(Debug, Clone, PartialEq)]
+enum DynValue {
+    Raw(u64),
+    Str(String),
+}
+
+/// Edits the DT_NEEDED, DT_RUNPATH and DT_RPATH entries of an ELF file
+///
+/// Edits are applied by `finish`. Strings already in .dynstr are reused, e.g., when renaming a dependency to
+/// another one it already has. Otherwise, .dynstr is copied with the new strings appended into a new PT_LOAD
+/// segment at the end of the file, together with the program headers, and the dynamic section if it has no room
+/// left for the new entries. Everything else keeps its file offset and address.
+pub struct DynamicEditor {
+    data: Vec<u8>,
+    is_64bit: bool,
+    is_big_endian: bool,
+    e_phentsize: usize,
+    e_shoff: u64,
+    e_shentsize: usize,
+    phdrs: Vec<ProgramHeader>,
+    // the index of PT_DYNAMIC in phdrs, and the number of entries it has room for
+    dynamic_index: usize,
+    dynamic_capacity: usize,
+    // the entries before the first DT_NULL
+    entries: Vec<(i64, DynValue)>,
+    dynstr: Vec<u8>,
+    // the section headers to update when .dynstr or .dynamic move
+    dynstr_section: Option<usize>,
+    dynamic_section: Option<usize>,
+}
+
+impl DynamicEditor {
+    /// Create an editor for the given file content
+    pub fn new(data: Vec<u8>) -> Result<DynamicEditor, ElfError> {
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(&data)?;
+        let is_64bit = elf.ehdr.class == Class::ELF64;
+        let is_big_endian = elf.ehdr.endianness == AnyEndian::Big;
+        let phdrs: Vec<ProgramHeader> = elf
+            .segments()
+            .ok_or(ElfError::MissingSegment("PT_DYNAMIC"))?
+            .iter()
+            .collect();
+        let dynamic_index = phdrs
+            .iter()
+            .position(|p| p.p_type == PT_DYNAMIC)
+            .ok_or(ElfError::MissingSegment("PT_DYNAMIC"))?;
+
+        let entry_size = if is_64bit { 16 } else { 8 };
+        let dynamic = &phdrs[dynamic_index];
+        let dynamic_data = data
+            .get(dynamic.p_offset as usize..(dynamic.p_offset + dynamic.p_filesz) as usize)
+            .ok_or(ElfError::Truncated)?;
+        let dynamic_capacity = dynamic_data.len() / entry_size;
+        let mut raw_entries = vec![];
+        for entry in dynamic_data.chunks_exact(entry_size) {
+            let (tag, value) = if is_64bit {
+                (
+                    read_u64(&entry[0..8], is_big_endian) as i64,
+                    read_u64(&entry[8..16], is_big_endian),
+                )
+            } else {
+                (
+                    read_u32(&entry[0..4], is_big_endian) as i32 as i64,
+                    read_u32(&entry[4..8], is_big_endian) as u64,
+                )
+            };
+            if tag == DT_NULL {
+                break;
+            }
+            raw_entries.push((tag, value));
+        }
+
+        let find = |tag: i64| raw_entries.iter().find(|(t, _)| *t == tag).map(|(_, v)| *v);
+        let (strtab_addr, strsz) = find(DT_STRTAB)
+            .zip(find(DT_STRSZ))
+            .ok_or(ElfError::MissingStringTable(".dynstr"))?;
+        let strtab_offset = phdrs
+            .iter()
+            .filter(|p| p.p_type == PT_LOAD)
+            .find(|p| strtab_addr >= p.p_vaddr && strtab_addr - p.p_vaddr < p.p_filesz)
+            .map(|p| p.p_offset + (strtab_addr - p.p_vaddr))
+            .ok_or(ElfError::UnmappedAddress(strtab_addr))?;
+        let dynstr = data
+            .get(strtab_offset as usize..(strtab_offset + strsz) as usize)
+            .ok_or(ElfError::Truncated)?
+            .to_vec();
+
+        let mut entries = vec![];
+        for (tag, value) in raw_entries {
+            let value = match tag {
+                DT_NEEDED | DT_RPATH | DT_RUNPATH => {
+                    let s = dynstr
+                        .get(value as usize..)
+                        .and_then(|s| s.split(|&b| b == 0).next())
+                        .ok_or(ElfError::BadSymbolIndex(value as usize))?;
+                    DynValue::Str(String::from_utf8_lossy(s).into_owned())
+                }
+                _ => DynValue::Raw(value),
+            };
+            entries.push((tag, value));
+        }
+
+        let (mut dynstr_section, mut dynamic_section) = (None, None);
+        if let Some(shdrs) = elf.section_headers() {
+            for (i, shdr) in shdrs.iter().enumerate() {
+                if shdr.sh_type == SHT_STRTAB && shdr.sh_addr == strtab_addr && strtab_addr != 0 {
+                    dynstr_section = Some(i);
+                } else if shdr.sh_type == SHT_DYNAMIC {
+                    dynamic_section = Some(i);
+                }
+            }
+        }
+
+        Ok(DynamicEditor {
+            is_64bit,
+            is_big_endian,
+            e_phentsize: elf.ehdr.e_phentsize as usize,
+            e_shoff: elf.ehdr.e_shoff,
+            e_shentsize: elf.ehdr.e_shentsize as usize,
+            phdrs,
+            dynamic_index,
+            dynamic_capacity,
+            entries,
+            dynstr,
+            dynstr_section,
+            dynamic_section,
+            data,
+        })
+    }
+
+    /// Set DT_RUNPATH, replacing any DT_RUNPATH and DT_RPATH entries
+    pub fn set_runpath(&mut self, runpath: &str) {
+        self.set_search_path(DT_RUNPATH, runpath);
+    }
+
+    /// Set DT_RPATH, replacing any DT_RPATH and DT_RUNPATH entries, since DT_RUNPATH would disable DT_RPATH
+    pub fn set_rpath(&mut self, rpath: &str) {
+        self.set_search_path(DT_RPATH, rpath);
+    }
+
+    // replace the first search path entry, or add one after the DT_NEEDED entries, and drop the others
+    fn set_search_path(&mut self, tag: i64, value: &str) {
+        let is_search_path = |t: i64| t == DT_RPATH || t == DT_RUNPATH;
+        let index = self
+            .entries
+            .iter()
+            .position(|(t, _)| is_search_path(*t))
+            .unwrap_or_else(|| self.after_needed());
+        self.entries.retain(|(t, _)| !is_search_path(*t));
+        self.entries
+            .insert(index, (tag, DynValue::Str(value.to_string())));
+    }
+
+    /// Remove the DT_RUNPATH and DT_RPATH entries, returns whether there were any
+    pub fn remove_runpath(&mut self) -> bool {
+        let len = self.entries.len();
+        self.entries
+            .retain(|(t, _)| *t != DT_RPATH && *t != DT_RUNPATH);
+        self.entries.len() != len
+    }
+
+    /// Add a DT_NEEDED entry after the existing ones, returns false if the name is already needed
+    pub fn add_needed(&mut self, name: &str) -> bool {
+        if self.needed_index(name).is_some() {
+            return false;
+        }
+        let index = self.after_needed();
+        self.entries
+            .insert(index, (DT_NEEDED, DynValue::Str(name.to_string())));
+        true
+    }
+
+    /// Remove the DT_NEEDED entry with the given name, returns false if there is none
+    pub fn remove_needed(&mut self, name: &str) -> bool {
+        match self.needed_index(name) {
+            Some(index) => {
+                self.entries.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rename the DT_NEEDED entry with the given name, keeping its position, returns false if there is none
+    pub fn replace_needed(&mut self, name: &str, new_name: &str) -> bool {
+        match self.needed_index(name) {
+            Some(index) => {
+                self.entries[index].1 = DynValue::Str(new_name.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn needed_index(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(t, v)| *t == DT_NEEDED && *v == DynValue::Str(name.to_string()))
+    }
+
+    // the index right after the last DT_NEEDED entry, or 0 if there is none
+    fn after_needed(&self) -> usize {
+        self.entries
+            .iter()
+            .rposition(|(t, _)| *t == DT_NEEDED)
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Apply the edits and return the new file content
+    pub fn finish(mut self) -> Result<Vec<u8>, ElfError> {
+        // place the strings, reusing the ones already in .dynstr
+        let original_strsz = self.dynstr.len();
+        let mut entries = vec![];
+        for (tag, value) in std::mem::take(&mut self.entries) {
+            let value = match value {
+                DynValue::Raw(value) => value,
+                DynValue::Str(s) => match find_string(&self.dynstr, &s) {
+                    Some(offset) => offset as u64,
+                    None => {
+                        let offset = self.dynstr.len() as u64;
+                        self.dynstr.extend_from_slice(s.as_bytes());
+                        self.dynstr.push(0);
+                        offset
+                    }
+                },
+            };
+            entries.push((tag, value));
+        }
+        let move_dynstr = self.dynstr.len() != original_strsz;
+        // keep at least one DT_NULL
+        let move_dynamic = entries.len() + 1 > self.dynamic_capacity;
+        if !move_dynstr && !move_dynamic {
+            let dynamic = self.phdrs[self.dynamic_index];
+            self.write_dynamic(dynamic.p_offset, &entries, self.dynamic_capacity);
+            return Ok(self.data);
+        }
+
+        // the new segment keeps the offset to address delta of the first PT_LOAD, which older kernels
+        // assume when computing AT_PHDR from e_phoff
+        let loads: Vec<&ProgramHeader> =
+            self.phdrs.iter().filter(|p| p.p_type == PT_LOAD).collect();
+        let first_load = loads
+            .iter()
+            .min_by_key(|p| p.p_vaddr)
+            .ok_or(ElfError::MissingSegment("PT_LOAD"))?;
+        let delta = first_load.p_vaddr.wrapping_sub(first_load.p_offset);
+        let page_size = loads
+            .iter()
+            .map(|p| p.p_align)
+            .fold(MIN_PAGE_SIZE, u64::max);
+        let memory_end = loads.iter().map(|p| p.p_vaddr + p.p_memsz).max().unwrap();
+        let segment_offset = (self.data.len() as u64)
+            .max(memory_end.wrapping_sub(delta))
+            .next_multiple_of(page_size);
+        let segment_addr = segment_offset.wrapping_add(delta);
+
+        if self.phdrs.len() + 1 >= PN_XNUM {
+            return Err(ElfError::Unsupported("too many program headers"));
+        }
+        let phdrs_size = ((self.phdrs.len() + 1) * self.e_phentsize) as u64;
+        let mut segment_size = phdrs_size;
+        let mut dynstr_offset = None;
+        if move_dynstr {
+            dynstr_offset = Some(segment_offset + segment_size);
+            segment_size += self.dynstr.len() as u64;
+        }
+        let entry_size = if self.is_64bit { 16 } else { 8 };
+        let mut dynamic_offset = None;
+        if move_dynamic {
+            segment_size = segment_size.next_multiple_of(entry_size);
+            dynamic_offset = Some(segment_offset + segment_size);
+            segment_size += (entries.len() + 1) as u64 * entry_size;
+        }
+
+        // update the program headers, the new PT_LOAD goes after the last one to keep them sorted
+        let to_addr = |offset: u64| offset.wrapping_add(delta);
+        for phdr in self.phdrs.iter_mut() {
+            if phdr.p_type == PT_PHDR {
+                phdr.p_offset = segment_offset;
+                phdr.p_vaddr = segment_addr;
+                phdr.p_paddr = segment_addr;
+                phdr.p_filesz = phdrs_size;
+                phdr.p_memsz = phdrs_size;
+            }
+        }
+        if let Some(offset) = dynamic_offset {
+            let dynamic = &mut self.phdrs[self.dynamic_index];
+            let size = (entries.len() + 1) as u64 * entry_size;
+            dynamic.p_offset = offset;
+            dynamic.p_vaddr = to_addr(offset);
+            dynamic.p_paddr = to_addr(offset);
+            dynamic.p_filesz = size;
+            dynamic.p_memsz = size;
+        }
+        let last_load = self
+            .phdrs
+            .iter()
+            .rposition(|p| p.p_type == PT_LOAD)
+            .unwrap();
+        self.phdrs.insert(
+            last_load + 1,
+            ProgramHeader {
+                p_type: PT_LOAD,
+                p_offset: segment_offset,
+                p_vaddr: segment_addr,
+                p_paddr: segment_addr,
+                p_filesz: segment_size,
+                p_memsz: segment_size,
+                // the dynamic linker writes to the dynamic section, e.g., DT_DEBUG
+                p_flags: if move_dynamic { PF_R | PF_W } else { PF_R },
+                p_align: page_size,
+            },
+        );
+
+        if let Some(offset) = dynstr_offset {
+            for (tag, value) in entries.iter_mut() {
+                match *tag {
+                    DT_STRTAB => *value = to_addr(offset),
+                    DT_STRSZ => *value = self.dynstr.len() as u64,
+                    _ => {}
+                }
+            }
+        }
+
+        self.data
+            .resize((segment_offset + segment_size) as usize, 0);
+        for (i, phdr) in self.phdrs.clone().iter().enumerate() {
+            self.write_phdr(segment_offset + (i * self.e_phentsize) as u64, phdr);
+        }
+        if let Some(offset) = dynstr_offset {
+            let start = offset as usize;
+            self.data[start..start + self.dynstr.len()].copy_from_slice(&self.dynstr);
+            if let Some(index) = self.dynstr_section {
+                self.write_section(index, to_addr(offset), offset, self.dynstr.len() as u64);
+            }
+        }
+        let dynamic = self
+            .phdrs
+            .iter()
+            .find(|p| p.p_type == PT_DYNAMIC)
+            .copied()
+            .unwrap();
+        let capacity = match dynamic_offset {
+            Some(_) => entries.len() + 1,
+            None => self.dynamic_capacity,
+        };
+        self.write_dynamic(dynamic.p_offset, &entries, capacity);
+        if let (Some(index), Some(offset)) = (self.dynamic_section, dynamic_offset) {
+            self.write_section(index, dynamic.p_vaddr, offset, dynamic.p_filesz);
+        }
+        self.write_file_header(segment_offset, self.phdrs.len() as u16);
+        Ok(self.data)
+    }
+
+    // write the entries followed by DT_NULL up to the capacity
+    fn write_dynamic(&mut self, offset: u64, entries: &[(i64, u64)], capacity: usize) {
+        let entry_size = if self.is_64bit { 16 } else { 8 };
+        let padding = std::iter::repeat_n((DT_NULL, 0), capacity - entries.len());
+        for (i, (tag, value)) in entries.iter().copied().chain(padding).enumerate() {
+            let at = offset + (i * entry_size) as u64;
+            self.write_word(at, tag as u64);
+            self.write_word(at + entry_size as u64 / 2, value);
+        }
+    }
+
+    fn write_phdr(&mut self, offset: u64, phdr: &ProgramHeader) {
+        self.write_u32(offset, phdr.p_type);
+        if self.is_64bit {
+            self.write_u32(offset + 4, phdr.p_flags);
+            let fields = [
+                phdr.p_offset,
+                phdr.p_vaddr,
+                phdr.p_paddr,
+                phdr.p_filesz,
+                phdr.p_memsz,
+                phdr.p_align,
+            ];
+            for (i, field) in fields.into_iter().enumerate() {
+                self.write_word(offset + 8 + 8 * i as u64, field);
+            }
+        } else {
+            let fields = [
+                phdr.p_offset,
+                phdr.p_vaddr,
+                phdr.p_paddr,
+                phdr.p_filesz,
+                phdr.p_memsz,
+            ];
+            for (i, field) in fields.into_iter().enumerate() {
+                self.write_word(offset + 4 + 4 * i as u64, field);
+            }
+            self.write_u32(offset + 24, phdr.p_flags);
+            self.write_word(offset + 28, phdr.p_align);
+        }
+    }
+
+    // update sh_addr, sh_offset and sh_size of a section header
+    fn write_section(&mut self, index: usize, addr: u64, offset: u64, size: u64) {
+        let shdr = self.e_shoff + (index * self.e_shentsize) as u64;
+        let word = if self.is_64bit { 8 } else { 4 };
+        let sh_addr = shdr + 8 + word;
+        self.write_word(sh_addr, addr);
+        self.write_word(sh_addr + word, offset);
+        self.write_word(sh_addr + 2 * word, size);
+    }
+
+    fn write_file_header(&mut self, e_phoff: u64, e_phnum: u16) {
+        let (phoff_at, phnum_at) = if self.is_64bit {
+            (0x20, 0x38)
+        } else {
+            (0x1c, 0x2c)
+        };
+        self.write_word(phoff_at, e_phoff);
+        let bytes = if self.is_big_endian {
+            e_phnum.to_be_bytes()
+        } else {
+            e_phnum.to_le_bytes()
+        };
+        self.data[phnum_at..phnum_at + 2].copy_from_slice(&bytes);
+    }
+
+    // write a word of the file class, i.e., 8 bytes for ELFCLASS64 and 4 bytes for ELFCLASS32
+    fn write_word(&mut self, offset: u64, value: u64) {
+        if self.is_64bit {
+            let bytes = if self.is_big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            self.data[offset as usize..offset as usize + 8].copy_from_slice(&bytes);
+        } else {
+            self.write_u32(offset, value as u32);
+        }
+    }
+
+    fn write_u32(&mut self, offset: u64, value: u32) {
+        let bytes = if self.is_big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.data[offset as usize..offset as usize + 4].copy_from_slice(&bytes);
+    }
+}
+
+// find the offset of a string in a string table, the tail of a longer string is reused as well
+fn find_string(table: &[u8], s: &str) -> Option<usize> {
+    let needle: Vec<u8> = s.bytes().chain([0]).collect();
+    table.windows(needle.len()).position(|w| w == needle)
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = bytes.try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn read_u64(bytes: &[u8], big_endian: bool) -> u64 {
+    let bytes = bytes.try_into().unwrap();
+    if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::elf::Elf;
+    use crate::elf::resolver::DependencyResolver;
+    use elf::abi::PT_NOTE;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn edit(name: &str, edits: impl FnOnce(&mut DynamicEditor)) -> Vec<u8> {
+        let mut editor = DynamicEditor::new(std::fs::read(fixture(name)).unwrap()).unwrap();
+        edits(&mut editor);
+        editor.finish().unwrap()
+    }
+
+    fn load_segments(data: &[u8]) -> Vec<ProgramHeader> {
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(data).unwrap();
+        elf.segments()
+            .unwrap()
+            .iter()
+            .filter(|p| p.p_type == PT_LOAD)
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_in_place() {
+        let original = std::fs::read(fixture("deps/lib/libfirst.so")).unwrap();
+        let data = edit("deps/lib/libfirst.so", |editor| {
+            assert!(editor.remove_needed("libmissing.so.1"));
+            assert!(!editor.remove_needed("libmissing.so.1"));
+        });
+        assert_eq!(data.len(), original.len());
+        let elf = Elf::new(&data).unwrap();
+        assert_eq!(elf.get_needed().unwrap(), vec!["libsecond.so"]);
+        assert_eq!(elf.get_soname().unwrap().as_deref(), Some("libfirst.so"));
+
+        // the RPATH string is reused for the RUNPATH
+        let original = std::fs::read(fixture("deps/bin/app")).unwrap();
+        let data = edit("deps/bin/app", |editor| {
+            editor.set_runpath("$ORIGIN/../lib")
+        });
+        assert_eq!(data.len(), original.len());
+        let elf = Elf::new(&data).unwrap();
+        assert!(elf.get_rpath().unwrap().is_empty());
+        assert_eq!(elf.get_runpath().unwrap(), vec!["$ORIGIN/../lib"]);
+
+        let data = edit("deps/bin/app", |editor| assert!(editor.remove_runpath()));
+        let elf = Elf::new(&data).unwrap();
+        assert!(elf.get_rpath().unwrap().is_empty() && elf.get_runpath().unwrap().is_empty());
+        assert_eq!(
+            elf.get_interpreter().unwrap().as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+    }
+
+    #[test]
+    fn test_grow_dynstr() {
+        let original = std::fs::read(fixture("deps/lib/libfirst.so")).unwrap();
+        let data = edit("deps/lib/libfirst.so", |editor| {
+            assert!(editor.replace_needed("libsecond.so", "libsecond.debloated.so"));
+        });
+        let elf = Elf::new(&data).unwrap();
+        assert_eq!(
+            elf.get_needed().unwrap(),
+            vec!["libsecond.debloated.so", "libmissing.so.1"]
+        );
+        // symbols and device code are where they were
+        assert_eq!(elf.get_symbol_addr(b"fixture_add").unwrap(), Some(0x410));
+        assert_eq!(elf.get_gpu_code_offset().unwrap(), Some(0x440));
+
+        // one more PT_LOAD, page aligned after the original content, mapping the new .dynstr
+        let loads = load_segments(&data);
+        assert_eq!(loads.len(), load_segments(&original).len() + 1);
+        let segment = loads.last().unwrap();
+        assert_eq!(segment.p_offset % 0x1000, 0);
+        assert!(segment.p_offset >= original.len() as u64);
+        assert_eq!(segment.p_flags, PF_R);
+        assert_eq!(data.len() as u64, segment.p_offset + segment.p_filesz);
+        // .text and .nv_fatbin are kept byte for byte
+        assert!(data[0x400..0xbb0] == original[0x400..0xbb0]);
+    }
+
+    #[test]
+    fn test_move_dynamic_all_classes() {
+        for name in [
+            "libsynth.64le.so",
+            "libsynth.32le.so",
+            "libsynth.64be.so",
+            "libsynth.32be.so",
+        ] {
+            let data = edit(name, |editor| {
+                assert!(editor.add_needed("libextra.so"));
+                assert!(!editor.add_needed("libextra.so"));
+                editor.set_runpath("$ORIGIN/debloated");
+            });
+            let elf = Elf::new(&data).unwrap();
+            assert_eq!(elf.get_needed().unwrap(), vec!["libextra.so"], "{}", name);
+            assert_eq!(elf.get_runpath().unwrap(), vec!["$ORIGIN/debloated"]);
+            assert_eq!(elf.get_soname().unwrap().as_deref(), Some(name));
+            // version definitions still resolve their names from .dynstr
+            let info = elf.get_symbol_info(b"fixture_versioned@@VERS_2").unwrap();
+            assert_eq!(info.unwrap().version.as_deref(), Some("VERS_2"));
+            assert!(elf.get_build_id().unwrap().is_some());
+
+            // the dynamic section moved into the new writable segment
+            let parsed = ElfBytes::<AnyEndian>::minimal_parse(&data).unwrap();
+            let segments: Vec<ProgramHeader> = parsed.segments().unwrap().iter().collect();
+            let dynamic = segments.iter().find(|p| p.p_type == PT_DYNAMIC).unwrap();
+            let segment = load_segments(&data).pop().unwrap();
+            assert_eq!(segment.p_flags, PF_R | PF_W);
+            assert!(dynamic.p_offset >= segment.p_offset);
+            assert!(segments.iter().any(|p| p.p_type == PT_NOTE));
+        }
+    }
+
+    #[test]
+    fn test_retarget_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("app");
+        let private = PathBuf::from(fixture("deps/lib/private"));
+        let data = edit("deps/bin/app", |editor| {
+            assert!(editor.replace_needed("libfirst.so", "libthird.so"));
+            assert!(!editor.replace_needed("libfirst.so", "libthird.so"));
+            editor.set_runpath(private.to_str().unwrap());
+        });
+        std::fs::write(&app, data).unwrap();
+
+        let resolver = DependencyResolver::new(vec![], None, Some(vec![]));
+        let graph = resolver.resolve(app.to_str().unwrap()).unwrap();
+        let third = std::fs::canonicalize(private.join("libthird.so")).unwrap();
+        assert_eq!(graph.libraries(), vec![third.to_str().unwrap()]);
+        assert!(graph.unresolved().is_empty());
+    }
+
+    #[test]
+    fn test_find_string() {
+        let table = b"\0libfoo.so\0$ORIGIN\0";
+        assert_eq!(find_string(table, "libfoo.so"), Some(1));
+        assert_eq!(find_string(table, "foo.so"), Some(4));
+        assert_eq!(find_string(table, ""), Some(0));
+        assert_eq!(find_string(table, "libfoo"), None);
+    }
+}
@@ -1,159 +1,1107 @@
-use elf::abi::{PF_R, PF_X, PT_LOAD};
+use elf::abi::{
+    DT_NEEDED, DT_RPATH, DT_RUNPATH, DT_SONAME, DT_STRSZ, DT_STRTAB, EM_AARCH64, EM_PPC64,
+    EM_X86_64, PF_X, PT_INTERP, PT_LOAD, PT_NOTE, R_AARCH64_RELATIVE, R_PPC64_RELATIVE,
+    R_X86_64_RELATIVE, SHF_ALLOC, SHF_EXECINSTR, SHT_NOTE, SHT_PROGBITS, SHT_REL, SHT_RELA,
+    STB_LOCAL, STT_FILE, STT_SECTION, VER_FLG_BASE,
+};
 use elf::endian::AnyEndian;
+use elf::file::Class;
+use elf::gnu_symver::SymbolVersionTable;
+use elf::hash::{GnuHashTable, SysVHashTable};
+use elf::note::{Note, NoteGnuBuildId};
+use elf::parse::ParseError;
+use elf::segment::ProgramHeader;
+use elf::string_table::StringTable;
+use elf::symbol::{Symbol, SymbolTable};
 use elf::ElfBytes;
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
 
-/// A struct to parse and manipulate 64-bit ELF files
-/// TODO: now the elf struct only support 64 bit elf file, need to support 32 bit elf file if necessary
+/// Errors returned while parsing or querying an ELF file
+#[derive(Debug)]
+pub enum ElfError {
+    /// The data does not start with a valid ELF identification
+    NotElf,
+    /// The data ends before a structure it references
+    Truncated,
+    /// No program header matches the description
+    MissingSegment(&'static str),
+    /// The named string table section does not exist
+    MissingStringTable(&'static str),
+    /// A symbol name offset points outside its string table
+    BadSymbolIndex(usize),
+    /// The named symbol does not exist
+    MissingSymbol(String),
+    /// The named section does not exist
+    MissingSection(String),
+    /// No PT_LOAD segment maps the virtual address
+    UnmappedAddress(u64),
+    /// The virtual address is mapped but not backed by file content, e.g., .bss
+    NotFileBacked(u64),
+    /// No PT_LOAD segment maps the file offset
+    UnmappedOffset(u64),
+    /// Any other structural error reported by the ELF parser
+    Malformed(ParseError),
+    /// The ld.so.cache file is not in a known format
+    BadLdCache(&'static str),
+    /// The ar archive or zip file is malformed
+    BadArchive(&'static str),
+    /// The archive has no member with the given name
+    MissingMember(String),
+    /// The file is valid but uses a layout that cannot be edited
+    Unsupported(&'static str),
+    /// Reading a file failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::Truncated => write!(f, "truncated ELF file"),
+            ElfError::MissingSegment(desc) => write!(f, "missing {} segment", desc),
+            ElfError::MissingStringTable(name) => write!(f, "missing string table {}", name),
+            ElfError::BadSymbolIndex(offset) => {
+                write!(f, "symbol name offset {:#x} out of string table", offset)
+            }
+            ElfError::MissingSymbol(name) => write!(f, "missing symbol {}", name),
+            ElfError::MissingSection(name) => write!(f, "missing section {}", name),
+            ElfError::UnmappedAddress(addr) => {
+                write!(f, "address {:#x} not mapped by any PT_LOAD segment", addr)
+            }
+            ElfError::NotFileBacked(addr) => {
+                write!(f, "address {:#x} not backed by file content", addr)
+            }
+            ElfError::UnmappedOffset(offset) => {
+                write!(
+                    f,
+                    "file offset {:#x} not mapped by any PT_LOAD segment",
+                    offset
+                )
+            }
+            ElfError::Malformed(e) => write!(f, "malformed ELF file: {}", e),
+            ElfError::BadLdCache(reason) => write!(f, "bad ld.so.cache: {}", reason),
+            ElfError::BadArchive(reason) => write!(f, "bad archive: {}", reason),
+            ElfError::MissingMember(path) => write!(f, "missing archive member {}", path),
+            ElfError::Unsupported(reason) => write!(f, "unsupported ELF file: {}", reason),
+            ElfError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+impl From<std::io::Error> for ElfError {
+    fn from(e: std::io::Error) -> Self {
+        ElfError::Io(e)
+    }
+}
+
+impl From<ParseError> for ElfError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::BadMagic(_)
+            | ParseError::UnsupportedElfClass(_)
+            | ParseError::UnsupportedElfEndianness(_) => ElfError::NotElf,
+            ParseError::SliceReadError(_) | ParseError::TryFromSliceError(_) => ElfError::Truncated,
+            e => ElfError::Malformed(e),
+        }
+    }
+}
+
+/// Magic of a fatbin region header
+pub const FATBIN_MAGIC: u32 = 0xba55ed50;
+/// Magic of the __fatbinwrap_* structs in .nvFatBinSegment
+pub const FATBIN_WRAPPER_MAGIC: u32 = 0x466243b1;
+const FATBIN_HEADER_SIZE: usize = 16;
+// fatbin regions are 8-byte aligned
+const FATBIN_ALIGN: u64 = 8;
+
+/// Where an embedded fatbin was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FatbinSource {
+    /// A device code section, .nv_fatbin or __nv_relfatbin, which may hold several regions
+    Section(&'static str),
+    /// The data pointer of a __fatbinwrap_* struct in .nvFatBinSegment
+    Wrapper,
+    /// A fatbin header found by scanning read-only data
+    MagicScan,
+}
+
+/// A fatbin embedded in an ELF file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FatbinBlob {
+    /// The file offset of the first region header
+    pub offset: u64,
+    pub size: u64,
+    pub source: FatbinSource,
+}
+
+impl FatbinBlob {
+    fn contains(&self, offset: u64) -> bool {
+        offset >= self.offset && offset - self.offset < self.size
+    }
+}
+
+/// A local symbol that refers to a fatbin region
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatbinSymbol {
+    /// The file offset of the region header
+    pub offset: u64,
+    pub name: String,
+    /// The name of the STT_FILE symbol it is scoped under, if any
+    pub file: Option<String>,
+}
+
+/// The instruction set of the host code, i.e., the platform a library is built for
+///
+/// Only the hosts CUDA supports are named, the device code is the same on all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostIsa {
+    X86_64,
+    /// 64-bit Arm, e.g., Grace Hopper
+    Aarch64,
+    /// 64-bit little endian POWER
+    Ppc64le,
+    /// Any other e_machine, or a named one in another ELF class or byte order
+    Other(u16),
+}
+
+impl fmt::Display for HostIsa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostIsa::X86_64 => write!(f, "x86_64"),
+            HostIsa::Aarch64 => write!(f, "aarch64"),
+            HostIsa::Ppc64le => write!(f, "ppc64le"),
+            HostIsa::Other(machine) => write!(f, "e_machine {}", machine),
+        }
+    }
+}
+
+/// A defined symbol with its version and attributes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    /// The symbol name, without version
+    pub name: String,
+    /// The version defined in .gnu.version_d, None for unversioned symbols
+    pub version: Option<String>,
+    /// Whether the version is hidden, i.e., only reachable as name@VERSION and not as the default name@@VERSION
+    pub hidden: bool,
+    /// The loaded memory address
+    pub addr: u64,
+    /// The file offset, None if the symbol is not backed by file content, e.g., in .bss
+    pub offset: Option<u64>,
+    pub size: u64,
+    /// The STB_* binding
+    pub binding: u8,
+    /// The STT_* type
+    pub symbol_type: u8,
+}
+
+// a defined symbol in the name index, with its .dynsym index if it comes from .dynsym
+type IndexedSymbol = (Symbol, Option<usize>);
+
+/// A struct to parse and manipulate ELF files
+/// Both ELFCLASS32 and ELFCLASS64 files are supported, in either byte order.
 ///
+/// * `data`: the raw file content
 /// * `parsed_elf`: the parsed elf file
-/// * `addr_offset_diff`: loaded memory addr - file offset
-pub struct ELF64<'data> {
+/// * `load_segments`: the PT_LOAD segments, used to translate between loaded memory addr and file offset
+/// * `symtab`, `dynsym`: the .symtab and .dynsym symbol tables, if any
+/// * `strtab`, `dynstr`: the .strtab and .dynstr string table data, if any
+/// * `gnu_hash`, `sysv_hash`: the .gnu.hash and .hash tables over .dynsym, if any
+/// * `symbol_versions`: the .gnu.version and .gnu.version_d/.gnu.version_r tables over .dynsym, if any
+/// * `symbol_index`: name -> symbols index over .symtab and .dynsym, built on first use
+/// * `relative_relocations`: address -> addend of the relative RELA relocations, built on first use
+pub struct Elf<'data> {
+    data: &'data [u8],
     parsed_elf: ElfBytes<'data, AnyEndian>, // the parsed elf file
-    addr_offset_diff: i64,                  // loaded memory addr - file offset
+    load_segments: Vec<ProgramHeader>,
+    symtab: Option<SymbolTable<'data, AnyEndian>>,
+    dynsym: Option<SymbolTable<'data, AnyEndian>>,
+    strtab: Option<&'data [u8]>,
+    dynstr: Option<&'data [u8]>,
+    gnu_hash: Option<GnuHashTable<'data, AnyEndian>>,
+    sysv_hash: Option<SysVHashTable<'data, AnyEndian>>,
+    symbol_versions: Option<SymbolVersionTable<'data, AnyEndian>>,
+    symbol_index: OnceCell<HashMap<&'data [u8], Vec<IndexedSymbol>>>,
+    relative_relocations: OnceCell<HashMap<u64, u64>>,
 }
 
-impl<'data> ELF64<'data> {
-    /// Create a new ELF64 struct from the given data
-    pub fn new(data: &'data [u8]) -> ELF64<'data> {
-        let parsed_elf = ElfBytes::<AnyEndian>::minimal_parse(data).unwrap();
-        let executable_phdr = parsed_elf
+/// The former name of `Elf`, from when only ELFCLASS64 files were supported
+#[deprecated(note = "use `Elf`, which also parses ELFCLASS32 files")]
+#[allow(dead_code)]
+pub type ELF64<'data> = Elf<'data>;
+
+impl<'data> Elf<'data> {
+    /// Create a new Elf struct from the given data
+    pub fn new(data: &'data [u8]) -> Result<Elf<'data>, ElfError> {
+        let parsed_elf = ElfBytes::<AnyEndian>::minimal_parse(data)?;
+        let load_segments = parsed_elf
             .segments()
-            .unwrap()
-            .iter()
-            .find(|p| p.p_type == PT_LOAD && p.p_flags == PF_R | PF_X)
-            .unwrap();
-        let addr_offset_diff = executable_phdr.p_vaddr as i64 - executable_phdr.p_offset as i64;
+            .map(|segments| segments.iter().filter(|p| p.p_type == PT_LOAD).collect())
+            .unwrap_or_default();
+
+        let common = parsed_elf.find_common_data()?;
+        let strtab = Self::find_section_data(&parsed_elf, ".strtab")?;
+        let dynstr = Self::find_section_data(&parsed_elf, ".dynstr")?;
+        let symbol_versions = parsed_elf.symbol_version_table()?;
 
-        ELF64 {
+        Ok(Elf {
+            data,
             parsed_elf,
-            addr_offset_diff,
+            load_segments,
+            symtab: common.symtab,
+            dynsym: common.dynsyms,
+            strtab,
+            dynstr,
+            gnu_hash: common.gnu_hash,
+            sysv_hash: common.sysv_hash,
+            symbol_versions,
+            symbol_index: OnceCell::new(),
+            relative_relocations: OnceCell::new(),
+        })
+    }
+
+    // get the data of the named section, if any
+    fn find_section_data(
+        parsed_elf: &ElfBytes<'data, AnyEndian>,
+        section_name: &str,
+    ) -> Result<Option<&'data [u8]>, ElfError> {
+        match parsed_elf.section_header_by_name(section_name)? {
+            Some(shdr) => Ok(Some(parsed_elf.section_data(&shdr)?.0)),
+            None => Ok(None),
         }
     }
 
-    // get the underlying str of the symbol string from the .dynsym section
-    fn get_dyn_symbol_bytes(&self, offset: usize) -> &'data [u8] {
-        let _dyn_str_section_header = self
-            .parsed_elf
-            .section_header_by_name(".dynstr")
-            .unwrap()
-            .unwrap();
-        let dyn_str = self
-            .parsed_elf
-            .section_data(&_dyn_str_section_header)
-            .unwrap()
-            .0;
-        let mut end = offset;
-        while dyn_str[end] != 0 {
-            end += 1;
+    /// Check if the ELF file is ELFCLASS64
+    pub fn is_64bit(&self) -> bool {
+        self.parsed_elf.ehdr.class == Class::ELF64
+    }
+
+    /// Check if the ELF file is big-endian
+    pub fn is_big_endian(&self) -> bool {
+        self.parsed_elf.ehdr.endianness == AnyEndian::Big
+    }
+
+    /// Get the e_machine field of the ELF header, e.g., EM_X86_64
+    pub fn machine(&self) -> u16 {
+        self.parsed_elf.ehdr.e_machine
+    }
+
+    /// Get the e_flags field of the ELF header, e.g., the target architecture of a cubin
+    pub fn flags(&self) -> u32 {
+        self.parsed_elf.ehdr.e_flags
+    }
+
+    /// Get the ABI version of the ELF identification, e.g., how the e_flags of a cubin are encoded
+    pub fn abi_version(&self) -> u8 {
+        self.parsed_elf.ehdr.abiversion
+    }
+
+    /// Get the instruction set of the host code
+    pub fn host_isa(&self) -> HostIsa {
+        if !self.is_64bit() || self.is_big_endian() {
+            return HostIsa::Other(self.machine());
+        }
+        match self.machine() {
+            EM_X86_64 => HostIsa::X86_64,
+            EM_AARCH64 => HostIsa::Aarch64,
+            EM_PPC64 => HostIsa::Ppc64le,
+            machine => HostIsa::Other(machine),
         }
-        &dyn_str[offset..end]
+    }
+
+    /// Translate a loaded memory address to its file offset, using the PT_LOAD segment containing it
+    pub fn addr_to_offset(&self, addr: u64) -> Result<u64, ElfError> {
+        if self.load_segments.is_empty() {
+            return Err(ElfError::MissingSegment("PT_LOAD"));
+        }
+        let segment = self
+            .load_segments
+            .iter()
+            .find(|p| addr >= p.p_vaddr && addr - p.p_vaddr < p.p_memsz)
+            .ok_or(ElfError::UnmappedAddress(addr))?;
+        let segment_offset = addr - segment.p_vaddr;
+        if segment_offset >= segment.p_filesz {
+            return Err(ElfError::NotFileBacked(addr));
+        }
+        Ok(segment.p_offset + segment_offset)
+    }
+
+    /// Translate a file offset to its loaded memory address, using the PT_LOAD segment containing it
+    pub fn offset_to_addr(&self, offset: u64) -> Result<u64, ElfError> {
+        if self.load_segments.is_empty() {
+            return Err(ElfError::MissingSegment("PT_LOAD"));
+        }
+        let segment = self
+            .load_segments
+            .iter()
+            .find(|p| offset >= p.p_offset && offset - p.p_offset < p.p_filesz)
+            .ok_or(ElfError::UnmappedOffset(offset))?;
+        Ok(segment.p_vaddr + (offset - segment.p_offset))
+    }
+
+    // get the NUL-terminated string at the given offset of a string table section
+    fn get_str_bytes(
+        str_data: Option<&'data [u8]>,
+        section_name: &'static str,
+        offset: usize,
+    ) -> Result<&'data [u8], ElfError> {
+        let str_data = str_data.ok_or(ElfError::MissingStringTable(section_name))?;
+        let tail = str_data
+            .get(offset..)
+            .ok_or(ElfError::BadSymbolIndex(offset))?;
+        let len = tail
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(ElfError::Truncated)?;
+        Ok(&tail[..len])
+    }
+
+    // get the underlying str of the symbol string from the .dynsym section
+    fn get_dyn_symbol_bytes(&self, offset: usize) -> Result<&'data [u8], ElfError> {
+        Self::get_str_bytes(self.dynstr, ".dynstr", offset)
     }
 
     // get the underlying str of the symbol string from the .symtab section
-    fn get_debug_symbol_bytes(&self, offset: usize) -> &'data [u8] {
-        let sym_str_section_header = self
-            .parsed_elf
-            .section_header_by_name(".strtab")
-            .unwrap()
-            .unwrap();
-        let sym_str = self
-            .parsed_elf
-            .section_data(&sym_str_section_header)
-            .unwrap()
-            .0;
-        let mut end = offset;
-        while sym_str[end] != 0 {
-            end += 1;
+    fn get_debug_symbol_bytes(&self, offset: usize) -> Result<&'data [u8], ElfError> {
+        Self::get_str_bytes(self.strtab, ".strtab", offset)
+    }
+
+    // get the string table of .dynsym for hash table lookups, None if there is nothing to look up
+    fn hash_string_table(&self) -> Option<StringTable<'data>> {
+        self.dynsym.as_ref().and(self.dynstr).map(StringTable::new)
+    }
+
+    // look up an exported symbol through .gnu.hash or .hash, if the file has one
+    // `dynstr` is the table of `hash_string_table`, resolved once by the caller
+    fn find_hashed_symbol(
+        &self,
+        dynstr: Option<&StringTable<'data>>,
+        symbol_bytes: &[u8],
+    ) -> Result<Option<IndexedSymbol>, ElfError> {
+        let (Some(dynsym), Some(dynstr)) = (&self.dynsym, dynstr) else {
+            return Ok(None);
+        };
+        let found = if let Some(gnu_hash) = &self.gnu_hash {
+            gnu_hash.find(symbol_bytes, dynsym, dynstr)?
+        } else if let Some(sysv_hash) = &self.sysv_hash {
+            sysv_hash.find(symbol_bytes, dynsym, dynstr)?
+        } else {
+            None
+        };
+        Ok(found
+            .filter(|(_, s)| !s.is_undefined())
+            .map(|(index, s)| (s, Some(index))))
+    }
+
+    // get the name -> symbols index over .symtab and .dynsym, building it on first use
+    // defined symbols are kept in table order, .symtab before .dynsym
+    fn symbol_index(&self) -> Result<&HashMap<&'data [u8], Vec<IndexedSymbol>>, ElfError> {
+        if let Some(index) = self.symbol_index.get() {
+            return Ok(index);
+        }
+
+        let mut index: HashMap<&[u8], Vec<IndexedSymbol>> = HashMap::new();
+        if let Some(symtab) = &self.symtab {
+            for s in symtab.iter() {
+                if s.st_name == 0 || s.is_undefined() {
+                    continue;
+                }
+                let name = self.get_debug_symbol_bytes(s.st_name as usize)?;
+                index.entry(name).or_default().push((s, None));
+            }
         }
-        &sym_str[offset..end]
+        if let Some(dynsym) = &self.dynsym {
+            for (i, s) in dynsym.iter().enumerate() {
+                if s.st_name == 0 || s.is_undefined() {
+                    continue;
+                }
+                let name = self.get_dyn_symbol_bytes(s.st_name as usize)?;
+                index.entry(name).or_default().push((s, Some(i)));
+            }
+        }
+        Ok(self.symbol_index.get_or_init(|| index))
+    }
+
+    // get the version of the .dynsym symbol at the given index, and whether the version is hidden
+    // unversioned symbols and those of the base version (the soname) have no version
+    fn get_dyn_symbol_version(&self, index: usize) -> Result<(Option<String>, bool), ElfError> {
+        let Some(symbol_versions) = &self.symbol_versions else {
+            return Ok((None, false));
+        };
+        let Some(definition) = symbol_versions.get_definition(index)? else {
+            return Ok((None, false));
+        };
+        if definition.flags & VER_FLG_BASE != 0 {
+            return Ok((None, definition.hidden));
+        }
+        let mut names = definition.names;
+        let version = names.next().transpose()?.map(String::from);
+        Ok((version, definition.hidden))
+    }
+
+    // get the version of an indexed symbol, .symtab symbols have none
+    fn get_indexed_symbol_version(
+        &self,
+        symbol: &IndexedSymbol,
+    ) -> Result<(Option<String>, bool), ElfError> {
+        match symbol.1 {
+            Some(index) => self.get_dyn_symbol_version(index),
+            None => Ok((None, false)),
+        }
+    }
+
+    // find the defined symbol with the given name, which may carry a version as name@VERSION or name@@VERSION
+    // an unversioned name prefers the default version, and falls back to a hidden one if there is no other
+    fn find_symbol(&self, symbol_bytes: &[u8]) -> Result<Option<IndexedSymbol>, ElfError> {
+        let dynstr = self.hash_string_table();
+        if let Some(symbol) = self.find_exported_symbol(dynstr.as_ref(), symbol_bytes)? {
+            return Ok(Some(symbol));
+        }
+        self.find_indexed_symbol(self.symbol_index()?, symbol_bytes)
+    }
+
+    // find an unversioned name through the hash table, if it resolves to a symbol that is not hidden
+    fn find_exported_symbol(
+        &self,
+        dynstr: Option<&StringTable<'data>>,
+        symbol_bytes: &[u8],
+    ) -> Result<Option<IndexedSymbol>, ElfError> {
+        if split_version(symbol_bytes).1.is_some() {
+            return Ok(None);
+        }
+        match self.find_hashed_symbol(dynstr, symbol_bytes)? {
+            Some(symbol) if !self.get_indexed_symbol_version(&symbol)?.1 => Ok(Some(symbol)),
+            _ => Ok(None),
+        }
+    }
+
+    // find a possibly versioned name in the name index, the fallback of `find_symbol`
+    fn find_indexed_symbol(
+        &self,
+        index: &HashMap<&'data [u8], Vec<IndexedSymbol>>,
+        symbol_bytes: &[u8],
+    ) -> Result<Option<IndexedSymbol>, ElfError> {
+        let (name, version) = split_version(symbol_bytes);
+        let Some(candidates) = index.get(name) else {
+            return Ok(None);
+        };
+        for candidate in candidates {
+            let (candidate_version, hidden) = self.get_indexed_symbol_version(candidate)?;
+            let matched = match version {
+                None => !hidden,
+                Some((version, default_only)) => {
+                    candidate_version.as_deref().map(str::as_bytes) == Some(version)
+                        && !(default_only && hidden)
+                }
+            };
+            if matched {
+                return Ok(Some(candidate.clone()));
+            }
+        }
+        if version.is_none() {
+            return Ok(candidates.first().cloned());
+        }
+        Ok(None)
+    }
+
+    // describe an indexed symbol
+    fn symbol_info(&self, name: &[u8], symbol: &IndexedSymbol) -> Result<SymbolInfo, ElfError> {
+        let (version, hidden) = self.get_indexed_symbol_version(symbol)?;
+        let s = &symbol.0;
+        Ok(SymbolInfo {
+            name: String::from_utf8_lossy(name).into_owned(),
+            version,
+            hidden,
+            addr: s.st_value,
+            offset: self.addr_to_offset(s.st_value).ok(),
+            size: s.st_size,
+            binding: s.st_bind(),
+            symbol_type: s.st_symtype(),
+        })
     }
 
     /// Get the file offset of the given symbol
-    pub fn get_symbol_offset(&self, symbol_bytes: &[u8]) -> Option<u64> {
-        match self.parsed_elf.symbol_table() {
-            Ok(Some((symtab, _))) => {
-                for (_, s) in symtab.iter().enumerate() {
-                    let found_sym_bytes = self.get_debug_symbol_bytes(s.st_name as usize);
-                    if symbol_bytes == found_sym_bytes {
-                        return Some(s.st_value - self.addr_offset_diff as u64);
+    ///
+    /// Exported symbols are resolved through .gnu.hash or .hash when present,
+    /// everything else through a name index over .symtab and .dynsym that is built once.
+    /// The name may select a version as name@VERSION, or name@@VERSION for the default version only.
+    pub fn get_symbol_offset(&self, symbol_bytes: &[u8]) -> Result<Option<u64>, ElfError> {
+        match self.find_symbol(symbol_bytes)? {
+            Some((s, _)) => Ok(Some(self.addr_to_offset(s.st_value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the loaded memory address of the given symbol, optionally versioned as in `get_symbol_offset`
+    pub fn get_symbol_addr(&self, symbol_bytes: &[u8]) -> Result<Option<u64>, ElfError> {
+        let symbol = self.find_symbol(symbol_bytes)?;
+        Ok(symbol.map(|(s, _)| s.st_value))
+    }
+
+    /// Get the address, offset, size, binding, type, and version of the given symbol,
+    /// optionally versioned as in `get_symbol_offset`
+    pub fn get_symbol_info(&self, symbol_bytes: &[u8]) -> Result<Option<SymbolInfo>, ElfError> {
+        let (name, _) = split_version(symbol_bytes);
+        match self.find_symbol(symbol_bytes)? {
+            Some(symbol) => Ok(Some(self.symbol_info(name, &symbol)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get every defined version of the given symbol name in .dynsym order,
+    /// or its .symtab definitions if it is not in .dynsym
+    pub fn get_symbol_versions(&self, name: &[u8]) -> Result<Vec<SymbolInfo>, ElfError> {
+        let Some(candidates) = self.symbol_index()?.get(name) else {
+            return Ok(vec![]);
+        };
+        let has_dynamic = candidates.iter().any(|(_, index)| index.is_some());
+        candidates
+            .iter()
+            .filter(|(_, index)| index.is_some() == has_dynamic)
+            .map(|symbol| self.symbol_info(name, symbol))
+            .collect()
+    }
+
+    /// Get the loaded memory addresses of many symbols at once, in the order of `symbol_names`
+    ///
+    /// The hash table is resolved once for the batch, and the name index is only built, once, if some name
+    /// is not found through it.
+    pub fn get_symbol_addrs<'a, I>(&self, symbol_names: I) -> Result<Vec<Option<u64>>, ElfError>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let dynstr = self.hash_string_table();
+        let mut addrs = vec![];
+        let mut missed = vec![];
+        for name in symbol_names {
+            let symbol = self.find_exported_symbol(dynstr.as_ref(), name)?;
+            if symbol.is_none() {
+                missed.push((addrs.len(), name));
+            }
+            addrs.push(symbol.map(|(s, _)| s.st_value));
+        }
+        if missed.is_empty() {
+            return Ok(addrs);
+        }
+        let index = self.symbol_index()?;
+        for (i, name) in missed {
+            addrs[i] = self
+                .find_indexed_symbol(index, name)?
+                .map(|(s, _)| s.st_value);
+        }
+        Ok(addrs)
+    }
+
+    // get the string table referenced by the dynamic section
+    // .dynstr is preferred, DT_STRTAB/DT_STRSZ are used when section headers are stripped
+    fn dynamic_str_data(&self) -> Result<Option<&'data [u8]>, ElfError> {
+        if self.dynstr.is_some() {
+            return Ok(self.dynstr);
+        }
+        let Some(dynamic) = self.parsed_elf.dynamic()? else {
+            return Ok(None);
+        };
+        let strtab = dynamic.iter().find(|d| d.d_tag == DT_STRTAB);
+        let strsz = dynamic.iter().find(|d| d.d_tag == DT_STRSZ);
+        let (Some(strtab), Some(strsz)) = (strtab, strsz) else {
+            return Ok(None);
+        };
+        let start = self.addr_to_offset(strtab.d_ptr())? as usize;
+        let end = start
+            .checked_add(strsz.d_val() as usize)
+            .ok_or(ElfError::Truncated)?;
+        self.data
+            .get(start..end)
+            .map(Some)
+            .ok_or(ElfError::Truncated)
+    }
+
+    // get the strings of all dynamic entries with the given tag, in order
+    fn get_dynamic_strings(&self, tag: i64) -> Result<Vec<String>, ElfError> {
+        let Some(dynamic) = self.parsed_elf.dynamic()? else {
+            return Ok(vec![]);
+        };
+        let str_data = self.dynamic_str_data()?;
+        dynamic
+            .iter()
+            .filter(|d| d.d_tag == tag)
+            .map(|d| {
+                let bytes = Self::get_str_bytes(str_data, ".dynstr", d.d_val() as usize)?;
+                Ok(String::from_utf8_lossy(bytes).into_owned())
+            })
+            .collect()
+    }
+
+    /// Get the DT_NEEDED entries, in the order the dynamic linker loads them
+    pub fn get_needed(&self) -> Result<Vec<String>, ElfError> {
+        self.get_dynamic_strings(DT_NEEDED)
+    }
+
+    /// Get the DT_SONAME entry, if any
+    pub fn get_soname(&self) -> Result<Option<String>, ElfError> {
+        Ok(self.get_dynamic_strings(DT_SONAME)?.into_iter().next())
+    }
+
+    /// Get the colon-separated DT_RPATH search directories, unexpanded
+    pub fn get_rpath(&self) -> Result<Vec<String>, ElfError> {
+        Ok(split_search_path(&self.get_dynamic_strings(DT_RPATH)?))
+    }
+
+    /// Get the colon-separated DT_RUNPATH search directories, unexpanded
+    pub fn get_runpath(&self) -> Result<Vec<String>, ElfError> {
+        Ok(split_search_path(&self.get_dynamic_strings(DT_RUNPATH)?))
+    }
+
+    /// Get the program interpreter requested by PT_INTERP, if any
+    pub fn get_interpreter(&self) -> Result<Option<String>, ElfError> {
+        let Some(segments) = self.parsed_elf.segments() else {
+            return Ok(None);
+        };
+        let Some(interp) = segments.iter().find(|p| p.p_type == PT_INTERP) else {
+            return Ok(None);
+        };
+        let data = self.parsed_elf.segment_data(&interp)?;
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        Ok(Some(String::from_utf8_lossy(&data[..len]).into_owned()))
+    }
+
+    /// Get the NT_GNU_BUILD_ID note, if any
+    ///
+    /// PT_NOTE segments are searched first, then SHT_NOTE sections for files without program headers.
+    pub fn get_build_id(&self) -> Result<Option<&'data [u8]>, ElfError> {
+        if let Some(segments) = self.parsed_elf.segments() {
+            for phdr in segments.iter().filter(|p| p.p_type == PT_NOTE) {
+                for note in self.parsed_elf.segment_data_as_notes(&phdr)? {
+                    if let Note::GnuBuildId(NoteGnuBuildId(build_id)) = note {
+                        return Ok(Some(build_id));
                     }
                 }
             }
-            _ => {}
         }
-
-        match self.parsed_elf.dynamic_symbol_table() {
-            Ok(Some((dynsym, _))) => {
-                for (_, s) in dynsym.iter().enumerate() {
-                    let found_sym_bytes = self.get_dyn_symbol_bytes(s.st_name as usize);
-                    if symbol_bytes == found_sym_bytes {
-                        return Some(s.st_value - self.addr_offset_diff as u64);
+        if let Some(shdrs) = self.parsed_elf.section_headers() {
+            for shdr in shdrs.iter().filter(|s| s.sh_type == SHT_NOTE) {
+                for note in self.parsed_elf.section_data_as_notes(&shdr)? {
+                    if let Note::GnuBuildId(NoteGnuBuildId(build_id)) = note {
+                        return Ok(Some(build_id));
                     }
                 }
             }
-            _ => {}
         }
+        Ok(None)
+    }
+
+    /// Get the file offset of the given section
+    pub fn get_section_offset(&self, section_name: &str) -> Result<Option<u64>, ElfError> {
+        let section_header = self.parsed_elf.section_header_by_name(section_name)?;
+        Ok(section_header.map(|shdr| shdr.sh_offset))
+    }
+
+    /// Get the size of the given section
+    pub fn get_section_size(&self, section_name: &str) -> Result<Option<u64>, ElfError> {
+        let section_header = self.parsed_elf.section_header_by_name(section_name)?;
+        Ok(section_header.map(|shdr| shdr.sh_size))
+    }
 
-        None
+    /// Get the names of all sections, in section header order
+    pub fn get_section_names(&self) -> Result<Vec<&'data [u8]>, ElfError> {
+        let (Some(shdrs), Some(strtab)) = self.parsed_elf.section_headers_with_strtab()? else {
+            return Ok(vec![]);
+        };
+        shdrs
+            .iter()
+            .map(|shdr| Ok(strtab.get_raw(shdr.sh_name as usize)?))
+            .collect()
+    }
+
+    /// Get the data of the given section
+    pub fn get_section_data(&self, section_name: &str) -> Result<Option<&'data [u8]>, ElfError> {
+        Self::find_section_data(&self.parsed_elf, section_name)
+    }
+
+    /// Get the names of the .symtab symbols, by symbol index
+    ///
+    /// Section symbols and unnamed ones are listed with an empty name.
+    pub fn get_debug_symbol_names(&self) -> Result<Vec<&'data [u8]>, ElfError> {
+        let Some(symtab) = &self.symtab else {
+            return Ok(vec![]);
+        };
+        symtab
+            .iter()
+            .map(|s| {
+                if s.st_name == 0 || s.st_symtype() == STT_SECTION {
+                    Ok(&[][..])
+                } else {
+                    self.get_debug_symbol_bytes(s.st_name as usize)
+                }
+            })
+            .collect()
     }
 
-    /// Get the loaded memory address of the given symbol
-    pub fn get_symbol_addr(&self, symbol_bytes: &[u8]) -> Option<u64> {
-        let offset = self.get_symbol_offset(symbol_bytes);
-        match offset {
-            Some(off) => Some((off as i64 + self.addr_offset_diff) as u64),
-            None => None,
+    /// Get the relocations against symbols of all REL and RELA sections, in section header order
+    ///
+    /// Each is reported as the name of the section it applies to, i.e., the one in sh_info, and the symbol index.
+    pub fn get_symbol_relocations(&self) -> Result<Vec<(&'data [u8], u32)>, ElfError> {
+        let (Some(shdrs), Some(strtab)) = self.parsed_elf.section_headers_with_strtab()? else {
+            return Ok(vec![]);
+        };
+        let mut relocations = vec![];
+        for shdr in shdrs.iter() {
+            let symbols: Vec<u32> = match shdr.sh_type {
+                SHT_REL => self
+                    .parsed_elf
+                    .section_data_as_rels(&shdr)?
+                    .map(|rel| rel.r_sym)
+                    .collect(),
+                SHT_RELA => self
+                    .parsed_elf
+                    .section_data_as_relas(&shdr)?
+                    .map(|rela| rela.r_sym)
+                    .collect(),
+                _ => continue,
+            };
+            let Ok(target) = shdrs.get(shdr.sh_info as usize) else {
+                continue;
+            };
+            let target_name = strtab.get_raw(target.sh_name as usize)?;
+            relocations.extend(
+                symbols
+                    .into_iter()
+                    .filter(|&sym| sym != 0)
+                    .map(|sym| (target_name, sym)),
+            );
         }
+        Ok(relocations)
     }
 
-    /// Get the file offset of the given section
-    pub fn get_section_offset(&self, section_name: &str) -> Option<u64> {
-        let section_header = self
-            .parsed_elf
-            .section_header_by_name(section_name)
-            .unwrap();
-        if section_header.is_none() {
-            return None;
+    /// Check cheaply if the ELF file embeds any fatbin, through its .nv_fatbin and __nv_relfatbin sections and
+    /// the __fatbinwrap_* structs in .nvFatBinSegment, e.g., for every library of a dependency graph
+    ///
+    /// Unlike `find_fatbins`, read-only data is not scanned for the fatbin magic and wrapper pointers are not resolved.
+    pub fn has_gpu_code(&self) -> Result<bool, ElfError> {
+        for name in [".nv_fatbin", "__nv_relfatbin"] {
+            if self.get_section_size(name)?.is_some_and(|size| size > 0) {
+                return Ok(true);
+            }
         }
-        Some(section_header.unwrap().sh_offset)
+        let Some(segment) = self.get_section_data(".nvFatBinSegment")? else {
+            return Ok(false);
+        };
+        let pointer_size = if self.is_64bit() { 8 } else { 4 };
+        Ok(segment
+            .chunks_exact(8 + 2 * pointer_size)
+            .any(|wrapper| self.read_u32(&wrapper[0..4]) == FATBIN_WRAPPER_MAGIC))
     }
 
-    /// Get the size of the given section
-    pub fn get_section_size(&self, section_name: &str) -> Option<u64> {
-        let section_header = self
-            .parsed_elf
-            .section_header_by_name(section_name)
-            .unwrap();
+    /// Find every fatbin embedded in the ELF file, sorted by file offset
+    ///
+    /// Fatbins are found through the .nv_fatbin and __nv_relfatbin sections, the __fatbinwrap_* structs
+    /// in .nvFatBinSegment, and by scanning read-only data for the fatbin magic, in that order.
+    /// A fatbin inside one found earlier is not reported again.
+    pub fn find_fatbins(&self) -> Result<Vec<FatbinBlob>, ElfError> {
+        let mut blobs: Vec<FatbinBlob> = vec![];
+        let sections = [
+            (
+                ".nv_fatbin",
+                self.get_gpu_code_offset()?,
+                self.get_gpu_code_size()?,
+            ),
+            (
+                "__nv_relfatbin",
+                self.get_section_offset("__nv_relfatbin")?,
+                self.get_section_size("__nv_relfatbin")?,
+            ),
+        ];
+        for (name, offset, size) in sections {
+            if let (Some(offset), Some(size)) = (offset, size) {
+                if size > 0 && self.data.len() as u64 >= offset.saturating_add(size) {
+                    blobs.push(FatbinBlob {
+                        offset,
+                        size,
+                        source: FatbinSource::Section(name),
+                    });
+                }
+            }
+        }
 
-        if section_header.is_none() {
+        for offset in self.find_wrapped_fatbins()? {
+            if blobs.iter().any(|b| b.contains(offset)) {
+                continue;
+            }
+            if let Some(size) = self.fatbin_size_at(offset) {
+                blobs.push(FatbinBlob {
+                    offset,
+                    size,
+                    source: FatbinSource::Wrapper,
+                });
+            }
+        }
+
+        for (start, end) in self.read_only_data_ranges()? {
+            let mut offset = start.next_multiple_of(FATBIN_ALIGN);
+            while offset < end {
+                if let Some(blob) = blobs.iter().find(|b| b.contains(offset)) {
+                    offset = (blob.offset + blob.size).next_multiple_of(FATBIN_ALIGN);
+                    continue;
+                }
+                match self.fatbin_size_at(offset) {
+                    Some(size) => {
+                        blobs.push(FatbinBlob {
+                            offset,
+                            size,
+                            source: FatbinSource::MagicScan,
+                        });
+                        offset = (offset + size).next_multiple_of(FATBIN_ALIGN);
+                    }
+                    None => offset += FATBIN_ALIGN,
+                }
+            }
+        }
+
+        blobs.sort_by_key(|b| b.offset);
+        Ok(blobs)
+    }
+
+    // get the size of the fatbin region starting at the given file offset, if there is a valid one
+    // fatbin headers are little-endian regardless of the host byte order
+    fn fatbin_size_at(&self, offset: u64) -> Option<u64> {
+        let start = usize::try_from(offset).ok()?;
+        let header = self
+            .data
+            .get(start..start.checked_add(FATBIN_HEADER_SIZE)?)?;
+        let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let version = u16::from_le_bytes(header[4..6].try_into().unwrap());
+        let header_size = u16::from_le_bytes(header[6..8].try_into().unwrap());
+        let fat_size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        if magic != FATBIN_MAGIC || version != 1 || header_size as usize != FATBIN_HEADER_SIZE {
             return None;
         }
-        Some(section_header.unwrap().sh_size)
+        let size = fat_size.checked_add(header_size as u64)?;
+        (offset.checked_add(size)? <= self.data.len() as u64).then_some(size)
     }
 
-    /// Check if the ELF file has GPU code section (.nv_fatbin)
-    pub fn has_gpu_code(&self) -> bool {
-        let section_header = self
-            .parsed_elf
-            .section_header_by_name(".nv_fatbin")
-            .unwrap();
-        section_header.is_some()
+    // get the file offsets of the fatbins referenced by the __fatbinwrap_* structs in .nvFatBinSegment
+    // a wrapper is { u32 magic, u32 version, data pointer, filename or prelinked fatbins pointer }
+    fn find_wrapped_fatbins(&self) -> Result<Vec<u64>, ElfError> {
+        let Some(shdr) = self.parsed_elf.section_header_by_name(".nvFatBinSegment")? else {
+            return Ok(vec![]);
+        };
+        let (segment, _) = self.parsed_elf.section_data(&shdr)?;
+        let pointer_size = if self.is_64bit() { 8 } else { 4 };
+        let wrapper_size = 8 + 2 * pointer_size;
+
+        let mut offsets = vec![];
+        for (i, wrapper) in segment.chunks_exact(wrapper_size).enumerate() {
+            let wrapper_addr = shdr.sh_addr + (i * wrapper_size) as u64;
+            if let Some(offset) = self.wrapped_fatbin_offset(wrapper, wrapper_addr)? {
+                offsets.push(offset);
+            }
+        }
+        Ok(offsets)
+    }
+
+    // get the file offset of the fatbin referenced by the __fatbinwrap_* struct at the given address, if any
+    fn wrapped_fatbin_offset(
+        &self,
+        wrapper: &[u8],
+        wrapper_addr: u64,
+    ) -> Result<Option<u64>, ElfError> {
+        let pointer_size = if self.is_64bit() { 8 } else { 4 };
+        if wrapper.len() < 8 + pointer_size || self.read_u32(&wrapper[0..4]) != FATBIN_WRAPPER_MAGIC
+        {
+            return Ok(None);
+        }
+        let mut data_addr = self.read_pointer(&wrapper[8..8 + pointer_size]);
+        if data_addr == 0 {
+            // left for the dynamic linker to fill, e.g., without --apply-dynamic-relocs
+            match self.get_relative_relocation(wrapper_addr + 8)? {
+                Some(addend) => data_addr = addend,
+                None => return Ok(None),
+            }
+        }
+        Ok(self.addr_to_offset(data_addr).ok())
+    }
+
+    /// Find the local symbols that refer to a fatbin region, in .symtab order
+    ///
+    /// These are the fatbinData labels at the start of a region and the __fatbinwrap_* structs pointing to one,
+    /// both emitted by nvcc once per compilation unit. Each is reported with the STT_FILE symbol it is
+    /// scoped under, which names the compilation unit, e.g., tmpxft_000040a0_00000000-6_demo.cudafe1.cpp.
+    /// Stripped files have no .symtab and no such symbols.
+    pub fn find_fatbin_symbols(&self) -> Result<Vec<FatbinSymbol>, ElfError> {
+        let Some(symtab) = &self.symtab else {
+            return Ok(vec![]);
+        };
+        let pointer_size = if self.is_64bit() { 8 } else { 4 };
+        let wrapper_size = 8 + 2 * pointer_size;
+
+        let mut symbols = vec![];
+        let mut file = None;
+        for s in symtab.iter() {
+            if s.st_bind() != STB_LOCAL {
+                // global symbols follow all local ones and belong to no file
+                file = None;
+                continue;
+            }
+            if s.st_symtype() == STT_FILE {
+                let name = self.get_debug_symbol_bytes(s.st_name as usize)?;
+                file = (!name.is_empty()).then(|| String::from_utf8_lossy(name).into_owned());
+                continue;
+            }
+            if s.st_name == 0 || s.is_undefined() || s.st_symtype() == STT_SECTION {
+                continue;
+            }
+            let Ok(symbol_offset) = self.addr_to_offset(s.st_value) else {
+                continue;
+            };
+            let name = self.get_debug_symbol_bytes(s.st_name as usize)?;
+            let offset = if name.starts_with(b"__fatbinwrap_") {
+                let start = symbol_offset as usize;
+                let Some(wrapper) = self.data.get(start..start.saturating_add(wrapper_size)) else {
+                    continue;
+                };
+                self.wrapped_fatbin_offset(wrapper, s.st_value)?
+            } else {
+                self.fatbin_size_at(symbol_offset).map(|_| symbol_offset)
+            };
+            if let Some(offset) = offset {
+                symbols.push(FatbinSymbol {
+                    offset,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    file: file.clone(),
+                });
+            }
+        }
+        Ok(symbols)
+    }
+
+    // get the addend of the symbol-less RELA relocation applied at the given address, if any
+    fn get_relative_relocation(&self, addr: u64) -> Result<Option<u64>, ElfError> {
+        Ok(self.relative_relocations()?.get(&addr).copied())
+    }
+
+    // get the address -> addend index of the symbol-less RELA relocations, building it on first use
+    // on known machines only the relative relocation type counts, e.g., not R_X86_64_IRELATIVE
+    // the first relocation of an address wins, in section header order
+    fn relative_relocations(&self) -> Result<&HashMap<u64, u64>, ElfError> {
+        if let Some(relocations) = self.relative_relocations.get() {
+            return Ok(relocations);
+        }
+
+        let mut relocations = HashMap::new();
+        let relative_type = match self.machine() {
+            EM_X86_64 => Some(R_X86_64_RELATIVE),
+            EM_AARCH64 => Some(R_AARCH64_RELATIVE),
+            EM_PPC64 => Some(R_PPC64_RELATIVE),
+            _ => None,
+        };
+        if let Some(shdrs) = self.parsed_elf.section_headers() {
+            for shdr in shdrs.iter().filter(|s| s.sh_type == SHT_RELA) {
+                for rela in self.parsed_elf.section_data_as_relas(&shdr)? {
+                    if rela.r_sym == 0 && relative_type.is_none_or(|t| rela.r_type == t) {
+                        relocations
+                            .entry(rela.r_offset)
+                            .or_insert(rela.r_addend as u64);
+                    }
+                }
+            }
+        }
+        Ok(self.relative_relocations.get_or_init(|| relocations))
+    }
+
+    // get the file ranges of allocated, non-executable data, where fatbins may be embedded
+    // sections are used when present, non-executable PT_LOAD segments otherwise
+    fn read_only_data_ranges(&self) -> Result<Vec<(u64, u64)>, ElfError> {
+        let file_size = self.data.len() as u64;
+        let ranges: Vec<(u64, u64)> = match self.parsed_elf.section_headers() {
+            Some(shdrs) => shdrs
+                .iter()
+                .filter(|s| {
+                    s.sh_type == SHT_PROGBITS
+                        && s.sh_flags & SHF_ALLOC as u64 != 0
+                        && s.sh_flags & SHF_EXECINSTR as u64 == 0
+                })
+                .map(|s| (s.sh_offset, s.sh_offset.saturating_add(s.sh_size)))
+                .collect(),
+            None => self
+                .load_segments
+                .iter()
+                .filter(|p| p.p_flags & PF_X == 0)
+                .map(|p| (p.p_offset, p.p_offset.saturating_add(p.p_filesz)))
+                .collect(),
+        };
+        Ok(ranges
+            .into_iter()
+            .map(|(start, end)| (start.min(file_size), end.min(file_size)))
+            .collect())
+    }
+
+    // read a u32 in the byte order of the file
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        if self.is_big_endian() {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    // read a pointer in the class and byte order of the file
+    fn read_pointer(&self, bytes: &[u8]) -> u64 {
+        if !self.is_64bit() {
+            return self.read_u32(bytes) as u64;
+        }
+        let bytes = bytes.try_into().unwrap();
+        if self.is_big_endian() {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        }
     }
 
     /// Get the file offset and size of the GPU code section (.nv_fatbin)
-    pub fn get_gpu_code_offset(&self) -> Option<u64> {
-        return self.get_section_offset(".nv_fatbin");
+    pub fn get_gpu_code_offset(&self) -> Result<Option<u64>, ElfError> {
+        self.get_section_offset(".nv_fatbin")
     }
 
     /// Get the size of the GPU code section (.nv_fatbin)
-    pub fn get_gpu_code_size(&self) -> Option<u64> {
-        return self.get_section_size(".nv_fatbin");
+    pub fn get_gpu_code_size(&self) -> Result<Option<u64>, ElfError> {
+        self.get_section_size(".nv_fatbin")
+    }
+}
+
+// split name@VERSION or name@@VERSION into the name and the version, if any
+// the flag tells whether only the default version (@@) may match
+fn split_version(symbol_bytes: &[u8]) -> (&[u8], Option<(&[u8], bool)>) {
+    let Some(at) = symbol_bytes.iter().position(|&b| b == b'@') else {
+        return (symbol_bytes, None);
+    };
+    let name = &symbol_bytes[..at];
+    match symbol_bytes[at + 1..].strip_prefix(b"@") {
+        Some(version) => (name, Some((version, true))),
+        None => (name, Some((&symbol_bytes[at + 1..], false))),
     }
 }
 
+// split DT_RPATH/DT_RUNPATH values into their directories, dropping empty entries
+fn split_search_path(values: &[String]) -> Vec<String> {
+    values
+        .iter()
+        .flat_map(|v| v.split(':'))
+        .filter(|dir| !dir.is_empty())
+        .map(String::from)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use elf::abi::{PF_R, PF_W, PF_X, STB_GLOBAL, STT_FUNC, STT_OBJECT};
     use std::path::PathBuf;
 
     fn fixture(name: &str) -> PathBuf {
@@ -167,9 +1115,9 @@ mod tests {
     fn test_get_symbol_offset() {
         let so_path = fixture("libdemo.so");
         let data = std::fs::read(so_path.clone()).unwrap();
-        let elf64 = ELF64::new(&data);
+        let elf64 = Elf::new(&data).unwrap();
         let symbol = b"_Z6matMulPiS_S_iii";
-        let offset = elf64.get_symbol_offset(symbol).unwrap();
+        let offset = elf64.get_symbol_offset(symbol).unwrap().unwrap();
         assert_eq!(offset, 0xabed);
     }
 
@@ -177,9 +1125,9 @@ mod tests {
     fn test_get_symbol_addr() {
         let so_path = fixture("libdemo.so");
         let data = std::fs::read(so_path.clone()).unwrap();
-        let elf64 = ELF64::new(&data);
+        let elf64 = Elf::new(&data).unwrap();
         let symbol = b"_Z6matMulPiS_S_iii";
-        let addr = elf64.get_symbol_addr(symbol).unwrap();
+        let addr = elf64.get_symbol_addr(symbol).unwrap().unwrap();
         assert_eq!(addr, 0xabed);
     }
 
@@ -187,10 +1135,10 @@ mod tests {
     fn test_get_section_offset_size() {
         let so_path = fixture("libdemo.so");
         let data = std::fs::read(so_path.clone()).unwrap();
-        let elf64 = ELF64::new(&data);
+        let elf64 = Elf::new(&data).unwrap();
 
-        let offset = elf64.get_section_offset(".text").unwrap();
-        let size = elf64.get_section_size(".text").unwrap();
+        let offset = elf64.get_section_offset(".text").unwrap().unwrap();
+        let size = elf64.get_section_size(".text").unwrap().unwrap();
 
         assert_eq!(offset, 0xa9f0);
         assert_eq!(size, 0x7a132);
@@ -200,21 +1148,454 @@ mod tests {
     fn test_has_gpu_code() {
         let so_path = fixture("libdemo.so");
         let data = std::fs::read(so_path.clone()).unwrap();
-        let elf64 = ELF64::new(&data);
+        let elf64 = Elf::new(&data).unwrap();
+
+        assert!(elf64.has_gpu_code().unwrap());
 
-        assert!(elf64.has_gpu_code());
+        // a cubin embeds no fatbin
+        let data = std::fs::read(fixture("libdemo.3.sm_70.cubin")).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        assert!(!elf.has_gpu_code().unwrap());
     }
 
     #[test]
     fn test_get_gpu_code_offset_size() {
         let so_path = fixture("libdemo.so");
         let data = std::fs::read(so_path.clone()).unwrap();
-        let elf64 = ELF64::new(&data);
+        let elf64 = Elf::new(&data).unwrap();
 
-        let offset = elf64.get_gpu_code_offset().unwrap();
-        let size = elf64.get_gpu_code_size().unwrap();
+        let offset = elf64.get_gpu_code_offset().unwrap().unwrap();
+        let size = elf64.get_gpu_code_size().unwrap().unwrap();
 
         assert_eq!(offset, 0x948d0);
         assert_eq!(size, 0x63e0);
     }
+
+    #[test]
+    fn test_find_fatbins() {
+        // the relocated wrapper is found with the relative relocation type of each host
+        for name in [
+            "libfatbins.so",
+            "libfatbins.aarch64.so",
+            "libfatbins.ppc64le.so",
+        ] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+            assert!(elf.has_gpu_code().unwrap());
+            // the wrapper pointing into .nv_fatbin is not reported twice
+            let blobs = elf.find_fatbins().unwrap();
+            let expected = [
+                (0x440, FatbinSource::Section(".nv_fatbin")),
+                (0xbb0, FatbinSource::Section("__nv_relfatbin")),
+                (0x1330, FatbinSource::Wrapper),
+                (0x1aa8, FatbinSource::MagicScan),
+            ];
+            assert_eq!(blobs.len(), expected.len(), "{}", name);
+            for (blob, (offset, source)) in blobs.iter().zip(expected) {
+                assert_eq!(
+                    (blob.offset, blob.size, blob.source),
+                    (offset, 0x770, source)
+                );
+            }
+        }
+
+        // both wrappers of libdemo.so point into .nv_fatbin
+        let data = std::fs::read(fixture("libdemo.so")).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        assert_eq!(
+            elf.find_fatbins().unwrap(),
+            vec![FatbinBlob {
+                offset: 0x948d0,
+                size: 0x63e0,
+                source: FatbinSource::Section(".nv_fatbin"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_fatbin_symbols() {
+        // the wrapper is only relocated, the globals after the last file belong to none
+        let data = std::fs::read(fixture("libunits.so")).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        assert_eq!(
+            elf.find_fatbin_symbols().unwrap(),
+            vec![
+                FatbinSymbol {
+                    offset: 0x440,
+                    name: "fatbinData".to_string(),
+                    file: Some("link.stub".to_string()),
+                },
+                FatbinSymbol {
+                    offset: 0x1350,
+                    name: "__fatbinwrap_38_tmpxft_00001a2b_00000000_6_matmul_cpp1_ii_1f2e3d4c"
+                        .to_string(),
+                    file: Some("tmpxft_00001a2b_00000000-6_matmul.cudafe1.cpp".to_string()),
+                },
+            ]
+        );
+
+        let data = std::fs::read(fixture("libfatbins.so")).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        assert!(elf.find_fatbin_symbols().unwrap().is_empty());
+
+        let data = std::fs::read(fixture("libdemo.so")).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        let symbols: Vec<(u64, Option<String>)> = elf
+            .find_fatbin_symbols()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.offset, s.file))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                (0x948d0, Some("link.stub".to_string())),
+                (
+                    0x95040,
+                    Some("tmpxft_000040a0_00000000-6_demo.cudafe1.cpp".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_new_not_elf() {
+        let err = Elf::new(b"definitely not an elf file").err().unwrap();
+        assert!(matches!(err, ElfError::NotElf));
+    }
+
+    #[test]
+    fn test_new_truncated() {
+        let so_path = fixture("libdemo.so");
+        let data = std::fs::read(so_path.clone()).unwrap();
+        let err = Elf::new(&data[..0x100]).err().unwrap();
+        assert!(matches!(err, ElfError::Truncated));
+    }
+
+    #[test]
+    fn test_missing_segment() {
+        let so_path = fixture("libdemo.so");
+        let mut data = std::fs::read(so_path.clone()).unwrap();
+        // turn the four PT_LOAD program headers into PT_NULL
+        for i in 0..4 {
+            let p_type = 0x40 + i * 0x38;
+            data[p_type..p_type + 4].copy_from_slice(&0u32.to_le_bytes());
+        }
+        let elf64 = Elf::new(&data).unwrap();
+        let err = elf64
+            .get_symbol_offset(b"_Z6matMulPiS_S_iii")
+            .err()
+            .unwrap();
+        assert!(matches!(err, ElfError::MissingSegment(_)));
+    }
+
+    #[test]
+    fn test_addr_offset_translation() {
+        let so_path = fixture("libdemo.so");
+        let data = std::fs::read(so_path.clone()).unwrap();
+        let elf64 = Elf::new(&data).unwrap();
+
+        // R|X segment: vaddr == offset
+        assert_eq!(elf64.addr_to_offset(0xabed).unwrap(), 0xabed);
+        assert_eq!(elf64.offset_to_addr(0xabed).unwrap(), 0xabed);
+        // RW segment: vaddr == offset + 0x1000
+        assert_eq!(elf64.addr_to_offset(0xb5500).unwrap(), 0xb4500);
+        assert_eq!(elf64.offset_to_addr(0xb4500).unwrap(), 0xb5500);
+        // .bss lies past p_filesz of the RW segment
+        let err = elf64.addr_to_offset(0xb55a0).err().unwrap();
+        assert!(matches!(err, ElfError::NotFileBacked(0xb55a0)));
+        // past the last segment
+        let err = elf64.addr_to_offset(0x100000).err().unwrap();
+        assert!(matches!(err, ElfError::UnmappedAddress(0x100000)));
+        // section headers are not loaded
+        let err = elf64.offset_to_addr(0xf8be8).err().unwrap();
+        assert!(matches!(err, ElfError::UnmappedOffset(0xf8be8)));
+    }
+
+    // a local function of libdemo.so, only listed in .symtab
+    const LOCAL_SYMBOL: &[u8] = b"libcudart_static_955d0e854921d49ef397edbaffbaf12e1afbf272";
+
+    #[test]
+    fn test_get_symbol_offset_missing_string_table() {
+        let so_path = fixture("libdemo.so");
+        let mut data = std::fs::read(so_path.clone()).unwrap();
+        // point the name of .strtab (section 32) at the name of .comment (section 30)
+        let shoff = 0xf8be8;
+        let comment_name = shoff + 30 * 0x40;
+        let strtab_name = shoff + 32 * 0x40;
+        data.copy_within(comment_name..comment_name + 4, strtab_name);
+        let elf64 = Elf::new(&data).unwrap();
+        let err = elf64.get_symbol_offset(LOCAL_SYMBOL).err().unwrap();
+        assert!(matches!(err, ElfError::MissingStringTable(".strtab")));
+    }
+
+    #[test]
+    fn test_get_symbol_offset_bad_symbol_index() {
+        let so_path = fixture("libdemo.so");
+        let mut data = std::fs::read(so_path.clone()).unwrap();
+        // corrupt st_name of the first real .symtab entry
+        let st_name = 0xb45e0 + 0x18;
+        data[st_name..st_name + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let elf64 = Elf::new(&data).unwrap();
+        let err = elf64.get_symbol_offset(LOCAL_SYMBOL).err().unwrap();
+        assert!(matches!(err, ElfError::BadSymbolIndex(_)));
+    }
+
+    // (fixture, is 64-bit, is big-endian, fixture_add offset, .nv_fatbin offset)
+    const SYNTH_FIXTURES: [(&str, bool, bool, u64, u64); 4] = [
+        ("libsynth.64le.so", true, false, 0x410, 0x440),
+        ("libsynth.32le.so", false, false, 0x410, 0x440),
+        ("libsynth.64be.so", true, true, 0x410, 0x440),
+        ("libsynth.32be.so", false, true, 0x410, 0x440),
+    ];
+
+    #[test]
+    fn test_elf_class_and_endianness() {
+        for (name, is_64bit, is_big_endian, _, _) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+            assert_eq!(elf.is_64bit(), is_64bit, "{}", name);
+            assert_eq!(elf.is_big_endian(), is_big_endian, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_host_isa() {
+        for (name, isa) in [
+            ("libfatbins.so", HostIsa::X86_64),
+            ("libfatbins.aarch64.so", HostIsa::Aarch64),
+            ("libfatbins.ppc64le.so", HostIsa::Ppc64le),
+            ("libsynth.32le.so", HostIsa::Other(3)),
+            // big endian POWER is not a CUDA host
+            ("libsynth.64be.so", HostIsa::Other(21)),
+        ] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            assert_eq!(Elf::new(&data).unwrap().host_isa(), isa, "{}", name);
+        }
+        assert_eq!(
+            serde_json::to_string(&[HostIsa::X86_64, HostIsa::Aarch64, HostIsa::Ppc64le]).unwrap(),
+            r#"["x86_64","aarch64","ppc64le"]"#
+        );
+        assert_eq!(HostIsa::Other(243).to_string(), "e_machine 243");
+    }
+
+    #[test]
+    fn test_symbol_lookup_all_classes() {
+        for (name, _, _, fixture_add_offset, _) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+            let offset = elf.get_symbol_offset(b"fixture_add").unwrap().unwrap();
+            assert_eq!(offset, fixture_add_offset, "{}", name);
+            let addr = elf.get_symbol_addr(b"fixture_add").unwrap().unwrap();
+            assert_eq!(addr, fixture_add_offset, "{}", name);
+            assert!(elf.get_symbol_offset(b"no_such_symbol").unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_gpu_code_all_classes() {
+        for (name, _, _, _, fatbin_offset) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+            assert!(elf.has_gpu_code().unwrap(), "{}", name);
+            assert_eq!(elf.get_gpu_code_offset().unwrap(), Some(fatbin_offset));
+            assert_eq!(elf.get_gpu_code_size().unwrap(), Some(0x770));
+            assert_eq!(
+                elf.get_section_offset(".text").unwrap(),
+                Some(fatbin_offset - 0x40)
+            );
+        }
+    }
+
+    #[test]
+    fn test_hashed_symbol_lookup() {
+        for (name, _, _, fixture_add_offset, _) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+            assert!(
+                elf.gnu_hash.is_some() && elf.sysv_hash.is_some(),
+                "{}",
+                name
+            );
+
+            // exported symbols never touch the name index
+            let offset = elf.get_symbol_offset(b"fixture_add").unwrap();
+            assert_eq!(offset, Some(fixture_add_offset), "{}", name);
+            assert!(elf.symbol_index.get().is_none(), "{}", name);
+
+            // the SysV table gives the same answer
+            let mut sysv_only = Elf::new(&data).unwrap();
+            sysv_only.gnu_hash = None;
+            let offset = sysv_only.get_symbol_offset(b"fixture_add").unwrap();
+            assert_eq!(offset, Some(fixture_add_offset), "{}", name);
+            assert!(sysv_only.symbol_index.get().is_none(), "{}", name);
+
+            // local symbols fall back to the index
+            let offset = elf.get_symbol_offset(b"fixture_local").unwrap();
+            assert_eq!(offset, Some(fixture_add_offset + 0x20), "{}", name);
+            assert!(elf.symbol_index.get().is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_get_symbol_addrs() {
+        let so_path = fixture("libdemo.so");
+        let data = std::fs::read(so_path.clone()).unwrap();
+        let elf64 = Elf::new(&data).unwrap();
+        let names: [&[u8]; 4] = [
+            b"_Z6matMulPiS_S_iii",
+            LOCAL_SYMBOL,
+            b"no_such_symbol",
+            b"sem_destroy", // undefined, imported from libc
+        ];
+        let addrs = elf64.get_symbol_addrs(names).unwrap();
+        assert_eq!(addrs, vec![Some(0xabed), Some(0xb480), None, None]);
+    }
+
+    #[test]
+    fn test_get_symbol_addrs_mixed() {
+        for (name, _, _, _, _) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+
+            // a batch of exported symbols is resolved through the hash table alone
+            let elf = Elf::new(&data).unwrap();
+            let exported: [&[u8]; 2] = [b"fixture_add", b"fixture_counter"];
+            let addrs = elf.get_symbol_addrs(exported).unwrap();
+            assert!(addrs.iter().all(Option::is_some), "{}", name);
+            assert!(elf.symbol_index.get().is_none(), "{}", name);
+
+            // hash hits, index fallbacks for local, versioned and missing names, in order
+            let names: [&[u8]; 6] = [
+                b"fixture_add",
+                b"fixture_local",
+                b"fixture_mul",
+                b"fixture_versioned@VERS_1",
+                b"no_such_symbol",
+                b"fixture_versioned",
+            ];
+            let addrs = elf.get_symbol_addrs(names).unwrap();
+            assert!(elf.symbol_index.get().is_some(), "{}", name);
+            let single = Elf::new(&data).unwrap();
+            let expected: Vec<Option<u64>> = names
+                .iter()
+                .map(|n| single.get_symbol_addr(n).unwrap())
+                .collect();
+            assert_eq!(addrs, expected, "{}", name);
+            assert!(expected[1].is_some() && expected[3].is_some() && expected[4].is_none());
+            assert_ne!(expected[3], expected[5], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_data_symbol_offset() {
+        for (name, _, _, fixture_add_offset, _) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+
+            // .data is mapped one page above its file offset
+            let offset = elf.get_symbol_offset(b"fixture_counter").unwrap().unwrap();
+            let addr = elf.get_symbol_addr(b"fixture_counter").unwrap().unwrap();
+            assert_eq!(addr, offset + 0x1000, "{}", name);
+            assert_eq!(
+                &data[offset as usize..offset as usize + 4],
+                &7u32.to_le_bytes()
+            );
+
+            // .bss has an address but no file content
+            let addr = elf.get_symbol_addr(b"fixture_bss").unwrap().unwrap();
+            let err = elf.get_symbol_offset(b"fixture_bss").err().unwrap();
+            assert!(
+                matches!(err, ElfError::NotFileBacked(a) if a == addr),
+                "{}",
+                name
+            );
+
+            // text symbols are unaffected
+            let offset = elf.get_symbol_offset(b"fixture_add").unwrap();
+            assert_eq!(offset, Some(fixture_add_offset), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_writable_text_segment() {
+        let so_path = fixture("libdemo.so");
+        let mut data = std::fs::read(so_path.clone()).unwrap();
+        // make the text PT_LOAD (program header 1) R|W|X
+        let p_flags = 0x40 + 0x38 + 4;
+        data[p_flags] = (PF_R | PF_W | PF_X) as u8;
+        let elf64 = Elf::new(&data).unwrap();
+        let offset = elf64.get_symbol_offset(b"_Z6matMulPiS_S_iii").unwrap();
+        assert_eq!(offset, Some(0xabed));
+    }
+
+    #[test]
+    fn test_versioned_symbol_lookup() {
+        for (name, _, _, fixture_add_offset, _) in SYNTH_FIXTURES {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let elf = Elf::new(&data).unwrap();
+            // fixture_versioned@VERS_1 is hidden and comes first in the hash chain
+            let vers_1 = fixture_add_offset + 0x24;
+            let vers_2 = fixture_add_offset + 0x28;
+
+            let lookups: [(&[u8], Option<u64>); 6] = [
+                (b"fixture_versioned", Some(vers_2)),
+                (b"fixture_versioned@VERS_1", Some(vers_1)),
+                (b"fixture_versioned@VERS_2", Some(vers_2)),
+                (b"fixture_versioned@@VERS_2", Some(vers_2)),
+                (b"fixture_versioned@@VERS_1", None),
+                (b"fixture_versioned@VERS_3", None),
+            ];
+            for (symbol, offset) in lookups {
+                assert_eq!(elf.get_symbol_offset(symbol).unwrap(), offset, "{}", name);
+            }
+
+            let info = elf.get_symbol_info(b"fixture_versioned").unwrap().unwrap();
+            assert_eq!(info.name, "fixture_versioned");
+            assert_eq!(info.version.as_deref(), Some("VERS_2"));
+            assert!(!info.hidden);
+            assert_eq!((info.offset, info.size), (Some(vers_2), 4));
+            assert_eq!((info.binding, info.symbol_type), (STB_GLOBAL, STT_FUNC));
+
+            let versions = elf.get_symbol_versions(b"fixture_versioned").unwrap();
+            let versions: Vec<_> = versions
+                .iter()
+                .map(|v| (v.version.as_deref(), v.hidden, v.offset))
+                .collect();
+            assert_eq!(
+                versions,
+                vec![
+                    (Some("VERS_1"), true, Some(vers_1)),
+                    (Some("VERS_2"), false, Some(vers_2)),
+                ],
+                "{}",
+                name
+            );
+
+            // unversioned symbols have no version, .bss symbols no offset
+            let info = elf.get_symbol_info(b"fixture_bss").unwrap().unwrap();
+            assert_eq!(info.version, None);
+            assert_eq!((info.offset, info.size), (None, 0x20));
+            assert_eq!(info.symbol_type, STT_OBJECT);
+            let versions = elf.get_symbol_versions(b"fixture_local").unwrap();
+            assert_eq!(versions.len(), 1);
+            assert_eq!(versions[0].offset, Some(fixture_add_offset + 0x20));
+        }
+    }
+
+    #[test]
+    fn test_required_versions_are_not_definitions() {
+        // libdemo.so only requires versions, e.g., sem_destroy@GLIBC_2.2.5, its own symbols are unversioned
+        let so_path = fixture("libdemo.so");
+        let data = std::fs::read(so_path.clone()).unwrap();
+        let elf64 = Elf::new(&data).unwrap();
+        assert_eq!(
+            elf64.get_symbol_offset(b"sem_destroy@GLIBC_2.2.5").unwrap(),
+            None
+        );
+        let info = elf64
+            .get_symbol_info(b"_Z6matMulPiS_S_iii")
+            .unwrap()
+            .unwrap();
+        assert_eq!((info.version, info.addr), (None, 0xabed));
+    }
 }
@@ -0,0 +1,161 @@
+use crate::elf::archive::open_object;
+use crate::elf::elf::{Elf, ElfError, HostIsa};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use xxhash_rust::xxh3::xxh3_128;
+
+/// The identity of an analyzed library, used to detect that it changed between trace, locate and reconstruct
+///
+/// Two identities are equal only if size, content hash and build-id all match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryIdentity {
+    /// The file size in bytes
+    pub size: u64,
+    /// The XXH3-128 hash of the whole file content, in hex
+    pub content_hash: String,
+    /// The NT_GNU_BUILD_ID note in hex, if the file is an ELF file carrying one
+    pub build_id: Option<String>,
+    /// The instruction set of the host code, if the file is an ELF file, absent in reports of older versions
+    #[serde(default)]
+    pub isa: Option<HostIsa>,
+}
+
+// the ISA follows from the content, and is not recorded by older versions
+impl PartialEq for LibraryIdentity {
+    fn eq(&self, other: &LibraryIdentity) -> bool {
+        self.size == other.size
+            && self.content_hash == other.content_hash
+            && self.build_id == other.build_id
+    }
+}
+
+impl Eq for LibraryIdentity {}
+
+impl LibraryIdentity {
+    /// Compute the identity of the given file content
+    pub fn new(data: &[u8]) -> LibraryIdentity {
+        // a file that does not parse as ELF still has a size and a hash
+        let elf = Elf::new(data).ok();
+        let build_id = elf
+            .as_ref()
+            .and_then(|elf| elf.get_build_id().ok())
+            .flatten()
+            .map(to_hex);
+        LibraryIdentity {
+            size: data.len() as u64,
+            content_hash: format!("{:032x}", xxh3_128(data)),
+            build_id,
+            isa: elf.map(|elf| elf.host_isa()),
+        }
+    }
+
+    /// Open the file or archive member at the given path and compute its identity
+    pub fn from_path(path: &str) -> Result<LibraryIdentity, ElfError> {
+        let data = open_object(path)?;
+        Ok(LibraryIdentity::new(&data))
+    }
+}
+
+impl fmt::Display for LibraryIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "size {}, xxh3 {}", self.size, self.content_hash)?;
+        if let Some(build_id) = &self.build_id {
+            write!(f, ", build-id {}", build_id)?;
+        }
+        if let Some(isa) = &self.isa {
+            write!(f, ", {}", isa)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_build_id() {
+        let identity = LibraryIdentity::from_path(&fixture("libdemo.so")).unwrap();
+        assert_eq!(
+            identity.build_id.as_deref(),
+            Some("679bae2239bacda899a56fc914f4067b625c03e8")
+        );
+        assert_eq!(
+            identity.size,
+            std::fs::metadata(fixture("libdemo.so")).unwrap().len()
+        );
+
+        // the note is parsed in the byte order of each class
+        for name in [
+            "libsynth.64le.so",
+            "libsynth.32le.so",
+            "libsynth.64be.so",
+            "libsynth.32be.so",
+        ] {
+            let identity = LibraryIdentity::from_path(&fixture(name)).unwrap();
+            assert_eq!(
+                identity.build_id.as_deref(),
+                Some("101112131415161718191a1b1c1d1e1f20212223"),
+                "{}",
+                name
+            );
+        }
+
+        // archive members are identified by their own content
+        let member = format!("{}(synth.o)", fixture("libsynth.a"));
+        assert_eq!(
+            LibraryIdentity::from_path(&member).unwrap(),
+            LibraryIdentity::from_path(&fixture("libsynth.64le.so")).unwrap()
+        );
+
+        // no note, or not an ELF file at all
+        let identity = LibraryIdentity::from_path(&fixture("deps/lib/libfirst.so")).unwrap();
+        assert_eq!(identity.build_id, None);
+        assert_eq!(LibraryIdentity::new(b"not an elf file").build_id, None);
+    }
+
+    #[test]
+    fn test_host_isa() {
+        let identity = LibraryIdentity::from_path(&fixture("libfatbins.aarch64.so")).unwrap();
+        assert_eq!(identity.isa, Some(HostIsa::Aarch64));
+        let json = serde_json::to_value(&identity).unwrap();
+        assert_eq!(json["isa"], "aarch64");
+        assert_eq!(LibraryIdentity::new(b"not an elf file").isa, None);
+
+        // identities recorded without the ISA still match
+        let mut older = json;
+        older.as_object_mut().unwrap().remove("isa");
+        let older: LibraryIdentity = serde_json::from_value(older).unwrap();
+        assert_eq!(older.isa, None);
+        assert_eq!(older, identity);
+    }
+
+    #[test]
+    fn test_content_change() {
+        let mut data = std::fs::read(fixture("libsynth.64le.so")).unwrap();
+        let identity = LibraryIdentity::new(&data);
+        assert_eq!(identity, LibraryIdentity::new(&data.clone()));
+        assert_eq!(identity.content_hash.len(), 32);
+
+        // patching a byte keeps size and build-id but not the hash
+        data[0x410] ^= 0xff;
+        let patched = LibraryIdentity::new(&data);
+        assert_eq!(patched.size, identity.size);
+        assert_eq!(patched.build_id, identity.build_id);
+        assert_ne!(patched, identity);
+    }
+}
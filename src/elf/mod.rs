@@ -1,2 +1,12 @@
+/// ELF objects inside ar archives and zip files such as wheels
+pub mod archive;
+/// Editing of the dynamic section: DT_NEEDED, DT_RUNPATH and DT_RPATH
+pub mod editor;
 /// ELF (Executable and Linkable Format) module
 pub mod elf;
+/// Identity of analyzed files: size, content hash, and GNU build-id
+pub mod identity;
+/// Static dependency resolution, following the dynamic linker search order
+pub mod resolver;
+/// Shrinking of sections, removing unused device code from the file
+pub mod rewriter;
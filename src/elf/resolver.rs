@@ -0,0 +1,865 @@
+use crate::elf::elf::{Elf, ElfError};
+use crate::utils::mapped::MappedFile;
+use elf::abi::{EI_NIDENT, EM_386, EM_AARCH64, EM_PPC64, EM_X86_64};
+use elf::endian::AnyEndian;
+use elf::file::{parse_ident, Class, FileHeader};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::path::Path;
+
+const OLD_CACHE_MAGIC: &[u8] = b"ld.so-1.7.0";
+const NEW_CACHE_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+const OLD_HEADER_SIZE: usize = 16;
+const OLD_ENTRY_SIZE: usize = 12;
+const NEW_HEADER_SIZE: usize = 48;
+const NEW_ENTRY_SIZE: usize = 24;
+// entry flags: the low byte is the library type, only glibc (libc6) libraries are usable
+const FLAG_TYPE_MASK: u32 = 0xff;
+const FLAG_ELF_LIBC6: u32 = 0x3;
+
+/// The library name -> path mapping of a parsed /etc/ld.so.cache
+///
+/// Both the old "ld.so-1.7.0" and the new "glibc-ld.so.cache1.1" formats are supported,
+/// the new one is preferred when a file carries both.
+pub struct LdCache {
+    entries: Vec<(String, String)>,
+}
+
+impl LdCache {
+    /// Read and parse the cache file at the given path
+    pub fn load(path: &str) -> Result<LdCache, ElfError> {
+        let data = std::fs::read(path)?;
+        LdCache::parse(&data)
+    }
+
+    /// Parse the content of a cache file
+    pub fn parse(data: &[u8]) -> Result<LdCache, ElfError> {
+        if data.starts_with(NEW_CACHE_MAGIC) {
+            return Self::parse_new(data);
+        }
+        if !data.starts_with(OLD_CACHE_MAGIC) {
+            return Err(ElfError::BadLdCache("unknown magic"));
+        }
+        let nlibs = read_u32(data, 12, false)? as usize;
+        let strings = nlibs
+            .checked_mul(OLD_ENTRY_SIZE)
+            .and_then(|size| size.checked_add(OLD_HEADER_SIZE))
+            .ok_or(ElfError::BadLdCache("too many entries"))?;
+        // the new format, if any, follows the old entries, 8-byte aligned
+        let new_offset = (strings + 7) & !7;
+        if let Some(new_data) = data.get(new_offset..) {
+            if new_data.starts_with(NEW_CACHE_MAGIC) {
+                return Self::parse_new(new_data);
+            }
+        }
+
+        let mut entries = Vec::with_capacity(nlibs);
+        for i in 0..nlibs {
+            let entry = OLD_HEADER_SIZE + i * OLD_ENTRY_SIZE;
+            let flags = read_u32(data, entry, false)?;
+            if flags & FLAG_TYPE_MASK != FLAG_ELF_LIBC6 {
+                continue;
+            }
+            let key = read_cstr(data, strings, read_u32(data, entry + 4, false)?)?;
+            let value = read_cstr(data, strings, read_u32(data, entry + 8, false)?)?;
+            entries.push((key, value));
+        }
+        Ok(LdCache { entries })
+    }
+
+    // parse the new format, string offsets are relative to its header
+    fn parse_new(data: &[u8]) -> Result<LdCache, ElfError> {
+        // the low two bits of the header flags record the byte order, 2 for little and 3 for big
+        let flags = *data
+            .get(28)
+            .ok_or(ElfError::BadLdCache("truncated header"))?;
+        let big_endian = match flags & 0x3 {
+            2 => false,
+            3 => true,
+            _ => cfg!(target_endian = "big"),
+        };
+        let nlibs = read_u32(data, 20, big_endian)? as usize;
+        let mut entries = Vec::with_capacity(nlibs.min(data.len() / NEW_ENTRY_SIZE));
+        for i in 0..nlibs {
+            let entry = NEW_HEADER_SIZE + i * NEW_ENTRY_SIZE;
+            let flags = read_u32(data, entry, big_endian)?;
+            if flags & FLAG_TYPE_MASK != FLAG_ELF_LIBC6 {
+                continue;
+            }
+            let key = read_cstr(data, 0, read_u32(data, entry + 4, big_endian)?)?;
+            let value = read_cstr(data, 0, read_u32(data, entry + 8, big_endian)?)?;
+            entries.push((key, value));
+        }
+        Ok(LdCache { entries })
+    }
+
+    /// Get the cached paths of the given library name, in cache order
+    pub fn lookup<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Result<u32, ElfError> {
+    let bytes: [u8; 4] = data
+        .get(offset..offset + 4)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(ElfError::BadLdCache("truncated entry"))?;
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn read_cstr(data: &[u8], base: usize, offset: u32) -> Result<String, ElfError> {
+    let tail = data
+        .get(base + offset as usize..)
+        .ok_or(ElfError::BadLdCache("string offset out of file"))?;
+    let len = tail
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(ElfError::BadLdCache("unterminated string"))?;
+    Ok(String::from_utf8_lossy(&tail[..len]).into_owned())
+}
+
+// the ELF header fields the dynamic linker requires to match between an object and its dependencies
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ElfIdent {
+    is_64bit: bool,
+    is_big_endian: bool,
+    machine: u16,
+}
+
+impl ElfIdent {
+    fn of(elf: &Elf) -> ElfIdent {
+        ElfIdent {
+            is_64bit: elf.is_64bit(),
+            is_big_endian: elf.is_big_endian(),
+            machine: elf.machine(),
+        }
+    }
+
+    // read the identification of the file at the given path, None if it is not a readable ELF file
+    fn read(path: &Path) -> Option<ElfIdent> {
+        let mut header = [0u8; 64];
+        let mut file = std::fs::File::open(path).ok()?;
+        if !file.metadata().ok()?.is_file() {
+            return None;
+        }
+        let len = file.read(&mut header).ok()?;
+        let header = &header[..len];
+        let ident = parse_ident::<AnyEndian>(header).ok()?;
+        let ehdr = FileHeader::parse_tail(ident, header.get(EI_NIDENT..)?).ok()?;
+        Some(ElfIdent {
+            is_64bit: ehdr.class == Class::ELF64,
+            is_big_endian: ehdr.endianness == AnyEndian::Big,
+            machine: ehdr.e_machine,
+        })
+    }
+
+    // the value of $LIB
+    fn lib_dir(&self) -> &'static str {
+        if self.is_64bit {
+            "lib64"
+        } else {
+            "lib"
+        }
+    }
+
+    // the value of $PLATFORM, the AT_PLATFORM the kernel passes to processes of this machine
+    // POWER kernels pass the CPU generation, e.g., power9, the ISA is the closest guess without the host
+    fn platform(&self) -> Option<&'static str> {
+        match (self.machine, self.is_big_endian) {
+            (EM_X86_64, false) if self.is_64bit => Some("x86_64"),
+            (EM_386, false) => Some("i686"),
+            (EM_AARCH64, false) => Some("aarch64"),
+            (EM_PPC64, false) => Some("ppc64le"),
+            (EM_PPC64, true) => Some("ppc64"),
+            _ => None,
+        }
+    }
+
+    // the trusted system directories, searched last
+    fn system_dirs(&self) -> Vec<String> {
+        let multiarch = match (self.machine, self.is_big_endian) {
+            (EM_X86_64, false) if self.is_64bit => Some("x86_64-linux-gnu"),
+            (EM_386, false) => Some("i386-linux-gnu"),
+            (EM_AARCH64, false) => Some("aarch64-linux-gnu"),
+            (EM_PPC64, false) => Some("powerpc64le-linux-gnu"),
+            (EM_PPC64, true) => Some("powerpc64-linux-gnu"),
+            _ => None,
+        };
+        let mut dirs = vec![];
+        if let Some(triple) = multiarch {
+            dirs.push(format!("/lib/{}", triple));
+            dirs.push(format!("/usr/lib/{}", triple));
+        }
+        if self.is_64bit {
+            dirs.push("/lib64".to_string());
+            dirs.push("/usr/lib64".to_string());
+        } else {
+            dirs.push("/lib32".to_string());
+            dirs.push("/usr/lib32".to_string());
+        }
+        dirs.push("/lib".to_string());
+        dirs.push("/usr/lib".to_string());
+        dirs
+    }
+}
+
+/// A DT_NEEDED entry and the library it resolved to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    /// The name as recorded in DT_NEEDED
+    pub name: String,
+    /// The canonical path of the resolved library, None if it was not found
+    pub path: Option<String>,
+}
+
+/// An object in the dependency graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyNode {
+    /// The DT_SONAME of the object, if any
+    pub soname: Option<String>,
+    /// The DT_NEEDED entries of the object, in load order
+    pub dependencies: Vec<Dependency>,
+    /// Whether the object embeds device code, i.e., is worth locating
+    #[serde(default)]
+    pub has_gpu_code: bool,
+}
+
+/// The static dependency graph of an executable or shared library
+///
+/// Nodes are keyed by canonical path, the root included.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    /// The canonical path of the resolved executable or shared library
+    pub root: String,
+    /// The program interpreter requested by the root, if any
+    pub interpreter: Option<String>,
+    pub nodes: BTreeMap<String, DependencyNode>,
+}
+
+impl DependencyGraph {
+    /// Get the canonical paths of all resolved libraries, excluding the root
+    pub fn libraries(&self) -> Vec<&str> {
+        self.nodes
+            .keys()
+            .filter(|path| **path != self.root)
+            .map(|path| path.as_str())
+            .collect()
+    }
+
+    /// Get the DT_NEEDED names that could not be resolved, without duplicates
+    pub fn unresolved(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .nodes
+            .values()
+            .flat_map(|node| node.dependencies.iter())
+            .filter(|dep| dep.path.is_none())
+            .map(|dep| dep.name.as_str())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Get the loaded shared libraries that are not in the static closure, i.e., reached only through dlopen
+    ///
+    /// `loaded_sos` are the paths reported by the tracer, the interpreter is never reported.
+    pub fn dlopen_only<'a, I>(&self, loaded_sos: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let interpreter = self.interpreter.as_deref().map(canonical_path);
+        let mut dlopen_only: Vec<String> = loaded_sos
+            .into_iter()
+            .filter(|so_path| {
+                let so_path = canonical_path(so_path);
+                !self.nodes.contains_key(&so_path) && Some(&so_path) != interpreter.as_ref()
+            })
+            .cloned()
+            .collect();
+        dlopen_only.sort();
+        dlopen_only
+    }
+
+    /// Render the graph in Graphviz DOT format, unresolved libraries are drawn dashed and objects with device code
+    /// are drawn with a double border
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        for (path, node) in self.nodes.iter() {
+            let label = node
+                .soname
+                .clone()
+                .unwrap_or_else(|| file_name(path).to_string());
+            let shape = if *path == self.root { "box" } else { "ellipse" };
+            let peripheries = if node.has_gpu_code { 2 } else { 1 };
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\", shape={}, peripheries={}];\n",
+                escape(path),
+                escape(&label),
+                shape,
+                peripheries
+            ));
+        }
+        for name in self.unresolved() {
+            dot.push_str(&format!(
+                "    \"missing:{}\" [label=\"{}\", style=dashed, color=red];\n",
+                escape(name),
+                escape(name)
+            ));
+        }
+        for (path, node) in self.nodes.iter() {
+            for dep in node.dependencies.iter() {
+                match &dep.path {
+                    Some(dep_path) => dot.push_str(&format!(
+                        "    \"{}\" -> \"{}\";\n",
+                        escape(path),
+                        escape(dep_path)
+                    )),
+                    None => dot.push_str(&format!(
+                        "    \"{}\" -> \"missing:{}\" [style=dashed];\n",
+                        escape(path),
+                        escape(&dep.name)
+                    )),
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn canonical_path(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// an object loaded while resolving, with its search paths already expanded
+struct LoadedObject {
+    path: String,
+    ident: ElfIdent,
+    soname: Option<String>,
+    needed: Vec<String>,
+    rpath: Vec<String>,
+    runpath: Option<Vec<String>>,
+    has_gpu_code: bool,
+    loader: Option<usize>,
+}
+
+// the objects loaded so far while resolving a dependency graph
+struct Closure {
+    objects: Vec<LoadedObject>,
+    dependencies: Vec<Vec<Dependency>>,
+    by_path: HashMap<String, usize>,
+    by_name: HashMap<String, usize>,
+    queue: VecDeque<usize>,
+}
+
+impl Closure {
+    // add a loaded object and queue its dependencies
+    fn push(&mut self, object: LoadedObject) -> usize {
+        let index = self.objects.len();
+        if let Some(soname) = &object.soname {
+            self.by_name.entry(soname.clone()).or_insert(index);
+        }
+        self.by_path.insert(object.path.clone(), index);
+        self.objects.push(object);
+        self.dependencies.push(vec![]);
+        self.queue.push_back(index);
+        index
+    }
+
+    // load the library found at the given path, unless the same file is already loaded
+    fn load(&mut self, found: &str, requester: usize) -> Option<usize> {
+        let canonical = canonical_path(found);
+        if let Some(&index) = self.by_path.get(&canonical) {
+            return Some(index);
+        }
+        let object = MappedFile::open(found)
+            .map_err(ElfError::from)
+            .and_then(|data| {
+                DependencyResolver::load_object(found, &Elf::new(&data)?, Some(requester))
+            });
+        match object {
+            Ok(mut object) => {
+                object.path = canonical;
+                Some(self.push(object))
+            }
+            Err(e) => {
+                warn!("Skipping {}: {}", found, e);
+                None
+            }
+        }
+    }
+}
+
+/// Resolve the DT_NEEDED closure of an ELF file without running it
+///
+/// Libraries are searched in the order of the glibc dynamic linker:
+/// DT_RPATH of the requesting object and its loaders (unless the requester has DT_RUNPATH),
+/// LD_LIBRARY_PATH, DT_RUNPATH of the requesting object, ld.so.cache, and the system directories.
+/// Candidates with a different ELF class, byte order, or machine are skipped.
+pub struct DependencyResolver {
+    library_path: Vec<String>,
+    ld_cache: Option<LdCache>,
+    system_dirs: Option<Vec<String>>,
+}
+
+impl DependencyResolver {
+    /// Create a resolver
+    ///
+    /// * `library_path`: the LD_LIBRARY_PATH directories
+    /// * `ld_cache`: the parsed ld.so.cache, if any
+    /// * `system_dirs`: the directories searched last, None for the defaults of each ELF class and machine
+    pub fn new(
+        library_path: Vec<String>,
+        ld_cache: Option<LdCache>,
+        system_dirs: Option<Vec<String>>,
+    ) -> DependencyResolver {
+        DependencyResolver {
+            library_path,
+            ld_cache,
+            system_dirs,
+        }
+    }
+
+    /// Create a resolver from the current LD_LIBRARY_PATH and /etc/ld.so.cache
+    pub fn from_env() -> DependencyResolver {
+        let library_path = std::env::var("LD_LIBRARY_PATH")
+            .map(|value| {
+                value
+                    .split([':', ';'])
+                    .filter(|dir| !dir.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let ld_cache = match LdCache::load("/etc/ld.so.cache") {
+            Ok(ld_cache) => Some(ld_cache),
+            Err(e) => {
+                warn!("Ignoring /etc/ld.so.cache: {}", e);
+                None
+            }
+        };
+        DependencyResolver::new(library_path, ld_cache, None)
+    }
+
+    /// Resolve the dependency graph of the ELF file at the given path
+    pub fn resolve(&self, path: &str) -> Result<DependencyGraph, ElfError> {
+        let root_path = std::fs::canonicalize(path)?.to_string_lossy().into_owned();
+        let data = MappedFile::open(&root_path)?;
+        let root_elf = Elf::new(&data)?;
+        let interpreter = root_elf.get_interpreter()?;
+        let root = Self::load_object(&root_path, &root_elf, None)?;
+
+        let mut closure = Closure {
+            objects: vec![],
+            dependencies: vec![],
+            by_path: HashMap::new(),
+            by_name: HashMap::new(),
+            queue: VecDeque::new(),
+        };
+        closure.push(root);
+        let mut unresolved = HashSet::new();
+
+        // breadth first, the order the dynamic linker maps objects
+        while let Some(requester) = closure.queue.pop_front() {
+            for name in closure.objects[requester].needed.clone() {
+                let mut index = closure.by_name.get(&name).copied();
+                if index.is_none() && !unresolved.contains(&name) {
+                    index = self
+                        .search(&closure.objects, requester, &name)
+                        .and_then(|found| closure.load(&found, requester));
+                }
+                match index {
+                    Some(index) => {
+                        closure.by_name.insert(name.clone(), index);
+                    }
+                    None => {
+                        unresolved.insert(name.clone());
+                    }
+                }
+                let path = index.map(|index| closure.objects[index].path.clone());
+                closure.dependencies[requester].push(Dependency { name, path });
+            }
+        }
+
+        let Closure {
+            objects,
+            dependencies,
+            ..
+        } = closure;
+        let nodes = objects
+            .into_iter()
+            .zip(dependencies)
+            .map(|(object, dependencies)| {
+                (
+                    object.path,
+                    DependencyNode {
+                        soname: object.soname,
+                        dependencies,
+                        has_gpu_code: object.has_gpu_code,
+                    },
+                )
+            })
+            .collect();
+        Ok(DependencyGraph {
+            root: root_path,
+            interpreter,
+            nodes,
+        })
+    }
+
+    // read the dynamic section of an object found at the given path, expanding its search paths
+    fn load_object(path: &str, elf: &Elf, loader: Option<usize>) -> Result<LoadedObject, ElfError> {
+        let ident = ElfIdent::of(elf);
+        let origin = Path::new(path)
+            .parent()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let expand = |dirs: Vec<String>| -> Vec<String> {
+            dirs.iter()
+                .map(|dir| expand_tokens(dir, &origin, &ident))
+                .collect()
+        };
+        let runpath = elf.get_runpath()?;
+        let has_runpath = !runpath.is_empty();
+        Ok(LoadedObject {
+            path: path.to_string(),
+            ident,
+            soname: elf.get_soname()?,
+            needed: elf.get_needed()?,
+            rpath: expand(elf.get_rpath()?),
+            runpath: has_runpath.then(|| expand(runpath)),
+            has_gpu_code: elf.has_gpu_code()?,
+            loader,
+        })
+    }
+
+    // find the path of a DT_NEEDED name requested by objects[requester]
+    fn search(&self, objects: &[LoadedObject], requester: usize, name: &str) -> Option<String> {
+        let ident = objects[requester].ident;
+        let compatible = |path: &Path| ElfIdent::read(path) == Some(ident);
+        // names with a slash are paths and are not searched
+        if name.contains('/') {
+            return compatible(Path::new(name)).then(|| name.to_string());
+        }
+
+        let mut dirs: Vec<&str> = vec![];
+        if objects[requester].runpath.is_none() {
+            let mut next = Some(requester);
+            while let Some(index) = next {
+                if objects[index].runpath.is_none() {
+                    dirs.extend(objects[index].rpath.iter().map(|d| d.as_str()));
+                }
+                next = objects[index].loader;
+            }
+        }
+        dirs.extend(self.library_path.iter().map(|d| d.as_str()));
+        if let Some(runpath) = &objects[requester].runpath {
+            dirs.extend(runpath.iter().map(|d| d.as_str()));
+        }
+        for dir in dirs {
+            let candidate = Path::new(dir).join(name);
+            if compatible(&candidate) {
+                return Some(candidate.to_string_lossy().into_owned());
+            }
+        }
+
+        if let Some(ld_cache) = &self.ld_cache {
+            if let Some(found) = ld_cache.lookup(name).find(|p| compatible(Path::new(p))) {
+                return Some(found.to_string());
+            }
+        }
+
+        let system_dirs = match &self.system_dirs {
+            Some(dirs) => dirs.clone(),
+            None => ident.system_dirs(),
+        };
+        system_dirs
+            .iter()
+            .map(|dir| Path::new(dir).join(name))
+            .find(|candidate| compatible(candidate))
+            .map(|candidate| candidate.to_string_lossy().into_owned())
+    }
+}
+
+// expand the $ORIGIN, $LIB and $PLATFORM dynamic string tokens of a search directory
+// the tokens follow the object, not the analysis host, $PLATFORM is left as is on unknown machines
+fn expand_tokens(dir: &str, origin: &str, ident: &ElfIdent) -> String {
+    let mut expanded = dir.to_string();
+    let tokens = [
+        ("ORIGIN", Some(origin)),
+        ("LIB", Some(ident.lib_dir())),
+        ("PLATFORM", ident.platform()),
+    ];
+    for (token, value) in tokens {
+        let Some(value) = value else {
+            continue;
+        };
+        expanded = expanded
+            .replace(&format!("${{{}}}", token), value)
+            .replace(&format!("${}", token), value);
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // build a cache file in the new format, optionally behind an old format header
+    fn build_ld_cache(entries: &[(&str, &str)], with_old: bool) -> Vec<u8> {
+        let mut strings = vec![];
+        let mut offsets = vec![];
+        let strings_base = NEW_HEADER_SIZE + entries.len() * NEW_ENTRY_SIZE;
+        for (key, value) in entries {
+            let key_offset = strings_base + strings.len();
+            strings.extend_from_slice(key.as_bytes());
+            strings.push(0);
+            let value_offset = strings_base + strings.len();
+            strings.extend_from_slice(value.as_bytes());
+            strings.push(0);
+            offsets.push((key_offset as u32, value_offset as u32));
+        }
+
+        let mut new = NEW_CACHE_MAGIC.to_vec();
+        new.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        new.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        new.push(2); // little endian
+        new.resize(NEW_HEADER_SIZE, 0);
+        for (key, value) in offsets {
+            new.extend_from_slice(&(FLAG_ELF_LIBC6 | 0x0300).to_le_bytes());
+            new.extend_from_slice(&key.to_le_bytes());
+            new.extend_from_slice(&value.to_le_bytes());
+            new.extend_from_slice(&0u32.to_le_bytes());
+            new.extend_from_slice(&0u64.to_le_bytes());
+        }
+        new.extend_from_slice(&strings);
+        if !with_old {
+            return new;
+        }
+
+        let mut data = OLD_CACHE_MAGIC.to_vec();
+        data.resize(OLD_HEADER_SIZE, 0); // no old entries
+        data.extend_from_slice(&new);
+        data
+    }
+
+    // build a cache file in the old format only
+    fn build_old_ld_cache(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = OLD_CACHE_MAGIC.to_vec();
+        data.push(0);
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        let mut strings = vec![];
+        for (key, value) in entries {
+            data.extend_from_slice(&FLAG_ELF_LIBC6.to_le_bytes());
+            data.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            strings.extend_from_slice(key.as_bytes());
+            strings.push(0);
+            data.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            strings.extend_from_slice(value.as_bytes());
+            strings.push(0);
+        }
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    #[test]
+    fn test_ld_cache_formats() {
+        let entries = [
+            ("libfoo.so.1", "/opt/a/libfoo.so.1"),
+            ("libfoo.so.1", "/opt/b/libfoo.so.1"),
+        ];
+        for data in [
+            build_ld_cache(&entries, false),
+            build_ld_cache(&entries, true),
+            build_old_ld_cache(&entries),
+        ] {
+            let ld_cache = LdCache::parse(&data).unwrap();
+            let found: Vec<&str> = ld_cache.lookup("libfoo.so.1").collect();
+            assert_eq!(found, vec!["/opt/a/libfoo.so.1", "/opt/b/libfoo.so.1"]);
+            assert_eq!(ld_cache.lookup("libbar.so").count(), 0);
+        }
+
+        let err = LdCache::parse(b"not a cache").err().unwrap();
+        assert!(matches!(err, ElfError::BadLdCache(_)));
+        let data = build_ld_cache(&entries, false);
+        let err = LdCache::parse(&data[..NEW_HEADER_SIZE + 4]).err().unwrap();
+        assert!(matches!(err, ElfError::BadLdCache(_)));
+    }
+
+    #[test]
+    fn test_expand_tokens() {
+        let aarch64 = ElfIdent {
+            is_64bit: true,
+            is_big_endian: false,
+            machine: EM_AARCH64,
+        };
+        assert_eq!(
+            expand_tokens("$ORIGIN/../${LIB}/$PLATFORM", "/opt/app/lib", &aarch64),
+            "/opt/app/lib/../lib64/aarch64"
+        );
+        let ppc64le = ElfIdent {
+            machine: EM_PPC64,
+            ..aarch64
+        };
+        assert_eq!(
+            expand_tokens("/opt/${PLATFORM}", "", &ppc64le),
+            "/opt/ppc64le"
+        );
+        let unknown = ElfIdent {
+            machine: 0,
+            ..aarch64
+        };
+        assert_eq!(
+            expand_tokens("/opt/$PLATFORM", "", &unknown),
+            "/opt/$PLATFORM"
+        );
+    }
+
+    #[test]
+    fn test_dynamic_entries() {
+        let data = std::fs::read(fixture("deps/bin/app")).unwrap();
+        let elf64 = Elf::new(&data).unwrap();
+        assert_eq!(elf64.get_needed().unwrap(), vec!["libfirst.so"]);
+        assert_eq!(elf64.get_rpath().unwrap(), vec!["$ORIGIN/../lib"]);
+        assert!(elf64.get_runpath().unwrap().is_empty());
+        assert_eq!(elf64.get_soname().unwrap(), None);
+        assert_eq!(
+            elf64.get_interpreter().unwrap().as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+
+        let data = std::fs::read(fixture("deps/lib/libsecond.so")).unwrap();
+        let elf64 = Elf::new(&data).unwrap();
+        assert_eq!(elf64.get_soname().unwrap().as_deref(), Some("libsecond.so"));
+        assert_eq!(elf64.get_runpath().unwrap(), vec!["$ORIGIN/private"]);
+        assert_eq!(elf64.get_interpreter().unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve_closure() {
+        let resolver = DependencyResolver::new(vec![], None, Some(vec![]));
+        let graph = resolver.resolve(&fixture("deps/bin/app")).unwrap();
+        let app = canonical_path(&fixture("deps/bin/app"));
+        let first = canonical_path(&fixture("deps/lib/libfirst.so"));
+        let second = canonical_path(&fixture("deps/lib/libsecond.so"));
+        let third = canonical_path(&fixture("deps/lib/private/libthird.so"));
+
+        assert_eq!(graph.root, app);
+        assert_eq!(
+            graph.interpreter.as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+        let mut libraries = vec![first.as_str(), second.as_str(), third.as_str()];
+        libraries.sort();
+        assert_eq!(graph.libraries(), libraries);
+        assert_eq!(graph.unresolved(), vec!["libmissing.so.1"]);
+        assert_eq!(
+            graph.nodes[&first].dependencies,
+            vec![
+                Dependency {
+                    name: "libsecond.so".to_string(),
+                    path: Some(second.clone()),
+                },
+                Dependency {
+                    name: "libmissing.so.1".to_string(),
+                    path: None,
+                },
+            ]
+        );
+        assert_eq!(graph.nodes[&third].soname.as_deref(), Some("libthird.so"));
+        assert!(graph.nodes.values().all(|node| node.has_gpu_code));
+
+        let json = serde_json::to_string(&graph).unwrap();
+        let parsed: DependencyGraph = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, graph);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\";", app, first)));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"missing:libmissing.so.1\" [style=dashed];",
+            first
+        )));
+    }
+
+    #[test]
+    fn test_resolve_search_order() {
+        // without the RPATH of the executable, libsecond.so comes from LD_LIBRARY_PATH,
+        // where the ELFCLASS32 copy in lib32 must be skipped
+        let library_path = vec![fixture("deps/lib32"), fixture("deps/lib")];
+        let ld_cache = build_ld_cache(
+            &[
+                ("libmissing.so.1", &fixture("libsynth.32le.so")),
+                ("libmissing.so.1", &fixture("libsynth.64le.so")),
+            ],
+            false,
+        );
+        let resolver = DependencyResolver::new(
+            library_path,
+            Some(LdCache::parse(&ld_cache).unwrap()),
+            Some(vec![]),
+        );
+        let graph = resolver.resolve(&fixture("deps/lib/libfirst.so")).unwrap();
+        let first = canonical_path(&fixture("deps/lib/libfirst.so"));
+        let dependencies = &graph.nodes[&first].dependencies;
+        assert_eq!(
+            dependencies[0].path,
+            Some(canonical_path(&fixture("deps/lib/libsecond.so")))
+        );
+        assert_eq!(
+            dependencies[1].path,
+            Some(canonical_path(&fixture("libsynth.64le.so")))
+        );
+        assert!(graph.unresolved().is_empty());
+        assert_eq!(graph.interpreter, None);
+
+        // RUNPATH only applies to the direct dependencies of its object
+        let third = canonical_path(&fixture("deps/lib/private/libthird.so"));
+        assert!(graph.nodes.contains_key(&third));
+    }
+
+    #[test]
+    fn test_dlopen_only() {
+        let resolver = DependencyResolver::new(vec![], None, Some(vec![]));
+        let graph = resolver.resolve(&fixture("deps/bin/app")).unwrap();
+        let loaded_sos = vec![
+            fixture("deps/lib/libfirst.so"),
+            fixture("deps/lib/private/libthird.so"),
+            fixture("libsynth.64le.so"),
+            "/lib64/ld-linux-x86-64.so.2".to_string(),
+        ];
+        assert_eq!(
+            graph.dlopen_only(&loaded_sos),
+            vec![fixture("libsynth.64le.so")]
+        );
+    }
+}
@@ -0,0 +1,655 @@
+use crate::elf::elf::{ElfError, FATBIN_WRAPPER_MAGIC};
+use elf::abi::{PT_LOAD, PT_PHDR, SHT_DYNSYM, SHT_NOBITS, SHT_RELA, SHT_SYMTAB};
+use elf::endian::AnyEndian;
+use elf::file::Class;
+use elf::section::SectionHeader;
+use elf::segment::ProgramHeader;
+use elf::ElfBytes;
+use std::collections::HashMap;
+
+// the smallest page size loaded content is moved by, larger PT_LOAD alignments win
+const MIN_PAGE_SIZE: u64 = 0x1000;
+// e_phnum values from PN_XNUM on are stored in the first section header, which is not supported
+const PN_XNUM: usize = 0xffff;
+
+/// Shrinks sections of an ELF file, physically removing their tail from the file
+///
+/// Addresses never change. A shrunk section keeps the memory size of its segment, the part cut off is no longer
+/// backed by the file. What follows in the file moves to a lower offset, loaded content by whole pages so that
+/// the offsets of the PT_LOAD segments stay congruent to their addresses.
+///
+/// A PT_LOAD segment going on after the shrunk section, e.g., with .eh_frame after .nv_fatbin, is split in two at
+/// the cut. The program headers, one more of them, move to the start of the cut, mapped by the first part.
+///
+/// The file content is rewritten in place, e.g., through a memory mapping, and the caller truncates it by the
+/// number of bytes `shrink_section` returns.
+pub struct SectionRewriter<'data> {
+    data: &'data mut [u8],
+    is_64bit: bool,
+    is_big_endian: bool,
+}
+
+impl<'data> SectionRewriter<'data> {
+    /// Create a rewriter for the given file content
+    pub fn new(data: &'data mut [u8]) -> Result<SectionRewriter<'data>, ElfError> {
+        let ehdr = ElfBytes::<AnyEndian>::minimal_parse(data)?.ehdr;
+        Ok(SectionRewriter {
+            is_64bit: ehdr.class == Class::ELF64,
+            is_big_endian: ehdr.endianness == AnyEndian::Big,
+            data,
+        })
+    }
+
+    /// Keep the first `size` bytes of the named section and drop the rest from the file
+    ///
+    /// Segments covering the cut must end with the section, except one PT_LOAD segment, which is split. Returns the
+    /// number of bytes the file shrank by, what is left past its new end is to be truncated. This can be less than
+    /// the bytes cut off the section since the content after it keeps its alignment, and is zero when moving the
+    /// loaded content would not save a page, the segments then stay as they are.
+    pub fn shrink_section(&mut self, name: &str, size: u64) -> Result<u64, ElfError> {
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(self.data)?;
+        let (shdrs, strtab) = elf.section_headers_with_strtab()?;
+        let shdrs: Vec<SectionHeader> = shdrs
+            .ok_or_else(|| ElfError::MissingSection(name.to_string()))?
+            .iter()
+            .collect();
+        let strtab = strtab.ok_or(ElfError::MissingStringTable(".shstrtab"))?;
+        let index = shdrs
+            .iter()
+            .position(|s| strtab.get(s.sh_name as usize).ok() == Some(name))
+            .ok_or_else(|| ElfError::MissingSection(name.to_string()))?;
+        let section = shdrs[index];
+        if section.sh_type == SHT_NOBITS || size >= section.sh_size {
+            return Ok(0);
+        }
+        let phdrs: Vec<ProgramHeader> = elf
+            .segments()
+            .map(|segments| segments.iter().collect())
+            .unwrap_or_default();
+        let (e_phoff, e_phentsize) = (elf.ehdr.e_phoff, elf.ehdr.e_phentsize as u64);
+        let (e_shoff, e_shentsize) = (elf.ehdr.e_shoff, elf.ehdr.e_shentsize as u64);
+
+        // the bytes cut off the file
+        let cut_start = section.sh_offset + size;
+        let cut_end = section.sh_offset + section.sh_size;
+        if cut_end > self.data.len() as u64 {
+            return Err(ElfError::Truncated);
+        }
+        let overlaps_cut = |start: u64, end: u64| start < cut_end && end > cut_start;
+        // a segment covering the cut has to end with the section, otherwise it would lose content,
+        // except a PT_LOAD segment, which is split in two
+        let shrunk: Vec<bool> = phdrs
+            .iter()
+            .map(|p| overlaps_cut(p.p_offset, p.p_offset + p.p_filesz))
+            .collect();
+        let mut split = None;
+        for (i, (phdr, &shrunk)) in phdrs.iter().zip(&shrunk).enumerate() {
+            if !shrunk || phdr.p_offset <= cut_start && phdr.p_offset + phdr.p_filesz == cut_end {
+                continue;
+            }
+            if phdr.p_type != PT_LOAD
+                || phdr.p_offset > cut_start
+                || phdr.p_offset + phdr.p_filesz < cut_end
+                || split.is_some()
+            {
+                return Err(ElfError::Unsupported(
+                    "segment content after the shrunk section",
+                ));
+            }
+            split = Some(i);
+        }
+        let overlaps_other_section = shdrs.iter().enumerate().any(|(i, s)| {
+            i != index
+                && s.sh_type != SHT_NOBITS
+                && overlaps_cut(s.sh_offset, s.sh_offset + s.sh_size)
+        });
+        if overlaps_other_section
+            || overlaps_cut(e_phoff, e_phoff + e_phentsize * phdrs.len() as u64)
+            || overlaps_cut(e_shoff, e_shoff + e_shentsize * shdrs.len() as u64)
+        {
+            return Err(ElfError::Unsupported(
+                "headers or sections inside the shrunk section",
+            ));
+        }
+
+        let word = if self.is_64bit { 8 } else { 4 };
+        let loads: Vec<&ProgramHeader> = phdrs.iter().filter(|p| p.p_type == PT_LOAD).collect();
+        let page_size = loads
+            .iter()
+            .map(|p| p.p_align)
+            .fold(MIN_PAGE_SIZE, u64::max);
+        // the first part of a split segment ends with the program headers, which keep the offset to address
+        // delta of the first PT_LOAD, older kernels assume it when computing AT_PHDR from e_phoff
+        let phdrs_size = (phdrs.len() as u64 + 1) * e_phentsize;
+        let new_phoff = cut_start.next_multiple_of(word);
+        let kept_end = match split {
+            Some(i) => {
+                let delta = |p: &ProgramHeader| p.p_vaddr.wrapping_sub(p.p_offset);
+                let first_load = loads.iter().min_by_key(|p| p.p_vaddr).unwrap();
+                if delta(&phdrs[i]) != delta(first_load) {
+                    return Err(ElfError::Unsupported(
+                        "split segment mapped unlike the first segment",
+                    ));
+                }
+                if phdrs.len() + 1 >= PN_XNUM {
+                    return Err(ElfError::Unsupported("too many program headers"));
+                }
+                new_phoff + phdrs_size
+            }
+            None => cut_start,
+        };
+
+        // everything after the cut moves by the same amount, which keeps the alignment of all of it,
+        // loaded content moves by whole pages
+        let moves_load = split.is_some() || loads.iter().any(|p| p.p_offset >= cut_end);
+        let alignment = phdrs
+            .iter()
+            .filter(|p| p.p_offset >= cut_end)
+            .map(|p| p.p_align)
+            .chain(moves_load.then_some(page_size))
+            .chain(
+                shdrs
+                    .iter()
+                    .filter(|s| s.sh_offset >= cut_end)
+                    .map(|s| s.sh_addralign),
+            )
+            .chain(
+                [e_phoff, e_shoff]
+                    .into_iter()
+                    .filter(|&o| o >= cut_end)
+                    .map(|_| word),
+            )
+            .fold(1, u64::max);
+        let mut shift = cut_end.saturating_sub(kept_end) / alignment * alignment;
+
+        // a page is mapped from a single place in the file, so the loaded content staying in place and the moved
+        // one must not share a page
+        let kept_end_addr = loads
+            .iter()
+            .filter(|p| p.p_filesz > 0 && p.p_offset < cut_end)
+            .map(|p| p.p_vaddr + (p.p_offset + p.p_filesz).min(kept_end) - p.p_offset)
+            .max()
+            .unwrap_or(0)
+            .next_multiple_of(page_size);
+        let moved_start_addr = loads
+            .iter()
+            .filter(|p| p.p_filesz > 0 && p.p_offset + p.p_filesz > cut_end)
+            .map(|p| p.p_vaddr + cut_end.saturating_sub(p.p_offset))
+            .min();
+        if moved_start_addr.is_some_and(|addr| addr / page_size * page_size < kept_end_addr) {
+            shift = 0;
+        }
+        if shift == 0 {
+            split = None;
+        }
+        let moved = |offset: u64| {
+            if offset >= cut_end {
+                offset - shift
+            } else {
+                offset
+            }
+        };
+
+        let mut new_phdrs = Vec::with_capacity(phdrs.len() + 1);
+        for (i, (&phdr, &shrunk)) in phdrs.iter().zip(&shrunk).enumerate() {
+            let mut phdr = phdr;
+            if split == Some(i) {
+                // the first part keeps the memory up to the second one, which maps the content after the cut
+                let cut = cut_end - phdr.p_offset;
+                let tail = ProgramHeader {
+                    p_offset: cut_end - shift,
+                    p_vaddr: phdr.p_vaddr + cut,
+                    p_paddr: phdr.p_paddr + cut,
+                    p_filesz: phdr.p_filesz - cut,
+                    p_memsz: phdr.p_memsz - cut,
+                    ..phdr
+                };
+                phdr.p_filesz = kept_end - phdr.p_offset;
+                phdr.p_memsz = cut;
+                new_phdrs.extend([phdr, tail]);
+                continue;
+            }
+            // a segment going on after the cut is left as it is when not split
+            if shrunk && phdr.p_offset + phdr.p_filesz == cut_end {
+                phdr.p_filesz -= cut_end - cut_start;
+            }
+            phdr.p_offset = moved(phdr.p_offset);
+            new_phdrs.push(phdr);
+        }
+        let e_phoff = match split {
+            Some(i) => {
+                let phdrs_addr = phdrs[i].p_vaddr + (new_phoff - phdrs[i].p_offset);
+                for phdr in new_phdrs.iter_mut().filter(|p| p.p_type == PT_PHDR) {
+                    phdr.p_offset = new_phoff;
+                    phdr.p_vaddr = phdrs_addr;
+                    phdr.p_paddr = phdrs_addr;
+                    phdr.p_filesz = phdrs_size;
+                    phdr.p_memsz = phdrs_size;
+                }
+                new_phoff
+            }
+            None => moved(e_phoff),
+        };
+
+        let (cut_start, cut_end, shift_usize) =
+            (cut_start as usize, cut_end as usize, shift as usize);
+        self.data.copy_within(cut_end.., cut_end - shift_usize);
+        // what is left of the cut is padding, not part of any section or segment, except the program headers
+        self.data[cut_start..cut_end - shift_usize].fill(0);
+
+        for (i, phdr) in new_phdrs.iter().enumerate() {
+            self.write_phdr(e_phoff + i as u64 * e_phentsize, phdr);
+        }
+        let e_shoff = moved(e_shoff);
+        for (i, shdr) in shdrs.iter().enumerate() {
+            let at = e_shoff + i as u64 * e_shentsize;
+            let sh_size = if i == index { size } else { shdr.sh_size };
+            self.write_word(at + 8 + 2 * word, moved(shdr.sh_offset));
+            self.write_word(at + 8 + 3 * word, sh_size);
+        }
+        let (phoff_at, shoff_at, phnum_at) = if self.is_64bit {
+            (0x20, 0x28, 0x38)
+        } else {
+            (0x1c, 0x20, 0x2c)
+        };
+        self.write_word(phoff_at, e_phoff);
+        self.write_word(shoff_at, e_shoff);
+        self.write_u16(phnum_at, new_phdrs.len() as u16);
+        Ok(shift)
+    }
+
+    /// Point the references to moved data at its new address, e.g., to fatbin regions moved within .nv_fatbin
+    ///
+    /// `moves` maps old addresses to new ones. The addends of relative relocations and the pointers they apply
+    /// to, the data pointers of the __fatbinwrap_* structs in .nvFatBinSegment, and the values of symbols are
+    /// updated. Returns the number of references updated.
+    pub fn move_pointers(&mut self, moves: &HashMap<u64, u64>) -> Result<usize, ElfError> {
+        if moves.is_empty() {
+            return Ok(0);
+        }
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(self.data)?;
+        let (shdrs, strtab) = elf.section_headers_with_strtab()?;
+        let (Some(shdrs), Some(strtab)) = (shdrs, strtab) else {
+            return Ok(0);
+        };
+        let loads: Vec<ProgramHeader> = elf
+            .segments()
+            .map(|segments| segments.iter().filter(|p| p.p_type == PT_LOAD).collect())
+            .unwrap_or_default();
+        let to_offset = |addr: u64| {
+            loads
+                .iter()
+                .find(|p| addr >= p.p_vaddr && addr - p.p_vaddr < p.p_filesz)
+                .map(|p| p.p_offset + (addr - p.p_vaddr))
+        };
+        let word = if self.is_64bit { 8 } else { 4 };
+
+        // the file offsets of the words to write with their new values
+        let mut writes = vec![];
+        let move_pointer_at = |writes: &mut Vec<(u64, u64)>, offset: u64| {
+            if let Some(&new) = self.read_word(offset).and_then(|value| moves.get(&value)) {
+                writes.push((offset, new));
+            }
+        };
+        for shdr in shdrs.iter() {
+            match shdr.sh_type {
+                SHT_RELA => {
+                    for (i, rela) in elf.section_data_as_relas(&shdr)?.enumerate() {
+                        let Some(&new) = moves.get(&(rela.r_addend as u64)) else {
+                            continue;
+                        };
+                        if rela.r_sym != 0 {
+                            continue;
+                        }
+                        writes.push((shdr.sh_offset + i as u64 * shdr.sh_entsize + 2 * word, new));
+                        if let Some(offset) = to_offset(rela.r_offset) {
+                            move_pointer_at(&mut writes, offset);
+                        }
+                    }
+                }
+                SHT_SYMTAB | SHT_DYNSYM => {
+                    let value_at = if self.is_64bit { 8 } else { 4 };
+                    for i in 0..shdr.sh_size / shdr.sh_entsize.max(1) {
+                        move_pointer_at(
+                            &mut writes,
+                            shdr.sh_offset + i * shdr.sh_entsize + value_at,
+                        );
+                    }
+                }
+                _ if strtab.get(shdr.sh_name as usize).ok() == Some(".nvFatBinSegment") => {
+                    // a wrapper is { u32 magic, u32 version, data pointer, filename or prelinked fatbins pointer }
+                    let wrapper_size = 8 + 2 * word;
+                    for i in 0..shdr.sh_size / wrapper_size {
+                        let at = shdr.sh_offset + i * wrapper_size;
+                        if self.read_u32(at) == Some(FATBIN_WRAPPER_MAGIC) {
+                            move_pointer_at(&mut writes, at + 8);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        writes.sort_unstable();
+        writes.dedup();
+        for &(offset, value) in writes.iter() {
+            self.write_word(offset, value);
+        }
+        Ok(writes.len())
+    }
+
+    // read a word of the file class, None past the end of the file
+    fn read_word(&self, offset: u64) -> Option<u64> {
+        let offset = usize::try_from(offset).ok()?;
+        if !self.is_64bit {
+            return self.read_u32(offset as u64).map(u64::from);
+        }
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(8)?)?
+            .try_into()
+            .unwrap();
+        Some(if self.is_big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, offset: u64) -> Option<u32> {
+        let offset = usize::try_from(offset).ok()?;
+        let bytes = self
+            .data
+            .get(offset..offset.checked_add(4)?)?
+            .try_into()
+            .unwrap();
+        Some(if self.is_big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn write_phdr(&mut self, offset: u64, phdr: &ProgramHeader) {
+        self.write_u32(offset, phdr.p_type);
+        if self.is_64bit {
+            self.write_u32(offset + 4, phdr.p_flags);
+            let fields = [
+                phdr.p_offset,
+                phdr.p_vaddr,
+                phdr.p_paddr,
+                phdr.p_filesz,
+                phdr.p_memsz,
+                phdr.p_align,
+            ];
+            for (i, field) in fields.into_iter().enumerate() {
+                self.write_word(offset + 8 + 8 * i as u64, field);
+            }
+        } else {
+            let fields = [
+                phdr.p_offset,
+                phdr.p_vaddr,
+                phdr.p_paddr,
+                phdr.p_filesz,
+                phdr.p_memsz,
+            ];
+            for (i, field) in fields.into_iter().enumerate() {
+                self.write_word(offset + 4 + 4 * i as u64, field);
+            }
+            self.write_u32(offset + 24, phdr.p_flags);
+            self.write_word(offset + 28, phdr.p_align);
+        }
+    }
+
+    // write a word of the file class, i.e., 8 bytes for ELFCLASS64 and 4 bytes for ELFCLASS32
+    fn write_word(&mut self, offset: u64, value: u64) {
+        let offset = offset as usize;
+        match (self.is_64bit, self.is_big_endian) {
+            (true, true) => self.data[offset..offset + 8].copy_from_slice(&value.to_be_bytes()),
+            (true, false) => self.data[offset..offset + 8].copy_from_slice(&value.to_le_bytes()),
+            (false, _) => self.write_u32(offset as u64, value as u32),
+        }
+    }
+
+    fn write_u32(&mut self, offset: u64, value: u32) {
+        let bytes = if self.is_big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.data[offset as usize..offset as usize + 4].copy_from_slice(&bytes);
+    }
+
+    fn write_u16(&mut self, offset: u64, value: u16) {
+        let bytes = if self.is_big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.data[offset as usize..offset as usize + 2].copy_from_slice(&bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::elf::Elf;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn shrink(name: &str, section: &str, size: u64) -> Result<(Vec<u8>, u64), ElfError> {
+        let mut data = std::fs::read(fixture(name)).unwrap();
+        let saved = SectionRewriter::new(&mut data)?.shrink_section(section, size)?;
+        data.truncate(data.len() - saved as usize);
+        Ok((data, saved))
+    }
+
+    fn segments(data: &[u8]) -> Vec<ProgramHeader> {
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(data).unwrap();
+        elf.segments().unwrap().iter().collect()
+    }
+
+    #[test]
+    fn test_shrink_last_segment() {
+        let original = std::fs::read(fixture("libbloated.so")).unwrap();
+        // keep the first of the eight fatbins, .nv_fatbin is the last loaded content of the file
+        let (data, saved) = shrink("libbloated.so", ".nv_fatbin", 0x770).unwrap();
+        assert_eq!(saved, 0x3410);
+        assert_eq!(data.len(), original.len() - 0x3410);
+
+        let elf = Elf::new(&data).unwrap();
+        assert_eq!(elf.get_section_offset(".nv_fatbin").unwrap(), Some(0x4d0));
+        assert_eq!(elf.get_section_size(".nv_fatbin").unwrap(), Some(0x770));
+        assert_eq!(elf.find_fatbins().unwrap().len(), 1);
+
+        // only the file size of the fatbin segment changes
+        let (before, after) = (segments(&original), segments(&data));
+        assert_eq!(after[2].p_filesz, 0x770);
+        assert_eq!(after[2].p_memsz, before[2].p_memsz);
+        assert_eq!(
+            after[..2].iter().chain(&after[3..]).collect::<Vec<_>>(),
+            before[..2].iter().chain(&before[3..]).collect::<Vec<_>>()
+        );
+        let parse = |data| ElfBytes::<AnyEndian>::minimal_parse(data).unwrap().ehdr;
+        let (ehdr_before, ehdr_after) = (parse(&original), parse(&data));
+        assert_eq!(ehdr_after.e_phoff, ehdr_before.e_phoff);
+        assert_eq!(ehdr_after.e_shoff, ehdr_before.e_shoff - 0x3410);
+        // the loaded content after the program headers is untouched
+        assert!(data[0x158..0x4d0 + 0x770] == original[0x158..0x4d0 + 0x770]);
+
+        // the dynamic loader still finds everything, and the moved symbol table is intact
+        assert_eq!(elf.get_soname().unwrap().as_deref(), Some("libbloated.so"));
+        assert_eq!(
+            elf.get_symbol_addr(b"fixture_counter").unwrap(),
+            Some(0x1440)
+        );
+        assert_eq!(elf.get_symbol_offset(b"fixture_add").unwrap(), Some(0x410));
+        assert_eq!(elf.get_build_id().unwrap().unwrap()[0], 0x10);
+        let (symtab, _) = ElfBytes::<AnyEndian>::minimal_parse(&data)
+            .unwrap()
+            .symbol_table()
+            .unwrap()
+            .unwrap();
+        assert_eq!(symtab.len(), 7);
+    }
+
+    #[test]
+    fn test_shrink_split_segment() {
+        let original = std::fs::read(fixture("libdemo.so")).unwrap();
+        // keep the first region, nvcc puts __nv_module_id, .eh_frame_hdr, .eh_frame and .gcc_except_table after it
+        let (data, saved) = shrink("libdemo.so", ".nv_fatbin", 0x10).unwrap();
+        assert_eq!(saved, 0x6000);
+        assert_eq!(data.len(), original.len() - 0x6000);
+
+        // the segment is split at the cut, its first part ends with the program headers, one more of them
+        let (before, after) = (segments(&original), segments(&data));
+        assert_eq!(after.len(), before.len() + 1);
+        let ehdr = ElfBytes::<AnyEndian>::minimal_parse(&data).unwrap().ehdr;
+        assert_eq!(ehdr.e_phoff, 0x948e0);
+        assert_eq!(ehdr.e_phnum as usize, after.len());
+        let (head, tail) = (after[2], after[3]);
+        assert_eq!((head.p_offset, head.p_vaddr), (0x85000, 0x85000));
+        assert_eq!(head.p_filesz, 0x948e0 + 11 * 0x38 - 0x85000);
+        assert_eq!(head.p_memsz, 0x9acb0 - 0x85000);
+        assert_eq!((tail.p_offset, tail.p_vaddr), (0x94cb0, 0x9acb0));
+        assert_eq!(tail.p_memsz, before[2].p_memsz - head.p_memsz);
+        assert!(
+            (head.p_vaddr + head.p_filesz).next_multiple_of(0x1000)
+                <= tail.p_vaddr / 0x1000 * 0x1000
+        );
+        // the later segments move by whole pages and keep their addresses
+        for (b, a) in before[3..].iter().zip(&after[4..]) {
+            assert_eq!(a.p_vaddr, b.p_vaddr);
+            let moved = if b.p_offset >= 0x9acb0 { 0x6000 } else { 0 };
+            assert_eq!(a.p_offset, b.p_offset - moved);
+            assert_eq!((a.p_filesz, a.p_memsz), (b.p_filesz, b.p_memsz));
+        }
+        for load in after.iter().filter(|p| p.p_type == PT_LOAD) {
+            assert_eq!(load.p_offset % load.p_align, load.p_vaddr % load.p_align);
+        }
+
+        // every loaded section but the shrunk one reads the same at its address
+        let (elf_before, elf) = (Elf::new(&original).unwrap(), Elf::new(&data).unwrap());
+        let parsed = ElfBytes::<AnyEndian>::minimal_parse(&original).unwrap();
+        let (shdrs, strtab) = parsed.section_headers_with_strtab().unwrap();
+        let (shdrs, strtab) = (shdrs.unwrap(), strtab.unwrap());
+        let mut compared = 0;
+        for shdr in shdrs.iter() {
+            let name = strtab.get(shdr.sh_name as usize).unwrap();
+            if shdr.sh_addr == 0 || shdr.sh_type == SHT_NOBITS || name == ".nv_fatbin" {
+                continue;
+            }
+            let (start, size) = (shdr.sh_offset as usize, shdr.sh_size as usize);
+            let at = elf.addr_to_offset(shdr.sh_addr).unwrap() as usize;
+            assert!(
+                data[at..at + size] == original[start..start + size],
+                "{}",
+                name
+            );
+            assert_eq!(elf.get_section_offset(name).unwrap(), Some(at as u64));
+            compared += 1;
+        }
+        assert_eq!(compared, 26);
+        assert_eq!(elf.get_section_size(".nv_fatbin").unwrap(), Some(0x10));
+        assert_eq!(elf.get_soname().unwrap(), elf_before.get_soname().unwrap());
+        assert_eq!(
+            elf.get_build_id().unwrap(),
+            elf_before.get_build_id().unwrap()
+        );
+        let symbols = |data| {
+            let (symtab, _) = ElfBytes::<AnyEndian>::minimal_parse(data)
+                .unwrap()
+                .symbol_table()
+                .unwrap()
+                .unwrap();
+            symtab.len()
+        };
+        assert_eq!(symbols(&data), symbols(&original));
+    }
+
+    #[test]
+    fn test_move_pointers() {
+        // the second region of libdemo.so, pointed at by a wrapper through a relative relocation and a symbol
+        let mut data = std::fs::read(fixture("libdemo.so")).unwrap();
+        let mut rewriter = SectionRewriter::new(&mut data).unwrap();
+        let moves = HashMap::from([(0x95040, 0x94c90)]);
+        // the relocation addend, the pointer it applies to and the symbol
+        assert_eq!(rewriter.move_pointers(&moves).unwrap(), 3);
+        assert_eq!(rewriter.move_pointers(&HashMap::new()).unwrap(), 0);
+        let parsed = ElfBytes::<AnyEndian>::minimal_parse(&data).unwrap();
+        let (symtab, _) = parsed.symbol_table().unwrap().unwrap();
+        let values: Vec<u64> = symtab.iter().map(|s| s.st_value).collect();
+        assert!(values.contains(&0x94c90) && !values.contains(&0x95040));
+        // the data pointer of the second wrapper
+        let elf = Elf::new(&data).unwrap();
+        let pointer = elf.addr_to_offset(0xb5578).unwrap() as usize;
+        assert_eq!(data[pointer..pointer + 8], 0x94c90u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_shrink_all_classes() {
+        // the non-loaded content at the end of the file moves by its alignment, a word for the section headers
+        for name in [
+            "libsynth.64le.so",
+            "libsynth.32le.so",
+            "libsynth.64be.so",
+            "libsynth.32be.so",
+        ] {
+            let original = std::fs::read(fixture(name)).unwrap();
+            let (data, saved) = shrink(name, ".strtab", 1).unwrap();
+            assert_eq!(saved, 0x80, "{}", name);
+            assert_eq!(data.len(), original.len() - 0x80);
+            assert_eq!(segments(&data), segments(&original));
+            let elf = Elf::new(&data).unwrap();
+            assert_eq!(elf.get_section_size(".strtab").unwrap(), Some(1));
+            assert_eq!(elf.get_soname().unwrap().as_deref(), Some(name));
+            assert_eq!(elf.find_fatbins().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_shrink_errors() {
+        let original = std::fs::read(fixture("libsynth.64le.so")).unwrap();
+        // .text is followed by .nv_fatbin and .nv_fatbin by the RW segment, moving them would not save a page
+        let (data, saved) = shrink("libsynth.64le.so", ".text", 0x10).unwrap();
+        assert_eq!(saved, 0);
+        assert_eq!(segments(&data), segments(&original));
+        let (data, saved) = shrink("libsynth.64le.so", ".nv_fatbin", 0).unwrap();
+        assert_eq!(saved, 0);
+        let (before, after) = (segments(&original), segments(&data));
+        assert_eq!(after[0].p_filesz, before[0].p_filesz - 0x770);
+        assert_eq!(after[1..], before[1..]);
+        assert_eq!(
+            Elf::new(&data)
+                .unwrap()
+                .get_section_size(".nv_fatbin")
+                .unwrap(),
+            Some(0)
+        );
+        // the program headers cannot go into the RW segment, mapped one page above its offset
+        assert!(matches!(
+            shrink("libsynth.64le.so", ".data", 0),
+            Err(ElfError::Unsupported(_))
+        ));
+        assert!(matches!(
+            shrink("libsynth.64le.so", ".nv_relfatbin", 0),
+            Err(ElfError::MissingSection(_))
+        ));
+        // growing and NOBITS sections are left alone
+        let (data, saved) = shrink("libsynth.64le.so", ".bss", 0).unwrap();
+        assert_eq!(saved, 0);
+        assert_eq!(data, std::fs::read(fixture("libsynth.64le.so")).unwrap());
+        assert_eq!(
+            shrink("libsynth.64le.so", ".nv_fatbin", 0x1000).unwrap().1,
+            0
+        );
+    }
+}
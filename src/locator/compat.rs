@@ -0,0 +1,231 @@
+use super::gpu_code::ElementKind;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// How far code compiled for an architecture is portable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    /// e.g., sm_80, SASS runs on the later GPUs of the same major version, PTX on every later GPU
+    Generic,
+    /// e.g., sm_100f, SASS and PTX run on the later GPUs of the same family, i.e., major version
+    Family,
+    /// e.g., sm_90a, uses architecture-specific features and runs on exactly that GPU
+    ArchSpecific,
+}
+
+/// The architecture code is compiled for, i.e., its compute capability and variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arch {
+    /// e.g., 90 for sm_90, 100 for sm_100
+    pub capability: u32,
+    pub variant: Variant,
+}
+
+impl Arch {
+    /// An architecture without variant
+    pub fn generic(capability: u32) -> Arch {
+        Arch {
+            capability,
+            variant: Variant::Generic,
+        }
+    }
+
+    /// Parse an architecture name, e.g., sm_90a, compute_100f or 86
+    pub fn parse(name: &str) -> Option<Arch> {
+        let name = name
+            .strip_prefix("sm_")
+            .or_else(|| name.strip_prefix("compute_"))
+            .unwrap_or(name);
+        let (digits, variant) = match name.as_bytes().last()? {
+            b'a' => (&name[..name.len() - 1], Variant::ArchSpecific),
+            b'f' => (&name[..name.len() - 1], Variant::Family),
+            _ => (name, Variant::Generic),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(Arch {
+            capability: digits.parse().ok()?,
+            variant,
+        })
+    }
+
+    // compute capabilities are major * 10 + minor, e.g., 86 for 8.6 and 103 for 10.3
+    fn major(capability: u32) -> u32 {
+        capability / 10
+    }
+
+    /// Check if SASS of this architecture runs on a GPU of the given compute capability
+    ///
+    /// SASS is binary compatible with the later minor versions of its major version only.
+    pub fn runs_sass_on(&self, gpu_capability: u32) -> bool {
+        match self.variant {
+            Variant::ArchSpecific => self.capability == gpu_capability,
+            Variant::Generic | Variant::Family => {
+                Self::major(self.capability) == Self::major(gpu_capability)
+                    && self.capability <= gpu_capability
+            }
+        }
+    }
+
+    /// Check if PTX of this architecture can be compiled just in time for a GPU of the given compute capability
+    pub fn runs_ptx_on(&self, gpu_capability: u32) -> bool {
+        match self.variant {
+            Variant::ArchSpecific => self.capability == gpu_capability,
+            Variant::Family => {
+                Self::major(self.capability) == Self::major(gpu_capability)
+                    && self.capability <= gpu_capability
+            }
+            Variant::Generic => self.capability <= gpu_capability,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = match self.variant {
+            Variant::Generic => "",
+            Variant::Family => "f",
+            Variant::ArchSpecific => "a",
+        };
+        write!(f, "{}{}", self.capability, suffix)
+    }
+}
+
+impl Serialize for Arch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Select the element of a fatbin region the CUDA driver loads on a GPU of the given compute capability.
+///
+/// `elements` yields the kind and architecture of each element of the region. The driver prefers SASS: the
+/// compatible cubin of the highest compute capability, the most specific one among equals. Only without one
+/// it compiles the compatible PTX of the highest compute capability just in time. LTO-IR is never loaded.
+/// Returns the index of the loaded element, None if the region cannot run on the GPU.
+pub fn select_element<I>(elements: I, gpu_capability: u32) -> Option<usize>
+where
+    I: IntoIterator<Item = (ElementKind, Arch)>,
+{
+    let mut cubin: Option<(usize, Arch)> = None;
+    let mut ptx: Option<(usize, Arch)> = None;
+    for (index, (kind, arch)) in elements.into_iter().enumerate() {
+        let best = match kind {
+            ElementKind::Cubin if arch.runs_sass_on(gpu_capability) => &mut cubin,
+            ElementKind::Ptx if arch.runs_ptx_on(gpu_capability) => &mut ptx,
+            _ => continue,
+        };
+        // the first of equal elements wins
+        let better =
+            best.is_none_or(|(_, b)| (arch.capability, arch.variant) > (b.capability, b.variant));
+        if better {
+            *best = Some((index, arch));
+        }
+    }
+    cubin.or(ptx).map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arch(name: &str) -> Arch {
+        Arch::parse(name).unwrap()
+    }
+
+    #[test]
+    fn test_parse_arch() {
+        assert_eq!(arch("sm_80"), Arch::generic(80));
+        assert_eq!(arch("86"), Arch::generic(86));
+        assert_eq!(
+            arch("sm_90a"),
+            Arch {
+                capability: 90,
+                variant: Variant::ArchSpecific
+            }
+        );
+        assert_eq!(
+            arch("compute_100f"),
+            Arch {
+                capability: 100,
+                variant: Variant::Family
+            }
+        );
+        for name in ["", "sm_", "sm_a", "compute_9x", "sm_90b"] {
+            assert_eq!(Arch::parse(name), None, "{}", name);
+        }
+        assert_eq!(arch("sm_90a").to_string(), "90a");
+        assert_eq!(serde_json::json!(arch("sm_120")), serde_json::json!("120"));
+    }
+
+    #[test]
+    fn test_compatibility() {
+        // SASS stays within its major version
+        assert!(arch("sm_80").runs_sass_on(86));
+        assert!(!arch("sm_86").runs_sass_on(80));
+        assert!(!arch("sm_75").runs_sass_on(80));
+        assert!(!arch("sm_90").runs_sass_on(100));
+        assert!(arch("sm_100").runs_sass_on(103));
+        // arch-specific code runs on exactly its GPU, family code on its family
+        assert!(arch("sm_90a").runs_sass_on(90));
+        assert!(!arch("sm_100a").runs_sass_on(103));
+        assert!(arch("sm_100f").runs_sass_on(103));
+        assert!(!arch("sm_103f").runs_sass_on(100));
+
+        // PTX is compiled for any later GPU, unless it is specific
+        assert!(arch("compute_70").runs_ptx_on(90));
+        assert!(!arch("compute_90").runs_ptx_on(86));
+        assert!(!arch("compute_90a").runs_ptx_on(100));
+        assert!(arch("compute_100f").runs_ptx_on(103));
+        assert!(!arch("compute_100f").runs_ptx_on(120));
+    }
+
+    #[test]
+    fn test_select_element() {
+        use ElementKind::{Cubin, LtoIr, Ptx};
+        let region = [
+            (Cubin, arch("sm_70")),
+            (Cubin, arch("sm_75")),
+            (Cubin, arch("sm_80")),
+            (Cubin, arch("sm_86")),
+            (Cubin, arch("sm_90")),
+            (Cubin, arch("sm_90a")),
+            (Ptx, arch("compute_90")),
+            (LtoIr, arch("sm_90")),
+        ];
+        for (gpu, expected) in [
+            (70, Some(0)),
+            (72, Some(0)),
+            (75, Some(1)),
+            // the sm_75 cubin cannot run on 8.0
+            (80, Some(2)),
+            (89, Some(3)),
+            // the arch-specific cubin is preferred on its GPU
+            (90, Some(5)),
+            // no SASS runs on Blackwell, the PTX is compiled just in time
+            (100, Some(6)),
+            (120, Some(6)),
+            (60, None),
+        ] {
+            assert_eq!(select_element(region, gpu), expected, "{}", gpu);
+        }
+
+        // without PTX nothing runs on a later major version
+        assert_eq!(select_element(region[..6].to_vec(), 100), None);
+        // the newest compatible PTX is compiled, arch-specific PTX only for its GPU
+        let region = [
+            (Ptx, arch("compute_70")),
+            (Ptx, arch("compute_80")),
+            (Ptx, arch("compute_90a")),
+        ];
+        assert_eq!(select_element(region, 86), Some(1));
+        assert_eq!(select_element(region, 90), Some(2));
+        assert_eq!(select_element(region, 100), Some(1));
+        // duplicates load the first one
+        assert_eq!(
+            select_element([(Cubin, arch("sm_70")), (Cubin, arch("sm_70"))], 75),
+            Some(0)
+        );
+    }
+}
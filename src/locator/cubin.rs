@@ -0,0 +1,204 @@
+use super::compat::{Arch, Variant};
+use crate::elf::elf::{Elf, ElfError};
+use std::collections::HashSet;
+
+/// Symbol of the libcublas internal constants, listed as a kernel of the cubin defining it
+pub const CUBLAS_INTERNAL_CONSTANT: &str = "_ZN6cublas8internal15deviceConstantsE";
+
+// the ELF ABI versions of cubins, e_flags moved with the second one
+const ABI_VERSION_V1: u8 = 7;
+const ABI_VERSION_V2: u8 = 8;
+// e_flags of ABI version 1: the SM in the low byte, arch-specific targets like sm_90a flagged
+const EF_CUDA_SM_V1: u32 = 0xff;
+const EF_CUDA_ACCELERATORS_V1: u32 = 0x800;
+// e_flags of ABI version 2: the SM in the second byte
+const EF_CUDA_SM_V2: u32 = 0xff00;
+const EF_CUDA_ACCELERATORS_V2: u32 = 0x8;
+// .nv.callgraph entries with a callee from this one up are markers, not calls
+const CALLGRAPH_MARKER_MIN: u32 = 0xfffffffc;
+
+/// A cubin, i.e., the SASS of one compilation unit for one architecture, parsed in-process
+///
+/// No CUDA toolkit is needed on the analysis host.
+pub struct Cubin {
+    /// The architecture the SASS is compiled for, from the ELF header
+    pub arch: Option<Arch>,
+    /// The mangled names of the kernels, i.e., of its .text.<kernel> sections
+    pub kernels: HashSet<String>,
+    /// The device calls and references between its functions, as (caller, callee) mangled names
+    ///
+    /// Callees may be defined in other cubins, e.g., with relocatable device code.
+    pub calls: Vec<(String, String)>,
+}
+
+impl Cubin {
+    /// Parse the architecture, kernels and device calls of a cubin
+    pub fn parse(cubin_data: &[u8]) -> Result<Cubin, ElfError> {
+        let elf = Elf::new(cubin_data)?;
+        let mut kernels: HashSet<String> = elf
+            .get_section_names()?
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(b".text."))
+            .map(|kernel| String::from_utf8_lossy(kernel).into_owned())
+            .collect();
+
+        // workaround for libcublas internal constants, the cubin defining them is used without any of its kernels
+        if elf
+            .get_symbol_addr(CUBLAS_INTERNAL_CONSTANT.as_bytes())?
+            .is_some()
+        {
+            kernels.insert(CUBLAS_INTERNAL_CONSTANT.to_string());
+        }
+
+        Ok(Cubin {
+            arch: cubin_arch(elf.abi_version(), elf.flags()),
+            kernels,
+            calls: cubin_calls(&elf)?,
+        })
+    }
+}
+
+// read the device calls of a cubin from .nv.callgraph, and the references of each function from the relocations
+// of its .text.<function> section, e.g., the kernel launched through dynamic parallelism or a function pointer
+fn cubin_calls(elf: &Elf) -> Result<Vec<(String, String)>, ElfError> {
+    let names = elf.get_debug_symbol_names()?;
+    let name = |index: u32| {
+        names
+            .get(index as usize)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    };
+
+    let mut calls = vec![];
+    // .nv.callgraph is a list of (caller, callee) symbol indices, as little endian u32 pairs
+    let callgraph = elf.get_section_data(".nv.callgraph")?.unwrap_or_default();
+    for entry in callgraph.chunks_exact(8) {
+        let caller = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+        let callee = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+        if callee >= CALLGRAPH_MARKER_MIN {
+            continue;
+        }
+        if let (Some(caller), Some(callee)) = (name(caller), name(callee)) {
+            calls.push((caller, callee));
+        }
+    }
+    for (section, symbol) in elf.get_symbol_relocations()? {
+        let Some(caller) = section.strip_prefix(b".text.") else {
+            continue;
+        };
+        let caller = String::from_utf8_lossy(caller).into_owned();
+        if let Some(callee) = name(symbol).filter(|callee| *callee != caller) {
+            calls.push((caller, callee));
+        }
+    }
+    Ok(calls)
+}
+
+// decode the target architecture from the e_flags of a cubin, None for unknown ABI versions
+fn cubin_arch(abi_version: u8, flags: u32) -> Option<Arch> {
+    let (sm, arch_specific) = match abi_version {
+        ABI_VERSION_V1 => (flags & EF_CUDA_SM_V1, flags & EF_CUDA_ACCELERATORS_V1 != 0),
+        ABI_VERSION_V2 => (
+            (flags & EF_CUDA_SM_V2) >> 8,
+            flags & EF_CUDA_ACCELERATORS_V2 != 0,
+        ),
+        _ => return None,
+    };
+    if sm == 0 {
+        return None;
+    }
+    Some(Arch {
+        capability: sm,
+        variant: if arch_specific {
+            Variant::ArchSpecific
+        } else {
+            Variant::Generic
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_cubin_kernels() {
+        let _ = env_logger::try_init();
+        for (name, capability) in [("libdemo.3.sm_70.cubin", 70), ("libdemo.4.sm_75.cubin", 75)] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let cubin = Cubin::parse(&data).unwrap();
+            assert_eq!(cubin.arch, Some(Arch::generic(capability)), "{}", name);
+            assert_eq!(cubin.kernels.len(), 2, "{}", name);
+            assert!(cubin.kernels.contains("_Z12matrixMulGPUPiS_S_iii"));
+            assert!(cubin.kernels.contains("_Z16setScalarItemGPUiPiii"));
+        }
+
+        // the cubins of the device link step have no kernels
+        for name in ["libdemo.1.sm_70.cubin", "libdemo.2.sm_75.cubin"] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            assert!(Cubin::parse(&data).unwrap().kernels.is_empty(), "{}", name);
+        }
+
+        // a host library has a .text section, but no .text.<kernel> ones
+        let data = std::fs::read(fixture("libsynth.64le.so")).unwrap();
+        let cubin = Cubin::parse(&data).unwrap();
+        assert!(cubin.kernels.is_empty());
+        assert_eq!(cubin.arch, None);
+        assert!(Cubin::parse(b"not a cubin").is_err());
+    }
+
+    #[test]
+    fn test_cubin_calls() {
+        let _ = env_logger::try_init();
+        let data = std::fs::read(fixture("calls.sm_70.cubin")).unwrap();
+        let cubin = Cubin::parse(&data).unwrap();
+        assert_eq!(cubin.arch, Some(Arch::generic(70)));
+        assert_eq!(cubin.kernels.len(), 4);
+        assert_eq!(
+            cubin.calls,
+            vec![
+                ("_Z5childv".to_string(), "_Z6squarei".to_string()),
+                ("_Z6launchv".to_string(), "_Z5childv".to_string()),
+                (
+                    "_Z6launchv".to_string(),
+                    "_Z16setScalarItemGPUiPiii".to_string()
+                ),
+            ]
+        );
+
+        // the callgraph of nvcc cubins without device calls has only markers, the only relocations are of .debug_frame
+        let data = std::fs::read(fixture("libdemo.3.sm_70.cubin")).unwrap();
+        assert!(Cubin::parse(&data).unwrap().calls.is_empty());
+    }
+
+    #[test]
+    fn test_cubin_arch() {
+        // sm_75 with virtual arch 75, as nvcc writes it
+        assert_eq!(cubin_arch(7, 0x4b054b), Some(Arch::generic(75)));
+        assert_eq!(
+            cubin_arch(7, 0x5a0d5a),
+            Some(Arch {
+                capability: 90,
+                variant: Variant::ArchSpecific
+            })
+        );
+        assert_eq!(
+            cubin_arch(8, 0x6408),
+            Some(Arch {
+                capability: 100,
+                variant: Variant::ArchSpecific
+            })
+        );
+        assert_eq!(cubin_arch(8, 0x7800), Some(Arch::generic(120)));
+        assert_eq!(cubin_arch(0, 0x4b054b), None);
+        assert_eq!(cubin_arch(7, 0), None);
+    }
+}
@@ -0,0 +1,378 @@
+use ruzstd::decoding::StreamingDecoder;
+use std::fmt;
+use std::io::Read;
+
+/// Magic of a zstd frame, as written by the zstd compression mode of recent CUDA releases
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+// the length of a match is stored minus this minimum
+const LZ4_MIN_MATCH: usize = 4;
+// the most an LZ4 payload may claim to grow when decompressed, LZ4 blocks grow 255 times at most,
+// larger claims come from corrupt headers; zstd frames record their own size instead
+const MAX_LZ4_COMPRESSION_RATIO: usize = 1024;
+// the Frame_Content_Size flag and Single_Segment_flag of the zstd frame header descriptor
+const ZSTD_FCS_FLAG_SHIFT: u8 = 6;
+const ZSTD_SINGLE_SEGMENT_FLAG: u8 = 0x20;
+
+/// Errors returned while decompressing a fatbin element payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The compressed data ends in the middle of a sequence
+    Truncated,
+    /// A match refers to data before the start of the output, at the given output position
+    BadOffset { offset: usize, position: usize },
+    /// The data decompresses to another size than the element header tells
+    SizeMismatch { expected: usize, actual: usize },
+    /// The element header claims more decompressed bytes than the LZ4 compressed data can hold
+    TooLarge { size: usize, compressed_size: usize },
+    /// The zstd frame is malformed
+    Zstd(String),
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressError::Truncated => write!(f, "truncated compressed data"),
+            DecompressError::BadOffset { offset, position } => {
+                write!(f, "match offset {} out of output at {}", offset, position)
+            }
+            DecompressError::SizeMismatch { expected, actual } => write!(
+                f,
+                "decompressed to {} bytes instead of {}",
+                actual, expected
+            ),
+            DecompressError::TooLarge {
+                size,
+                compressed_size,
+            } => write!(
+                f,
+                "{} bytes claimed from {} compressed bytes",
+                size, compressed_size
+            ),
+            DecompressError::Zstd(e) => write!(f, "bad zstd frame: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {}
+
+/// Decompress a compressed element payload to exactly `uncompressed_size` bytes.
+///
+/// zstd frames are recognized by their magic, everything else is taken as NVIDIA's LZ scheme.
+pub fn decompress(input: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, DecompressError> {
+    if input.starts_with(&ZSTD_MAGIC) {
+        zstd_decompress(input, uncompressed_size)
+    } else {
+        lz4_decompress(input, uncompressed_size)
+    }
+}
+
+/// Decompress data in NVIDIA's LZ scheme, which is the LZ4 block format.
+///
+/// Each sequence is a token, whose high nibble is the literal length and low nibble the match length
+/// minus 4, the literals, a 2-byte little-endian match offset and the extra length bytes of the match.
+/// Lengths of 15 continue in the following bytes, as long as they are 255.
+/// The last sequence only has literals, decompression also stops once the output is complete.
+pub fn lz4_decompress(input: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, DecompressError> {
+    if uncompressed_size > input.len().saturating_mul(MAX_LZ4_COMPRESSION_RATIO) {
+        return Err(DecompressError::TooLarge {
+            size: uncompressed_size,
+            compressed_size: input.len(),
+        });
+    }
+    // grown as it is produced, the header size is only trusted up to the limit
+    let mut output = vec![];
+    let too_long = |actual| DecompressError::SizeMismatch {
+        expected: uncompressed_size,
+        actual,
+    };
+    let mut i = 0;
+    while i < input.len() {
+        let token = input[i];
+        i += 1;
+
+        let literal_size = lz4_length(input, &mut i, (token >> 4) as usize)?;
+        let literals = input
+            .get(i..i.saturating_add(literal_size))
+            .ok_or(DecompressError::Truncated)?;
+        if output.len() + literal_size > uncompressed_size {
+            return Err(too_long(output.len() + literal_size));
+        }
+        output.extend_from_slice(literals);
+        i += literal_size;
+        if i == input.len() || output.len() == uncompressed_size {
+            break;
+        }
+
+        let offset = input
+            .get(i..i + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or(DecompressError::Truncated)?;
+        i += 2;
+        if offset == 0 || offset > output.len() {
+            return Err(DecompressError::BadOffset {
+                offset,
+                position: output.len(),
+            });
+        }
+        let match_size = lz4_length(input, &mut i, (token & 0xf) as usize)? + LZ4_MIN_MATCH;
+        if output.len() + match_size > uncompressed_size {
+            return Err(too_long(output.len() + match_size));
+        }
+        // the match may overlap the bytes it produces, e.g., a run of one byte has offset 1
+        let start = output.len() - offset;
+        for k in start..start + match_size {
+            output.push(output[k]);
+        }
+    }
+
+    if output.len() != uncompressed_size {
+        return Err(DecompressError::SizeMismatch {
+            expected: uncompressed_size,
+            actual: output.len(),
+        });
+    }
+    Ok(output)
+}
+
+// read a sequence length that starts with the given token nibble
+fn lz4_length(input: &[u8], i: &mut usize, nibble: usize) -> Result<usize, DecompressError> {
+    let mut length = nibble;
+    if nibble == 0xf {
+        loop {
+            let byte = *input.get(*i).ok_or(DecompressError::Truncated)?;
+            *i += 1;
+            length += byte as usize;
+            if byte != 0xff {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
+
+/// Decompress a zstd frame, the output may not grow beyond `uncompressed_size`.
+///
+/// zstd frames may grow far more than LZ4 blocks, e.g., a zero-filled constant bank in RLE blocks, so their size
+/// is not capped. A Frame_Content_Size in the frame header must match `uncompressed_size` before decoding.
+pub fn zstd_decompress(input: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut decoder =
+        StreamingDecoder::new(input).map_err(|e| DecompressError::Zstd(e.to_string()))?;
+    // the descriptor follows the magic, without the flags the frame does not record its size
+    let descriptor = input[ZSTD_MAGIC.len()];
+    if descriptor >> ZSTD_FCS_FLAG_SHIFT != 0 || descriptor & ZSTD_SINGLE_SEGMENT_FLAG != 0 {
+        let content_size = decoder.decoder.content_size();
+        if content_size != uncompressed_size as u64 {
+            return Err(DecompressError::SizeMismatch {
+                expected: uncompressed_size,
+                actual: usize::try_from(content_size).unwrap_or(usize::MAX),
+            });
+        }
+    }
+    // one byte more than expected tells a longer frame apart
+    let mut output = vec![];
+    decoder
+        .by_ref()
+        .take(uncompressed_size as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| DecompressError::Zstd(e.to_string()))?;
+    if output.len() != uncompressed_size {
+        return Err(DecompressError::SizeMismatch {
+            expected: uncompressed_size,
+            actual: output.len(),
+        });
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // a zstd frame of `blocks` RLE blocks of 128 KiB of `byte`, recording its content size
+    fn zstd_rle_frame(byte: u8, blocks: usize, content_size: u32) -> Vec<u8> {
+        const BLOCK_SIZE: u32 = 128 << 10;
+        // single segment, 4-byte Frame_Content_Size
+        let mut frame = ZSTD_MAGIC.to_vec();
+        frame.push(2 << ZSTD_FCS_FLAG_SHIFT | ZSTD_SINGLE_SEGMENT_FLAG);
+        frame.extend(content_size.to_le_bytes());
+        for i in 0..blocks {
+            let last = (i + 1 == blocks) as u32;
+            let header = BLOCK_SIZE << 3 | 1 << 1 | last;
+            frame.extend(&header.to_le_bytes()[..3]);
+            frame.push(byte);
+        }
+        frame
+    }
+
+    #[test]
+    fn test_lz4_decompress() {
+        // literals "abcd", then a match of 8 bytes at offset 4 overlapping its own output
+        let input = [0x44, b'a', b'b', b'c', b'd', 4, 0, 0x10, b'!'];
+        assert_eq!(lz4_decompress(&input, 13).unwrap(), b"abcdabcdabcd!");
+
+        // extended lengths: 21 literals ending in a zero, then a match of 299 zeros at offset 1
+        let mut input = vec![0xff, 21 - 15];
+        input.extend(b"0123456789abcdefghij\0");
+        input.extend([1, 0, 255, (299 - LZ4_MIN_MATCH - 15 - 255) as u8]);
+        let mut expected = b"0123456789abcdefghij".to_vec();
+        expected.extend([0; 300]);
+        assert_eq!(lz4_decompress(&input, expected.len()).unwrap(), expected);
+
+        // the output is complete before the input ends, e.g., padding after the last sequence
+        let input = [0x30, b'x', b'y', b'z', 0, 0, 0];
+        assert_eq!(lz4_decompress(&input, 3).unwrap(), b"xyz");
+    }
+
+    #[test]
+    fn test_lz4_decompress_errors() {
+        assert_eq!(
+            lz4_decompress(&[0x40, b'a', b'b'], 4).err().unwrap(),
+            DecompressError::Truncated
+        );
+        assert_eq!(
+            lz4_decompress(&[0x14, b'a', 2, 0], 9).err().unwrap(),
+            DecompressError::BadOffset {
+                offset: 2,
+                position: 1
+            }
+        );
+        assert_eq!(
+            lz4_decompress(&[0x14, b'a', 0, 0], 9).err().unwrap(),
+            DecompressError::BadOffset {
+                offset: 0,
+                position: 1
+            }
+        );
+        assert_eq!(
+            lz4_decompress(&[0x14, b'a', 1], 9).err().unwrap(),
+            DecompressError::Truncated
+        );
+        assert_eq!(
+            lz4_decompress(&[0xf0, 0xff], 9).err().unwrap(),
+            DecompressError::Truncated
+        );
+        // the header claims more or less than there is
+        assert_eq!(
+            lz4_decompress(&[0x14, b'a', 1, 0, 0x10, b'b'], 20)
+                .err()
+                .unwrap(),
+            DecompressError::SizeMismatch {
+                expected: 20,
+                actual: 10
+            }
+        );
+        assert_eq!(
+            lz4_decompress(&[0x14, b'a', 1, 0, 0x10, b'b'], 5)
+                .err()
+                .unwrap(),
+            DecompressError::SizeMismatch {
+                expected: 5,
+                actual: 9
+            }
+        );
+    }
+
+    #[test]
+    fn test_lz4_decompress_reference_encoder() {
+        // cubins built by nvcc, compressed by an independent LZ4 block encoder
+        for name in ["libdemo.1.sm_70.cubin", "libdemo.4.sm_75.cubin"] {
+            let cubin = std::fs::read(fixture(name)).unwrap();
+            let compressed = lz4_flex::block::compress(&cubin);
+            assert!(compressed.len() < cubin.len(), "{}", name);
+            assert_eq!(
+                decompress(&compressed, cubin.len()).unwrap(),
+                cubin,
+                "{}",
+                name
+            );
+        }
+        let zeros = vec![0; 64 << 10];
+        let compressed = lz4_flex::block::compress(&zeros);
+        assert_eq!(lz4_decompress(&compressed, zeros.len()).unwrap(), zeros);
+    }
+
+    #[test]
+    fn test_decompress_huge_size() {
+        // a corrupt header claiming 1 TiB, or more than fits in memory, of two LZ4 bytes
+        for size in [1 << 40, usize::MAX] {
+            let input = [0x10, b'a'];
+            assert_eq!(
+                decompress(&input, size).err().unwrap(),
+                DecompressError::TooLarge {
+                    size,
+                    compressed_size: input.len()
+                }
+            );
+            // a zstd frame without a header is malformed whatever its claimed size
+            assert!(matches!(
+                decompress(&ZSTD_MAGIC, size).err().unwrap(),
+                DecompressError::Zstd(_)
+            ));
+        }
+        // the largest LZ4 expansion is still accepted, a run of 255 * 4 + 19 zeros
+        let input = [0x1f, 0, 1, 0, 255, 255, 255, 255, 0];
+        assert_eq!(lz4_decompress(&input, 1040).unwrap(), vec![0; 1040]);
+    }
+
+    #[test]
+    fn test_zstd_decompress() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i % 7 * 31 + i / 512) as u8).collect();
+        let compressed = compress_to_vec(&data[..], CompressionLevel::Fastest);
+        assert!(compressed.starts_with(&ZSTD_MAGIC));
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+
+        assert_eq!(
+            decompress(&compressed, data.len() - 1).err().unwrap(),
+            DecompressError::SizeMismatch {
+                expected: data.len() - 1,
+                actual: data.len()
+            }
+        );
+        assert_eq!(
+            decompress(&compressed, data.len() + 1).err().unwrap(),
+            DecompressError::SizeMismatch {
+                expected: data.len() + 1,
+                actual: data.len()
+            }
+        );
+        assert!(matches!(
+            decompress(&compressed[..compressed.len() / 2], data.len())
+                .err()
+                .unwrap(),
+            DecompressError::Zstd(_)
+        ));
+    }
+
+    #[test]
+    fn test_zstd_decompress_rle() {
+        // 1 MiB of zeros from 41 bytes, far beyond what LZ4 can grow
+        let size = 8 * (128 << 10);
+        let frame = zstd_rle_frame(0, 8, size as u32);
+        assert_eq!(frame.len(), 41);
+        assert_eq!(decompress(&frame, size).unwrap(), vec![0; size]);
+
+        // the header disagrees with the frame, which is not decoded
+        assert_eq!(
+            decompress(&frame, size + 8).err().unwrap(),
+            DecompressError::SizeMismatch {
+                expected: size + 8,
+                actual: size
+            }
+        );
+        // the frame holds more than it records, the output stops one byte past the header size
+        let frame = zstd_rle_frame(7, 8, (size - 1) as u32);
+        assert!(decompress(&frame, size - 1).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use super::ptx::ptx_target;
+use crate::elf::elf::{Elf, FATBIN_MAGIC};
+use elf::abi::EM_CUDA;
+use std::path::Path;
+
+// extensions of the device code files searched for in directories
+const DEVICE_FILE_EXTENSIONS: [&str; 3] = ["fatbin", "cubin", "ptx"];
+
+/// The kind of a standalone device code file, e.g., of a Triton or NVRTC cache, loaded with cuModuleLoad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceFileKind {
+    /// Fatbin regions, as written by nvcc -fatbin
+    Fatbin,
+    /// A single cubin, i.e., an ELF file for EM_CUDA
+    Cubin,
+    /// PTX text
+    Ptx,
+}
+
+impl DeviceFileKind {
+    /// Recognize a device code file by its content, None for anything else, e.g., host libraries
+    pub fn detect(data: &[u8]) -> Option<DeviceFileKind> {
+        if data.starts_with(&FATBIN_MAGIC.to_le_bytes()) {
+            return Some(DeviceFileKind::Fatbin);
+        }
+        if let Ok(elf) = Elf::new(data) {
+            return (elf.machine() == EM_CUDA).then_some(DeviceFileKind::Cubin);
+        }
+        // PTX is text up to an optional NUL terminator, with a .target directive
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let is_text = data[..end]
+            .iter()
+            .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+        if is_text && ptx_target(data).is_some() {
+            return Some(DeviceFileKind::Ptx);
+        }
+        None
+    }
+}
+
+/// Find the device code files under a directory, recursively, by their .fatbin, .cubin and .ptx extensions.
+///
+/// Returns the path of each file and its path relative to the directory, sorted by path.
+/// Symbolic links are not followed, so a cache linking to itself is searched once.
+pub fn find_device_files(dir: &str) -> std::io::Result<Vec<(String, String)>> {
+    let mut files = vec![];
+    let mut pending = vec![Path::new(dir).to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_device_file = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| DEVICE_FILE_EXTENSIONS.contains(&e));
+            if file_type.is_file() && is_device_file {
+                let relative = path.strip_prefix(dir).unwrap_or(&path);
+                files.push((
+                    path.to_string_lossy().into_owned(),
+                    relative.to_string_lossy().into_owned(),
+                ));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_detect() {
+        let _ = env_logger::try_init();
+        let cubin = std::fs::read(fixture("libdemo.3.sm_70.cubin")).unwrap();
+        assert_eq!(DeviceFileKind::detect(&cubin), Some(DeviceFileKind::Cubin));
+        let so = std::fs::read(fixture("libdemo.so")).unwrap();
+        assert_eq!(DeviceFileKind::detect(&so), None);
+        // the .nv_fatbin of libdemo.so, as nvcc -fatbin writes it
+        assert_eq!(
+            DeviceFileKind::detect(&so[0x948d0..0x948d0 + 0x63e0]),
+            Some(DeviceFileKind::Fatbin)
+        );
+
+        let ptx = b"//\n// Generated by NVIDIA NVVM Compiler\n.version 8.5\n.target sm_90a\n.address_size 64\n\0\0";
+        assert_eq!(DeviceFileKind::detect(ptx), Some(DeviceFileKind::Ptx));
+        assert_eq!(DeviceFileKind::detect(b".version 8.5\n"), None);
+        assert_eq!(DeviceFileKind::detect(b"\x01.target sm_90"), None);
+        assert_eq!(DeviceFileKind::detect(b""), None);
+    }
+
+    #[test]
+    fn test_find_device_files() {
+        let _ = env_logger::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        // a Triton cache, one directory per compiled kernel
+        for path in [
+            "3f2a/matmul.cubin",
+            "3f2a/matmul.ptx",
+            "3f2a/matmul.json",
+            "9c1b/matmul.cubin",
+            "nvrtc/nested/scale.fatbin",
+            "libfoo.so",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("empty.cubin")).unwrap();
+
+        let files = find_device_files(root).unwrap();
+        let relative: Vec<&str> = files.iter().map(|(_, r)| r.as_str()).collect();
+        assert_eq!(
+            relative,
+            vec![
+                "3f2a/matmul.cubin",
+                "3f2a/matmul.ptx",
+                "9c1b/matmul.cubin",
+                "nvrtc/nested/scale.fatbin"
+            ]
+        );
+        assert_eq!(files[0].0, format!("{}/3f2a/matmul.cubin", root));
+        assert!(find_device_files(&format!("{}/missing", root)).is_err());
+    }
+}
@@ -1,5 +1,108 @@
+use crate::elf::elf::FATBIN_MAGIC;
+use crate::locator::decompress::{decompress, DecompressError};
 use log::debug;
-use std::vec;
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+
+// regions are 8-byte aligned, the gaps between them are zero-filled
+const REGION_ALIGN: u64 = 8;
+// the element header fields up to and including the capability
+const MIN_ELEMENT_HEADER_SIZE: u32 = 32;
+
+/// Errors returned while parsing the regions of a GPU code section
+///
+/// Offsets are relative to the start of the GPU code data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FatbinError {
+    /// The data ends before the region header at the offset
+    Truncated(u64),
+    /// The data at the offset does not start with the fatbin magic
+    BadMagic { offset: u64, magic: u32 },
+    /// The region at the offset has a version other than 1
+    UnsupportedVersion { offset: u64, version: u16 },
+    /// The region at the offset has a header size other than 16
+    BadHeaderSize { offset: u64, header_size: u16 },
+    /// The region at the offset claims more data than there is
+    RegionOutOfBounds { offset: u64, end: u64 },
+    /// The element at the offset has a header too small for its fields
+    BadElementHeader { offset: u64, header_size: u32 },
+    /// The element at the offset extends past the end of its region
+    ElementOutOfBounds {
+        offset: u64,
+        end: u64,
+        region_end: u64,
+    },
+    /// The payload of the element at the offset cannot be decompressed
+    BadPayload { offset: u64, error: DecompressError },
+    /// The cubin of the element at the offset is not a valid ELF file
+    BadCubin { offset: u64, error: String },
+    /// The cubin or PTX at the offset does not tell the architecture it is compiled for
+    UnknownArch(u64),
+}
+
+impl fmt::Display for FatbinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FatbinError::Truncated(offset) => {
+                write!(f, "fatbin truncated at region header {:#x}", offset)
+            }
+            FatbinError::BadMagic { offset, magic } => {
+                write!(f, "bad fatbin magic {:#x} at {:#x}", magic, offset)
+            }
+            FatbinError::UnsupportedVersion { offset, version } => {
+                write!(
+                    f,
+                    "unsupported fatbin version {} of region {:#x}",
+                    version, offset
+                )
+            }
+            FatbinError::BadHeaderSize {
+                offset,
+                header_size,
+            } => write!(
+                f,
+                "bad header size {} of fatbin region {:#x}",
+                header_size, offset
+            ),
+            FatbinError::RegionOutOfBounds { offset, end } => {
+                write!(
+                    f,
+                    "fatbin region {:#x} ends at {:#x}, out of data",
+                    offset, end
+                )
+            }
+            FatbinError::BadElementHeader {
+                offset,
+                header_size,
+            } => write!(
+                f,
+                "bad header size {} of fatbin element {:#x}",
+                header_size, offset
+            ),
+            FatbinError::ElementOutOfBounds {
+                offset,
+                end,
+                region_end,
+            } => write!(
+                f,
+                "fatbin element {:#x} ends at {:#x}, out of its region ending at {:#x}",
+                offset, end, region_end
+            ),
+            FatbinError::BadPayload { offset, error } => {
+                write!(f, "bad payload of fatbin element {:#x}: {}", offset, error)
+            }
+            FatbinError::BadCubin { offset, error } => {
+                write!(f, "bad cubin of fatbin element {:#x}: {}", offset, error)
+            }
+            FatbinError::UnknownArch(offset) => {
+                write!(f, "unknown architecture of device code at {:#x}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FatbinError {}
 
 /// Represents the GPU code section containing multiple regions.
 pub struct GPUCode {
@@ -8,93 +111,294 @@ pub struct GPUCode {
 
 impl GPUCode {
     /// Create a new GPUCode instance by parsing the provided GPU code data.
-    pub fn new(gpu_code_data: &[u8]) -> Self {
+    ///
+    /// Zero padding up to the region alignment between regions, and at the end of the data, is skipped.
+    pub fn new(gpu_code_data: &[u8]) -> Result<Self, FatbinError> {
         let mut regions = Vec::new();
         let mut offset = 0;
-        while offset < gpu_code_data.len() {
-            let region = Region::new(gpu_code_data, offset as u64);
-            offset += region.size() as usize;
+        let len = gpu_code_data.len() as u64;
+        while offset < len {
+            // a region never starts with a zero byte, skip the padding up to the next aligned offset
+            if gpu_code_data[offset as usize] == 0 {
+                let padding_end = (offset + 1).next_multiple_of(REGION_ALIGN).min(len);
+                if is_zero(&gpu_code_data[offset as usize..padding_end as usize]) {
+                    offset = padding_end;
+                    continue;
+                }
+            }
+            let region = Region::new(gpu_code_data, offset)?;
+            offset += region.size();
             regions.push(region);
         }
 
-        Self { regions }
+        Ok(Self { regions })
+    }
+
+    /// Iterate over the regions with their index and file offset.
+    ///
+    /// `gpu_code_data` is the GPU code data the regions were parsed from, `file_offset` where it starts in the file.
+    pub fn regions<'a>(
+        &'a self,
+        gpu_code_data: &'a [u8],
+        file_offset: u64,
+    ) -> impl Iterator<Item = RegionEntry<'a>> + 'a {
+        self.regions
+            .iter()
+            .enumerate()
+            .map(move |(index, region)| RegionEntry {
+                index,
+                offset: file_offset + region.offset,
+                region,
+                gpu_code_data,
+                file_offset,
+            })
     }
+
+    /// Iterate over the elements of all regions in order, with their indices, file spans and payloads.
+    pub fn elements<'a>(
+        &'a self,
+        gpu_code_data: &'a [u8],
+        file_offset: u64,
+    ) -> impl Iterator<Item = ElementEntry<'a>> + 'a {
+        self.regions(gpu_code_data, file_offset)
+            .flat_map(|region| region.elements())
+    }
+}
+
+/// Represents the file span of an element within a region.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ElementSpan {
+    pub start: u64, // start file offset (inclusive)
+    pub end: u64,   // end file offset (exclusive)
+}
+
+/// A region as yielded by `GPUCode::regions`, positioned in the file
+pub struct RegionEntry<'a> {
+    /// Index of the region within its GPU code
+    pub index: usize,
+    /// File offset of the region header
+    pub offset: u64,
+    pub region: &'a Region,
+    gpu_code_data: &'a [u8],
+    file_offset: u64,
+}
+
+impl<'a> RegionEntry<'a> {
+    /// Iterate over the elements of the region, with their file spans and payloads
+    pub fn elements(&self) -> impl Iterator<Item = ElementEntry<'a>> + 'a {
+        let (region_index, region) = (self.index, self.region);
+        let (gpu_code_data, file_offset) = (self.gpu_code_data, self.file_offset);
+        region
+            .elements
+            .iter()
+            .enumerate()
+            .map(move |(index, element)| {
+                // the payload follows the element header, including its identifier and options
+                let start = element.offset + element.header.offset as u64;
+                let end = start + element.header.size;
+                ElementEntry {
+                    region_index,
+                    index,
+                    element,
+                    offset: file_offset + element.offset,
+                    span: ElementSpan {
+                        start: file_offset + start,
+                        end: file_offset + end,
+                    },
+                    stored: &gpu_code_data[start as usize..end as usize],
+                    gpu_code_data,
+                }
+            })
+    }
+}
+
+/// An element as yielded by `GPUCode::elements` and `RegionEntry::elements`, positioned in the file
+pub struct ElementEntry<'a> {
+    /// Index of the region within its GPU code
+    pub region_index: usize,
+    /// Index of the element within its region
+    pub index: usize,
+    pub element: &'a Element,
+    /// File offset of the element header
+    pub offset: u64,
+    /// File span of the payload as stored, i.e., compressed for compressed elements
+    pub span: ElementSpan,
+    /// The payload as stored, `span` of the file
+    pub stored: &'a [u8],
+    gpu_code_data: &'a [u8],
+}
+
+impl<'a> ElementEntry<'a> {
+    /// Get the header of the element
+    pub fn header(&self) -> &'a ElementHeader {
+        &self.element.header
+    }
+
+    /// Get the payload of the element, decompressed if it is compressed
+    pub fn payload(&self) -> Result<Cow<'a, [u8]>, FatbinError> {
+        self.element
+            .payload(self.gpu_code_data)
+            .map_err(|error| FatbinError::BadPayload {
+                offset: self.element.offset,
+                error,
+            })
+    }
+}
+
+// check if the bytes are all zero, e.g., padding
+fn is_zero(data: &[u8]) -> bool {
+    data.iter().all(|&b| b == 0)
+}
+
+// read little-endian integers, fatbins are little-endian regardless of the host byte order
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().unwrap(),
+    ))
 }
 
 /// Represents a region within the GPU code section, containing multiple elements.
 pub struct Region {
+    /// The offset of the region header within the GPU code data
+    pub offset: u64,
     pub header: RegionHeader,
     pub elements: Vec<Element>,
 }
 
 impl Region {
     /// Create a new Region instance by parsing the provided GPU code data starting from the specified offset.
-    pub fn new(gpu_code_data: &[u8], start_offset: u64) -> Self {
-        let region_data = &gpu_code_data[start_offset as usize..];
+    ///
+    /// The magic, version and header size are validated, and every element must lie within the region.
+    pub fn new(gpu_code_data: &[u8], start_offset: u64) -> Result<Self, FatbinError> {
+        let start = start_offset as usize;
+        let truncated = FatbinError::Truncated(start_offset);
+        let magic = read_u32(gpu_code_data, start).ok_or(truncated.clone())?;
+        if magic != FATBIN_MAGIC {
+            return Err(FatbinError::BadMagic {
+                offset: start_offset,
+                magic,
+            });
+        }
+        let version = read_u16(gpu_code_data, start + 4).ok_or(truncated.clone())?;
+        if version != 1 {
+            return Err(FatbinError::UnsupportedVersion {
+                offset: start_offset,
+                version,
+            });
+        }
         let header = RegionHeader {
-            header_size: u16::from_ne_bytes(region_data[6..8].try_into().unwrap()),
-            fat_size: u64::from_ne_bytes(region_data[8..16].try_into().unwrap()),
+            header_size: read_u16(gpu_code_data, start + 6).ok_or(truncated.clone())?,
+            fat_size: read_u64(gpu_code_data, start + 8).ok_or(truncated)?,
         };
-        let mut element_offset: usize = 16;
+        if header.header_size as u32 != RegionHeader::size() {
+            return Err(FatbinError::BadHeaderSize {
+                offset: start_offset,
+                header_size: header.header_size,
+            });
+        }
+        let region_end = start_offset
+            .saturating_add(header.header_size as u64)
+            .saturating_add(header.fat_size);
+        if region_end > gpu_code_data.len() as u64 {
+            return Err(FatbinError::RegionOutOfBounds {
+                offset: start_offset,
+                end: region_end,
+            });
+        }
+        let region_data = &gpu_code_data[..region_end as usize];
+
+        let mut element_offset = start_offset + header.header_size as u64;
         let mut elements = vec![];
-        while element_offset < header.fat_size as usize {
+        while element_offset < region_end {
             debug!("Element offset: {}", element_offset);
-            let element_header = ElementHeader {
-                file_type: u16::from_ne_bytes(
-                    region_data[element_offset..element_offset + 2]
-                        .try_into()
-                        .unwrap(),
-                ),
-                offset: u32::from_ne_bytes(
-                    region_data[element_offset + 4..element_offset + 8]
-                        .try_into()
-                        .unwrap(),
-                ),
-                size: u64::from_ne_bytes(
-                    region_data[element_offset + 8..element_offset + 16]
-                        .try_into()
-                        .unwrap(),
-                ),
-                capability: u32::from_ne_bytes(
-                    region_data[element_offset + 28..element_offset + 32]
-                        .try_into()
-                        .unwrap(),
-                ),
+            let out_of_bounds = |end| FatbinError::ElementOutOfBounds {
+                offset: element_offset,
+                end,
+                region_end,
             };
-            element_offset = element_header.offset as usize
-                + element_offset as usize
-                + element_header.size as usize;
+            let e = element_offset as usize;
+            if element_offset + MIN_ELEMENT_HEADER_SIZE as u64 > region_end {
+                return Err(out_of_bounds(
+                    element_offset + MIN_ELEMENT_HEADER_SIZE as u64,
+                ));
+            }
+            let header_size = read_u32(region_data, e + 4).unwrap();
+            if header_size < MIN_ELEMENT_HEADER_SIZE {
+                return Err(FatbinError::BadElementHeader {
+                    offset: element_offset,
+                    header_size,
+                });
+            }
+            let header_end = element_offset + header_size as u64;
+            if header_end > region_end {
+                return Err(out_of_bounds(header_end));
+            }
+            let element_header = ElementHeader::parse(&region_data[e..header_end as usize]);
+            let element_end = header_end.saturating_add(element_header.size);
+            if element_end > region_end {
+                return Err(out_of_bounds(element_end));
+            }
             let element = Element {
+                offset: element_offset,
                 header: element_header,
             };
+            element_offset = element_end;
             elements.push(element);
         }
 
-        Self { header, elements }
+        Ok(Self {
+            offset: start_offset,
+            header,
+            elements,
+        })
     }
 
     /// Calculate the total size of the region, including the header and FAT size.
     pub fn size(&self) -> u64 {
         self.header.fat_size + RegionHeader::size() as u64
     }
-
-    // Find the most suitable capability that is less than or equal to the target capability.
-    pub fn find_most_fit_capability(&self, target_cap: u32) -> u32 {
-        let mut most_fit_cap = 0;
-        for e in self.elements.iter() {
-            if e.header.capability <= target_cap && e.header.capability > most_fit_cap {
-                most_fit_cap = e.header.capability;
-            }
-        }
-        most_fit_cap
-    }
 }
 
 /// Represents an individual element within a region.
 pub struct Element {
+    /// The offset of the element header within the GPU code data
+    pub offset: u64,
     pub header: ElementHeader,
 }
 
+impl Element {
+    /// Get the payload of the element, decompressed if it is compressed.
+    ///
+    /// `gpu_code_data` is the GPU code data the element was parsed from.
+    /// Spans in the file always cover the payload as stored, i.e., `header.size` bytes.
+    pub fn payload<'a>(&self, gpu_code_data: &'a [u8]) -> Result<Cow<'a, [u8]>, DecompressError> {
+        let start = (self.offset + self.header.offset as u64) as usize;
+        let stored = gpu_code_data
+            .get(start..start + self.header.size as usize)
+            .ok_or(DecompressError::Truncated)?;
+        if !self.header.is_compressed() {
+            return Ok(Cow::Borrowed(stored));
+        }
+        // the stored payload is padded for alignment after the compressed data
+        let compressed = stored
+            .get(..self.header.compressed_size as usize)
+            .ok_or(DecompressError::Truncated)?;
+        decompress(compressed, self.header.uncompressed_size as usize).map(Cow::Owned)
+    }
+}
+
 /// Represents the header of a region, containing metadata about the region.
 pub struct RegionHeader {
     pub header_size: u16,
@@ -107,12 +411,171 @@ impl RegionHeader {
     }
 }
 
+/// The kind of code an element holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElementKind {
+    /// PTX assembly, compiled just in time by the driver
+    Ptx,
+    /// A cubin, i.e., an ELF file of SASS for one GPU architecture
+    Cubin,
+    /// NVVM IR for link-time optimization, as embedded by nvcc -dlto
+    LtoIr,
+    Other(u16),
+}
+
+impl From<u16> for ElementKind {
+    fn from(kind: u16) -> Self {
+        match kind {
+            1 => ElementKind::Ptx,
+            2 => ElementKind::Cubin,
+            4 => ElementKind::LtoIr,
+            kind => ElementKind::Other(kind),
+        }
+    }
+}
+
+/// A flag of an element header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElementFlag {
+    /// Compiled for 64-bit device addresses
+    #[serde(rename = "64bit")]
+    Address64,
+    /// Compiled with debug information, e.g., nvcc -G
+    Debug,
+    Cuda,
+    OpenCl,
+    /// Built on a Linux host
+    Linux,
+    /// Built on a macOS host
+    Mac,
+    /// Built on a Windows host
+    Windows,
+    /// The payload is compressed
+    Compressed,
+}
+
+impl ElementFlag {
+    const ALL: [ElementFlag; 8] = [
+        ElementFlag::Address64,
+        ElementFlag::Debug,
+        ElementFlag::Cuda,
+        ElementFlag::OpenCl,
+        ElementFlag::Linux,
+        ElementFlag::Mac,
+        ElementFlag::Windows,
+        ElementFlag::Compressed,
+    ];
+
+    /// Get the bit of the flag in the header
+    pub fn bit(&self) -> u64 {
+        match self {
+            ElementFlag::Address64 => 0x1,
+            ElementFlag::Debug => 0x2,
+            ElementFlag::Cuda => 0x4,
+            ElementFlag::OpenCl => 0x8,
+            ElementFlag::Linux => 0x10,
+            ElementFlag::Mac => 0x20,
+            ElementFlag::Windows => 0x40,
+            ElementFlag::Compressed => 0x2000,
+        }
+    }
+}
+
+/// The flags of an element header, serialized as the list of the known flags that are set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementFlags(pub u64);
+
+impl ElementFlags {
+    /// Check if the given flag is set
+    pub fn contains(&self, flag: ElementFlag) -> bool {
+        self.0 & flag.bit() != 0
+    }
+
+    /// Get the known flags that are set
+    pub fn flags(&self) -> Vec<ElementFlag> {
+        ElementFlag::ALL
+            .into_iter()
+            .filter(|f| self.contains(*f))
+            .collect()
+    }
+}
+
+impl Serialize for ElementFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.flags())
+    }
+}
+
 /// Represents the header of an element, containing metadata about the element.
+///
+/// The fixed part is 64 bytes long, strings such as the identifier follow it inside the header and
+/// are referenced by offset and length. Fields a shorter header lacks are 0 or None.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ElementHeader {
-    pub file_type: u16,
+    pub kind: ElementKind,
+    /// The header format version, 0x101
+    pub version: u16,
+    /// The size of the header, i.e., the offset of the payload from the element start
     pub offset: u32,
+    /// The size of the payload, padded for alignment
     pub size: u64,
+    /// The size of the compressed payload, 0 if it is not compressed
+    pub compressed_size: u32,
+    /// The version of the code, e.g., the PTX ISA version of PTX
+    pub major_version: u16,
+    pub minor_version: u16,
+    /// The compute capability the code is compiled for, e.g., 70 for sm_70
     pub capability: u32,
+    /// The identifier nvcc names the element with, usually the source file of its compilation unit
+    pub identifier: Option<String>,
+    pub flags: ElementFlags,
+    /// The options to compile the element with, e.g., the ptxas options for just-in-time compilation of PTX
+    pub options: Option<String>,
+    /// The size of the decompressed payload, 0 if it is not compressed
+    pub uncompressed_size: u64,
+}
+
+impl ElementHeader {
+    /// Decode the element header, the data must hold exactly the header
+    pub fn parse(header_data: &[u8]) -> Self {
+        let u16_at = |offset| read_u16(header_data, offset).unwrap_or(0);
+        let u32_at = |offset| read_u32(header_data, offset).unwrap_or(0);
+        let u64_at = |offset| read_u64(header_data, offset).unwrap_or(0);
+        ElementHeader {
+            kind: ElementKind::from(u16_at(0)),
+            version: u16_at(2),
+            offset: u32_at(4),
+            size: u64_at(8),
+            compressed_size: u32_at(16),
+            minor_version: u16_at(24),
+            major_version: u16_at(26),
+            capability: u32_at(28),
+            identifier: Self::parse_string(header_data, 32),
+            flags: ElementFlags(u64_at(40)),
+            options: Self::parse_string(header_data, 48),
+            uncompressed_size: u64_at(56),
+        }
+    }
+
+    // get the string referenced by the offset and length at the given field, if it lies within the header
+    fn parse_string(header_data: &[u8], field: usize) -> Option<String> {
+        let string_offset = read_u32(header_data, field)? as usize;
+        let string_size = read_u32(header_data, field + 4)? as usize;
+        if string_offset == 0 || string_size == 0 {
+            return None;
+        }
+        let string = header_data.get(string_offset..string_offset.checked_add(string_size)?)?;
+        let string = string.split(|&b| b == 0).next().unwrap_or_default();
+        (!string.is_empty()).then(|| String::from_utf8_lossy(string).into_owned())
+    }
+
+    /// Check if the payload is compressed, by its flag or by the sizes only compressed payloads have
+    pub fn is_compressed(&self) -> bool {
+        self.flags.contains(ElementFlag::Compressed)
+            || (self.compressed_size != 0 && self.uncompressed_size != 0)
+    }
 }
 
 #[cfg(test)]
@@ -133,7 +596,7 @@ mod tests {
         let so_path = fixture("libdemo.so");
         let data = std::fs::read(so_path).unwrap();
         let gpu_code_data = &data[0x948d0..0x9acb0];
-        let gpu_code = GPUCode::new(gpu_code_data);
+        let gpu_code = GPUCode::new(gpu_code_data).unwrap();
         let mut element_count = 0;
         let mut element_capabilities = vec![];
         let mut file_types = vec![];
@@ -142,31 +605,345 @@ mod tests {
             for element in region.elements.iter() {
                 element_count += 1;
                 element_capabilities.push(element.header.capability);
-                file_types.push(element.header.file_type);
+                file_types.push(element.header.kind);
             }
         }
 
         assert_eq!(element_count, 4);
-        assert_eq!(file_types, vec![2, 2, 2, 2]);
+        assert_eq!(file_types, vec![ElementKind::Cubin; 4]);
         assert_eq!(element_capabilities, vec![70, 75, 70, 75]);
     }
 
     #[test]
-    fn test_find_most_fit_capability() {
+    fn test_element_entries() {
         let _ = env_logger::try_init();
-        let so_path = fixture("libdemo.so");
-        let data = std::fs::read(so_path).unwrap();
+        let data = std::fs::read(fixture("libdemo.so")).unwrap();
         let gpu_code_data = &data[0x948d0..0x9acb0];
-        let gpu_code = GPUCode::new(gpu_code_data);
-        let region = &gpu_code.regions[0];
+        let gpu_code = GPUCode::new(gpu_code_data).unwrap();
+
+        let regions: Vec<(usize, u64)> = gpu_code
+            .regions(gpu_code_data, 0x948d0)
+            .map(|r| (r.index, r.offset))
+            .collect();
+        assert_eq!(regions, vec![(0, 0x948d0), (1, 0x95040)]);
+
+        // spans are file offsets of the payloads, the stored payloads are those spans of the file
+        let elements: Vec<ElementEntry> = gpu_code.elements(gpu_code_data, 0x948d0).collect();
+        let positions: Vec<(usize, usize, u64, u64, u64)> = elements
+            .iter()
+            .map(|e| (e.region_index, e.index, e.offset, e.span.start, e.span.end))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0, 0, 0x948e0, 0x94928, 0x94c90),
+                (0, 1, 0x94c90, 0x94cd8, 0x95040),
+                (1, 0, 0x95050, 0x95098, 0x97f80),
+                (1, 1, 0x97f80, 0x97fc8, 0x9acb0),
+            ]
+        );
+        for element in elements.iter() {
+            let span = element.span;
+            assert_eq!(
+                element.stored,
+                &data[span.start as usize..span.end as usize]
+            );
+            assert_eq!(element.payload().unwrap(), element.stored);
+            assert_eq!(element.header().capability, [70, 75][element.index]);
+        }
+
+        // compressed payloads are stored compressed and decompressed on demand
+        let data = std::fs::read(fixture("libcompressed.so")).unwrap();
+        let gpu_code_data = &data[0x440..0x440 + 0x4328];
+        let gpu_code = GPUCode::new(gpu_code_data).unwrap();
+        let element = gpu_code.elements(gpu_code_data, 0x440).next().unwrap();
+        assert_eq!(
+            (element.span.start, element.span.end),
+            (0x440 + 16 + 72, 0x440 + 16 + 72 + 5520)
+        );
+        assert_eq!(element.payload().unwrap().len(), 12008);
+    }
+
+    #[test]
+    fn test_new_gpu_code_all_classes() {
+        let _ = env_logger::try_init();
+        for (name, fatbin_offset) in [
+            ("libsynth.64le.so", 0x440),
+            ("libsynth.32le.so", 0x440),
+            ("libsynth.64be.so", 0x440),
+            ("libsynth.32be.so", 0x440),
+        ] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let gpu_code = GPUCode::new(&data[fatbin_offset..fatbin_offset + 0x770]).unwrap();
+            assert_eq!(gpu_code.regions.len(), 1, "{}", name);
+            let capabilities: Vec<u32> = gpu_code.regions[0]
+                .elements
+                .iter()
+                .map(|e| e.header.capability)
+                .collect();
+            assert_eq!(capabilities, vec![70, 75], "{}", name);
+        }
+    }
 
-        let cap = region.find_most_fit_capability(72);
-        assert_eq!(cap, 70);
+    #[test]
+    fn test_element_identifier() {
+        let _ = env_logger::try_init();
+        let data = std::fs::read(fixture("libdemo.so")).unwrap();
+        let gpu_code = GPUCode::new(&data[0x948d0..0x9acb0]).unwrap();
+        for element in gpu_code.regions.iter().flat_map(|r| r.elements.iter()) {
+            assert_eq!(element.header.identifier, None);
+        }
 
-        let cap = region.find_most_fit_capability(75);
-        assert_eq!(cap, 75);
+        // only the first element of the __nv_relfatbin region is named
+        let data = std::fs::read(fixture("libunits.so")).unwrap();
+        let gpu_code = GPUCode::new(&data[0xbb0..0xbb0 + 0x788]).unwrap();
+        let identifiers: Vec<Option<&str>> = gpu_code.regions[0]
+            .elements
+            .iter()
+            .map(|e| e.header.identifier.as_deref())
+            .collect();
+        assert_eq!(identifiers, vec![Some("kernels/scale.cu"), None]);
+        let capabilities: Vec<u32> = gpu_code.regions[0]
+            .elements
+            .iter()
+            .map(|e| e.header.capability)
+            .collect();
+        assert_eq!(capabilities, vec![70, 75]);
+    }
 
-        let cap = region.find_most_fit_capability(80);
-        assert_eq!(cap, 75);
+    // the first fatbin region of libdemo.so, as copied into the synthetic fixtures
+    fn region() -> Vec<u8> {
+        let data = std::fs::read(fixture("libsynth.64le.so")).unwrap();
+        data[0x440..0x440 + 0x770].to_vec()
+    }
+
+    #[test]
+    fn test_padding_between_regions() {
+        let _ = env_logger::try_init();
+        // a zero-sized region, padding to the alignment, a region and trailing padding
+        let mut data = region()[..16].to_vec();
+        data[8..16].copy_from_slice(&0u64.to_le_bytes());
+        data.extend([0; 8]);
+        data.extend(region());
+        data.extend([0; 20]);
+        let gpu_code = GPUCode::new(&data).unwrap();
+        assert_eq!(gpu_code.regions.len(), 2);
+        assert_eq!(gpu_code.regions[0].offset, 0);
+        assert!(gpu_code.regions[0].elements.is_empty());
+        assert_eq!(gpu_code.regions[1].offset, 0x18);
+        assert_eq!(gpu_code.regions[1].elements.len(), 2);
+
+        assert!(GPUCode::new(&[0; 64]).unwrap().regions.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_regions() {
+        let _ = env_logger::try_init();
+        let corrupt = |offset: usize, bytes: &[u8]| {
+            let mut data = region();
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            GPUCode::new(&data).err().unwrap()
+        };
+
+        assert_eq!(
+            corrupt(0, &[0xff]),
+            FatbinError::BadMagic {
+                offset: 0,
+                magic: 0xba55edff
+            }
+        );
+        assert_eq!(
+            corrupt(4, &2u16.to_le_bytes()),
+            FatbinError::UnsupportedVersion {
+                offset: 0,
+                version: 2
+            }
+        );
+        assert_eq!(
+            corrupt(6, &64u16.to_le_bytes()),
+            FatbinError::BadHeaderSize {
+                offset: 0,
+                header_size: 64
+            }
+        );
+        assert_eq!(
+            corrupt(8, &u64::MAX.to_le_bytes()),
+            FatbinError::RegionOutOfBounds {
+                offset: 0,
+                end: u64::MAX
+            }
+        );
+        // the header size of the first element
+        assert_eq!(
+            corrupt(20, &0u32.to_le_bytes()),
+            FatbinError::BadElementHeader {
+                offset: 16,
+                header_size: 0
+            }
+        );
+        // the payload size of the second element
+        assert_eq!(
+            corrupt(16 + 944 + 8, &0x1000u64.to_le_bytes()),
+            FatbinError::ElementOutOfBounds {
+                offset: 960,
+                end: 960 + 72 + 0x1000,
+                region_end: 0x770
+            }
+        );
+        // the region ends in the middle of an element header
+        let mut data = region();
+        data[8..16].copy_from_slice(&960u64.to_le_bytes());
+        assert_eq!(
+            GPUCode::new(&data).err().unwrap(),
+            FatbinError::ElementOutOfBounds {
+                offset: 960,
+                end: 960 + 32,
+                region_end: 976
+            }
+        );
+
+        // cut in the middle of a region header, or of the region data
+        let data = region();
+        assert_eq!(
+            GPUCode::new(&data[..8]).err().unwrap(),
+            FatbinError::Truncated(0)
+        );
+        assert_eq!(
+            GPUCode::new(&data[..0x700]).err().unwrap(),
+            FatbinError::RegionOutOfBounds {
+                offset: 0,
+                end: 0x770
+            }
+        );
+        // non-zero garbage after a region is not padding
+        let mut data = region();
+        data.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(
+            GPUCode::new(&data).err().unwrap(),
+            FatbinError::BadMagic {
+                offset: 0x770,
+                magic: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_element_header() {
+        let _ = env_logger::try_init();
+        let gpu_code = GPUCode::new(&region()).unwrap();
+        let header = &gpu_code.regions[0].elements[0].header;
+        assert_eq!(
+            *header,
+            ElementHeader {
+                kind: ElementKind::Cubin,
+                version: 0x101,
+                offset: 72,
+                size: 872,
+                compressed_size: 0,
+                major_version: 1,
+                minor_version: 7,
+                capability: 70,
+                identifier: None,
+                flags: ElementFlags(0x11),
+                options: None,
+                uncompressed_size: 0,
+            }
+        );
+        assert_eq!(
+            header.flags.flags(),
+            vec![ElementFlag::Address64, ElementFlag::Linux]
+        );
+        assert_eq!(gpu_code.regions[0].elements[1].offset, 16 + 72 + 872);
+
+        // a compressed PTX element with its identifier and options after the fixed part
+        let mut data = vec![0u8; 96];
+        data[0..2].copy_from_slice(&1u16.to_le_bytes());
+        data[2..4].copy_from_slice(&0x101u16.to_le_bytes());
+        data[4..8].copy_from_slice(&96u32.to_le_bytes());
+        data[8..16].copy_from_slice(&0x200u64.to_le_bytes());
+        data[16..20].copy_from_slice(&0x1f0u32.to_le_bytes());
+        data[24..26].copy_from_slice(&5u16.to_le_bytes());
+        data[26..28].copy_from_slice(&8u16.to_le_bytes());
+        data[28..32].copy_from_slice(&90u32.to_le_bytes());
+        data[32..40].copy_from_slice(&[64, 0, 0, 0, 8, 0, 0, 0]);
+        data[40..48].copy_from_slice(&0x2013u64.to_le_bytes());
+        data[48..56].copy_from_slice(&[80, 0, 0, 0, 4, 0, 0, 0]);
+        data[56..64].copy_from_slice(&0x800u64.to_le_bytes());
+        data[64..72].copy_from_slice(b"dense.cu");
+        data[80..83].copy_from_slice(b"-O3");
+        let header = ElementHeader::parse(&data);
+        assert_eq!(header.kind, ElementKind::Ptx);
+        assert_eq!((header.major_version, header.minor_version), (8, 5));
+        assert_eq!(header.capability, 90);
+        assert_eq!(
+            (header.compressed_size, header.uncompressed_size),
+            (0x1f0, 0x800)
+        );
+        assert_eq!(header.identifier.as_deref(), Some("dense.cu"));
+        assert_eq!(header.options.as_deref(), Some("-O3"));
+        assert!(header.flags.contains(ElementFlag::Compressed));
+        assert!(header.flags.contains(ElementFlag::Debug));
+        assert!(!header.flags.contains(ElementFlag::Windows));
+        assert_eq!(
+            serde_json::to_value(&header).unwrap()["flags"],
+            serde_json::json!(["64bit", "debug", "linux", "compressed"])
+        );
+
+        // strings must lie within the header, fields past a short header are zero
+        data[48..52].copy_from_slice(&94u32.to_le_bytes());
+        assert_eq!(ElementHeader::parse(&data).options, None);
+        let header = ElementHeader::parse(&data[..40]);
+        assert_eq!(header.identifier, None);
+        assert_eq!(header.flags, ElementFlags(0));
+        assert_eq!(
+            [2, 4, 8, 16].map(ElementKind::from),
+            [
+                ElementKind::Cubin,
+                ElementKind::LtoIr,
+                ElementKind::Other(8),
+                ElementKind::Other(16)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compressed_payload() {
+        let _ = env_logger::try_init();
+        // the second fatbin region of libdemo.so, compressed in the fixture
+        let demo = std::fs::read(fixture("libdemo.so")).unwrap();
+        let region_data = &demo[0x95040..0x95040 + 0x5c70];
+        let original = GPUCode::new(region_data).unwrap();
+        let data = std::fs::read(fixture("libcompressed.so")).unwrap();
+        let gpu_code_data = &data[0x440..0x440 + 0x4328];
+        let gpu_code = GPUCode::new(gpu_code_data).unwrap();
+        let elements = &gpu_code.regions[0].elements;
+        assert_eq!(elements.len(), 2);
+
+        // an LZ4 block, then a zstd frame, both decompress to the original cubins
+        for (element, original) in elements.iter().zip(original.regions[0].elements.iter()) {
+            assert!(element.header.is_compressed());
+            assert!(!original.header.is_compressed());
+            assert_eq!(element.header.uncompressed_size, original.header.size);
+            let payload = element.payload(gpu_code_data).unwrap();
+            assert!(matches!(payload, Cow::Owned(_)));
+            assert_eq!(payload, original.payload(region_data).unwrap());
+            assert!(payload.starts_with(b"\x7fELF"));
+        }
+        assert_eq!(elements[0].header.compressed_size, 5516);
+        assert!(gpu_code_data[elements[1].offset as usize + 72..]
+            .starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+
+        // the stored payload ends before the compressed data does
+        let mut element = Element {
+            offset: elements[0].offset,
+            header: elements[0].header.clone(),
+        };
+        element.header.compressed_size = element.header.size as u32 + 1;
+        assert_eq!(
+            element.payload(gpu_code_data).err().unwrap(),
+            DecompressError::Truncated
+        );
+        element.header.compressed_size = 2000;
+        assert!(element.payload(gpu_code_data).is_err());
     }
 }
@@ -1,10 +1,15 @@
-use super::gpu_code::GPUCode;
-use log::{debug, info, warn};
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
-use tempfile::tempdir;
+use super::compat::{select_element, Arch};
+use super::cubin::{Cubin, CUBLAS_INTERNAL_CONSTANT};
+use super::gpu_code::{
+    Element, ElementFlags, ElementHeader, ElementKind, ElementSpan, FatbinError, GPUCode, Region,
+    RegionHeader,
+};
+use super::ptx::{ptx_entries, ptx_target};
+use super::units::{unit_usage, UnitUsage};
+use crate::utils::demangle::{kernel_names, KernelName};
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 
 /// Locates deletable file spans in a shared object file based on detected GPU kernels and compute capability.
 pub struct KernelLocator<'so_path> {
@@ -12,88 +17,168 @@ pub struct KernelLocator<'so_path> {
     gpu_code: GPUCode,
     element_span: Vec<Vec<ElementSpan>>, // element_span[region_index][element_index] -> ElementSpan
     element_kernels: Vec<Vec<HashSet<String>>>, // element_kernels[region_index][element_index] -> kernel names
+    element_archs: Vec<Vec<Arch>>, // element_archs[region_index][element_index] -> architecture compiled for
+    region_units: Vec<Option<String>>, // region_units[region_index] -> source file of the compilation unit
+    region_sizes: Vec<u64>, // region_sizes[region_index] -> bytes of the region in the file, headers included
+    call_graph: HashMap<String, HashSet<String>>, // caller -> callees, over the cubins of all elements
 }
 
-/// Represents the file span of an element within a region.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct ElementSpan {
-    pub start: u64, // start file offset (inclusive)
-    pub end: u64,   // end file offset (exclusive)
+/// Describes an element of the located fatbins, for reports and inspection.
+#[derive(Debug, Clone, Serialize)]
+pub struct ElementInfo<'a> {
+    /// Index of the region, over all fatbins of the shared object file
+    pub region: usize,
+    /// File span of the element payload
+    pub span: ElementSpan,
+    pub header: &'a ElementHeader,
+    /// Architecture the element is compiled for, e.g., 90a for arch-specific SASS
+    pub arch: Arch,
+    /// Kernels of a cubin, or entry points of PTX, sorted
+    pub kernels: Vec<&'a str>,
+    pub deletable: bool,
 }
 
-const CUBLAS_INTERNAL_CONSTANT: &str = "_ZN6cublas8internal15deviceConstantsE";
-
 impl<'so_path> KernelLocator<'so_path> {
-    /// Create a new KernelLocator instance by parsing the provided shared object file and GPU code section.
+    /// Create a new KernelLocator instance by parsing the provided shared object file and its embedded fatbins.
     /// * `so_path`: Path to the shared object file.
-    /// * `gpu_code_start_offset`: Start offset of the GPU code section within the shared object file.
-    /// * `gpu_code_size`: Size of the GPU code section.
-    /// * `cuobjdump_path`: Path to the cuobjdump executable.
-    /// Returns a KernelLocator instance.
+    /// * `so_data`: Content of the shared object file, usually memory-mapped.
+    /// * `gpu_code_spans`: File offset and size of each fatbin embedded in the shared object file, in file order.
+    /// * `compilation_units`: Source file of the compilation unit of each fatbin region, by region file offset.
+    /// Returns a KernelLocator instance, or the error of the first fatbin that cannot be parsed.
     pub fn new(
         so_path: &'so_path str,
-        gpu_code_start_offset: u64,
-        gpu_code_size: u64,
-        cuobjdump_path: &str,
-    ) -> KernelLocator<'so_path> {
-        let so_data = std::fs::read(so_path).unwrap();
-        let gpu_code_data = &so_data[gpu_code_start_offset as usize
-            ..gpu_code_start_offset as usize + gpu_code_size as usize];
-        let gpu_code = GPUCode::new(gpu_code_data);
-
-        // extract all cubin paths
-        let target_cubin_dir: tempfile::TempDir = tempdir().unwrap();
-        let cubin_paths = Self::extract_all_cubins(
-            so_path,
-            target_cubin_dir.path().to_str().unwrap(),
-            cuobjdump_path,
-        );
-
-        // calculate element spans and parse element kernel names
+        so_data: &[u8],
+        gpu_code_spans: &[(u64, u64)],
+        compilation_units: &HashMap<u64, String>,
+    ) -> Result<KernelLocator<'so_path>, FatbinError> {
+        // regions of all fatbins are indexed together, in file order
+        let mut gpu_code = GPUCode { regions: vec![] };
         let mut element_span = vec![];
-        let mut offset = gpu_code_start_offset;
-
         let mut element_kernels = vec![];
-        let mut cubin_path_index = 0;
-        for region_idx in 0..gpu_code.regions.len() {
-            let region = &gpu_code.regions[region_idx];
-            let mut inner_offset = 0;
-            let mut spans = vec![];
-            let mut kernels = vec![];
-            for element_idx in 0..region.elements.len() {
-                // calculate element span
-                let element = &region.elements[element_idx];
-                let start = element.header.offset as u64
-                    + inner_offset
-                    + offset
-                    + region.header.header_size as u64;
-                let end = start + element.header.size;
-                spans.push(ElementSpan { start, end });
-                inner_offset += element.header.offset as u64 + element.header.size;
-
-                // parse element kernel names
-                if element.header.file_type != 2 {
-                    // only process cubin file type
-                    kernels.push(HashSet::new());
-                } else {
-                    let cubin_path = &cubin_paths[cubin_path_index];
-                    cubin_path_index += 1;
-                    let kernel_names = Self::extract_cubin_kernels(cubin_path, cuobjdump_path);
+        let mut element_archs = vec![];
+        let mut region_units = vec![];
+        let mut region_sizes = vec![];
+        let mut call_graph = HashMap::new();
+        for &(gpu_code_start_offset, gpu_code_size) in gpu_code_spans {
+            let gpu_code_data = &so_data[gpu_code_start_offset as usize
+                ..gpu_code_start_offset as usize + gpu_code_size as usize];
+            let fatbin = GPUCode::new(gpu_code_data)?;
+            for region in fatbin.regions(gpu_code_data, gpu_code_start_offset) {
+                // an element identifier names the source file directly, the symbols of the region are the fallback
+                let unit = region
+                    .region
+                    .elements
+                    .iter()
+                    .find_map(|e| e.header.identifier.clone())
+                    .or_else(|| compilation_units.get(&region.offset).cloned());
+                debug!(
+                    "{}: region at {:#x} from {}",
+                    so_path,
+                    region.offset,
+                    unit.as_deref().unwrap_or("unknown compilation unit")
+                );
+                region_units.push(unit);
+                region_sizes.push(region.region.size());
+
+                let mut spans = vec![];
+                let mut kernels = vec![];
+                let mut archs = vec![];
+                for element in region.elements() {
+                    spans.push(element.span);
+                    let header = element.header();
+
+                    // the payload tells arch-specific variants apart, the header capability is the fallback
+                    let header_arch = Arch::generic(header.capability);
+
+                    // parse element kernel names, the span covers the payload as stored
+                    if !matches!(header.kind, ElementKind::Cubin | ElementKind::Ptx) {
+                        // LTO-IR is linked at load time, its kernels are not listed
+                        kernels.push(HashSet::new());
+                        archs.push(header_arch);
+                        continue;
+                    }
+                    let payload = element.payload()?;
+                    let (kernel_names, arch) = parse_module(
+                        header.kind,
+                        &payload,
+                        element.element.offset,
+                        &mut call_graph,
+                    )?;
                     kernels.push(kernel_names);
+                    archs.push(arch.unwrap_or(header_arch));
                 }
+                element_span.push(spans);
+                element_kernels.push(kernels);
+                element_archs.push(archs);
             }
-            element_span.push(spans);
-            element_kernels.push(kernels);
-            let region_size = region.size();
-            offset += region_size;
+            gpu_code.regions.extend(fatbin.regions);
         }
 
-        Self {
+        Ok(Self {
             so_path,
             gpu_code,
             element_span,
             element_kernels,
-        }
+            element_archs,
+            region_units,
+            region_sizes,
+            call_graph,
+        })
+    }
+
+    /// Create a new KernelLocator instance for a cubin or PTX module loaded on its own, e.g., from a Triton cache.
+    /// The module is located as one region with one element spanning the whole file, so it is kept or deleted whole.
+    /// * `so_path`: Path to the module file.
+    /// * `module_data`: Content of the module file.
+    /// * `kind`: `ElementKind::Cubin` or `ElementKind::Ptx`.
+    /// Returns a KernelLocator instance, or an error if the module or its architecture cannot be parsed.
+    pub fn from_module(
+        so_path: &'so_path str,
+        module_data: &[u8],
+        kind: ElementKind,
+    ) -> Result<KernelLocator<'so_path>, FatbinError> {
+        let mut call_graph = HashMap::new();
+        let (kernels, arch) = parse_module(kind, module_data, 0, &mut call_graph)?;
+        // without its architecture, there is no telling whether the driver would load it
+        let arch = arch.ok_or(FatbinError::UnknownArch(0))?;
+        let size = module_data.len() as u64;
+        let header = ElementHeader {
+            kind,
+            version: 0,
+            offset: 0,
+            size,
+            compressed_size: 0,
+            major_version: 0,
+            minor_version: 0,
+            capability: arch.capability,
+            identifier: None,
+            flags: ElementFlags(0),
+            options: None,
+            uncompressed_size: 0,
+        };
+        let region = Region {
+            offset: 0,
+            header: RegionHeader {
+                header_size: 0,
+                fat_size: size,
+            },
+            elements: vec![Element { offset: 0, header }],
+        };
+        Ok(Self {
+            so_path,
+            gpu_code: GPUCode {
+                regions: vec![region],
+            },
+            element_span: vec![vec![ElementSpan {
+                start: 0,
+                end: size,
+            }]],
+            element_kernels: vec![vec![kernels]],
+            element_archs: vec![vec![arch]],
+            region_units: vec![None],
+            region_sizes: vec![size],
+            call_graph,
+        })
     }
 
     /// Locate deletable file spans based on detected kernels and compute capability.
@@ -105,39 +190,176 @@ impl<'so_path> KernelLocator<'so_path> {
         detected_kernels: &HashSet<String>,
         compute_capability: u32,
     ) -> Vec<ElementSpan> {
-        // given a set of detected kernels and compute capability, locate the file spans that can be deleted, i.e., no detected kernels in the spans
-        let mut deletable_spans = vec![];
-        for i in 0..self.gpu_code.regions.len() {
-            let most_fit_cap =
-                self.gpu_code.regions[i].find_most_fit_capability(compute_capability);
+        self.locate_deletable_elements(detected_kernels, compute_capability)
+            .into_iter()
+            .map(|(i, j)| *self.get_element_span(i, j))
+            .collect()
+    }
+
+    /// Locate the kernels removed for the target compute capability, i.e., the kernels of deletable elements that
+    /// would otherwise be loaded on the GPU.
+    /// * `detected_kernels`: Set of detected kernel names.
+    /// * `compute_capability`: Target compute capability (e.g., 70 for sm_70).
+    /// Returns the removed kernel names, sorted by mangled name.
+    pub fn locate_removed_kernels(
+        &self,
+        detected_kernels: &HashSet<String>,
+        compute_capability: u32,
+    ) -> Vec<KernelName> {
+        let loaded = self.locate_loaded_elements(compute_capability);
+        let mut removed = HashSet::new();
+        for (i, j) in self.locate_deletable_elements(detected_kernels, compute_capability) {
+            if loaded[i] == Some(j) {
+                removed.extend(self.get_element_kernels(i, j).iter());
+            }
+        }
+        kernel_names(removed)
+    }
+
+    /// Locate the kernels and device functions of the file that are used without being detected, i.e., reachable
+    /// from a detected kernel through device calls, function pointers or dynamic parallelism.
+    /// * `detected_kernels`: Set of detected kernel names.
+    /// Returns the reachable kernel names, sorted by mangled name.
+    pub fn locate_reachable_kernels(&self, detected_kernels: &HashSet<String>) -> Vec<KernelName> {
+        let used = self.used_kernels(detected_kernels);
+        let reachable: HashSet<&String> = self
+            .element_kernels
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|kernel| used.contains(*kernel) && !detected_kernels.contains(*kernel))
+            .collect();
+        kernel_names(reachable)
+    }
+
+    /// Locate the bytes kept and removed per compilation unit, i.e., per nvcc translation unit,
+    /// the units with the most removed bytes first.
+    /// * `detected_kernels`: Set of detected kernel names.
+    /// * `compute_capability`: Target compute capability (e.g., 70 for sm_70).
+    pub fn locate_unit_usage(
+        &self,
+        detected_kernels: &HashSet<String>,
+        compute_capability: u32,
+    ) -> Vec<UnitUsage> {
+        let mut removed = vec![0; self.gpu_code.regions.len()];
+        for (i, j) in self.locate_deletable_elements(detected_kernels, compute_capability) {
+            let span = self.get_element_span(i, j);
+            removed[i] += span.end - span.start;
+        }
+        unit_usage(
+            self.region_units
+                .iter()
+                .zip(self.region_sizes.iter())
+                .zip(removed)
+                .map(|((unit, &size), removed)| (unit.as_deref(), size, removed)),
+        )
+    }
+
+    /// Locate every element with its decoded header, and whether it is deletable.
+    /// * `detected_kernels`: Set of detected kernel names.
+    /// * `compute_capability`: Target compute capability (e.g., 70 for sm_70).
+    pub fn locate_elements(
+        &self,
+        detected_kernels: &HashSet<String>,
+        compute_capability: u32,
+    ) -> Vec<ElementInfo<'_>> {
+        let deletable: HashSet<(usize, usize)> = self
+            .locate_deletable_elements(detected_kernels, compute_capability)
+            .into_iter()
+            .collect();
+        let mut elements = vec![];
+        for (i, region) in self.gpu_code.regions.iter().enumerate() {
+            for (j, element) in region.elements.iter().enumerate() {
+                let mut kernels: Vec<&str> = self
+                    .get_element_kernels(i, j)
+                    .iter()
+                    .map(String::as_str)
+                    .collect();
+                kernels.sort_unstable();
+                elements.push(ElementInfo {
+                    region: i,
+                    span: *self.get_element_span(i, j),
+                    header: &element.header,
+                    arch: self.element_archs[i][j],
+                    kernels,
+                    deletable: deletable.contains(&(i, j)),
+                });
+            }
+        }
+        elements
+    }
+
+    /// Locate the element the CUDA driver loads from each region on a GPU of the given compute capability.
+    /// Returns the element index per region, None if no element of the region can run on the GPU.
+    fn locate_loaded_elements(&self, compute_capability: u32) -> Vec<Option<usize>> {
+        self.gpu_code
+            .regions
+            .iter()
+            .zip(self.element_archs.iter())
+            .map(|(region, archs)| {
+                let elements = region.elements.iter().map(|e| e.header.kind);
+                select_element(elements.zip(archs.iter().copied()), compute_capability)
+            })
+            .collect()
+    }
+
+    /// Get the detected kernels and everything reachable from them in the call graph, e.g., the device functions
+    /// they call and the kernels they launch, wherever in the file these are defined.
+    fn used_kernels(&self, detected_kernels: &HashSet<String>) -> HashSet<String> {
+        let mut used = detected_kernels.clone();
+        let mut pending: Vec<&String> = detected_kernels.iter().collect();
+        while let Some(caller) = pending.pop() {
+            for callee in self.call_graph.get(caller).into_iter().flatten() {
+                if used.insert(callee.clone()) {
+                    pending.push(callee);
+                }
+            }
+        }
+        used
+    }
+
+    /// Locate deletable elements based on detected kernels and compute capability.
+    /// Returns the region and element index of each deletable element.
+    fn locate_deletable_elements(
+        &self,
+        detected_kernels: &HashSet<String>,
+        compute_capability: u32,
+    ) -> Vec<(usize, usize)> {
+        // keep what the driver loads on the target GPU if one of its kernels is used, SASS and PTX it does not load are deletable
+        let used_kernels = self.used_kernels(detected_kernels);
+        let loaded = self.locate_loaded_elements(compute_capability);
+        let mut deletable_elements = vec![];
+        for (i, loaded) in loaded.into_iter().enumerate() {
             for j in 0..self.gpu_code.regions[i].elements.len() {
                 let element = &self.gpu_code.regions[i].elements[j];
-                if element.header.capability != most_fit_cap {
-                    deletable_spans.push(self.get_element_span(i, j).clone());
-                } else {
-                    if element.header.file_type != 2 {
+                match element.header.kind {
+                    ElementKind::Cubin | ElementKind::Ptx => {}
+                    // LTO-IR is linked at load time, its kernels are not parsed, so it is kept
+                    ElementKind::LtoIr | ElementKind::Other(_) => continue,
+                }
+                if loaded != Some(j) {
+                    deletable_elements.push((i, j));
+                    continue;
+                }
+                let element_kernels = self.get_element_kernels(i, j);
+
+                let is_disjoint = used_kernels.is_disjoint(element_kernels);
+                if is_disjoint {
+                    // workaround: libcublas has some special internal constants needs to be retained
+                    if self.so_path.contains("libcublas")
+                        && element_kernels.contains(CUBLAS_INTERNAL_CONSTANT)
+                    {
+                        info!(
+                            "Retaining libcublas internal constants, {}, {}, {}",
+                            self.so_path, i, j
+                        );
                         continue;
                     }
-                    let element_kernels = self.get_element_kernels(i, j);
-
-                    let is_disjoint = detected_kernels.is_disjoint(element_kernels);
-                    if is_disjoint {
-                        // workaround: libcublas has some special internal constants needs to be retained
-                        if self.so_path.contains("libcublas")
-                            && element_kernels.contains(CUBLAS_INTERNAL_CONSTANT)
-                        {
-                            info!(
-                                "Retaining libcublas internal constants, {}, {}, {}",
-                                self.so_path, i, j
-                            );
-                            continue;
-                        }
-                        deletable_spans.push(self.get_element_span(i, j).clone());
-                    }
+                    deletable_elements.push((i, j));
                 }
             }
         }
-        deletable_spans
+        deletable_elements
     }
 
     /// Get the file span of a specific element within a region.
@@ -166,139 +388,36 @@ impl<'so_path> KernelLocator<'so_path> {
 
         &self.element_kernels[region_index][element_index]
     }
+}
 
-    /// Extract all cubin files from the given shared object file using cuobjdump.
-    /// * `so_path`: Path to the shared object file.
-    /// * `target_dir`: Directory to store the extracted cubin files.
-    /// * `cuobjdump_path`: Path to the cuobjdump executable.
-    /// Returns a vector of paths to the extracted cubin files.
-    fn extract_all_cubins(so_path: &str, target_dir: &str, cuobjdump_path: &str) -> Vec<String> {
-        debug!("Extracting cubins from {}", so_path);
-        debug!("Target dir: {}", target_dir);
-
-        let mut child = Command::new(cuobjdump_path)
-            .current_dir(target_dir)
-            .arg(so_path)
-            .arg("-xelf")
-            .arg("all") // Customize the path as needed
-            .stdout(Stdio::piped())
-            .spawn() // Capture stdout
-            .expect("failed to execute command");
-        let stdout = child.stdout.take().unwrap();
-        let lines = BufReader::new(stdout).lines();
-        let mut cubin_file_paths = Vec::new();
-        for line in lines {
-            // line is like "Extracting ELF file    1: libtorch_cuda.1.sm_50.cubin"
-            let line = line.unwrap();
-            debug!("entry: {:?}", line);
-            let ele: Vec<&str> = line.split(":").collect();
-            let filename = ele[1].trim();
-            let path = std::path::Path::new(target_dir).join(filename);
-            if path.is_file() {
-                cubin_file_paths.push(path.to_str().unwrap().to_string());
-            }
-        }
-        let exit_status = child.wait().unwrap();
-        if !exit_status.success() {
-            panic!(
-                "cuobjdump failed with exit code: {:?}, {}",
-                exit_status.code(),
-                so_path
-            );
-        }
-
-        debug!("Extracted cubins to {} done", target_dir);
-
-        cubin_file_paths
+// parse the kernels of a cubin or PTX payload, and its architecture if the payload tells it
+// the device calls of a cubin are added to the call graph, PTX ones are not parsed
+fn parse_module(
+    kind: ElementKind,
+    payload: &[u8],
+    offset: u64,
+    call_graph: &mut HashMap<String, HashSet<String>>,
+) -> Result<(HashSet<String>, Option<Arch>), FatbinError> {
+    if kind == ElementKind::Ptx {
+        return Ok((ptx_entries(payload), ptx_target(payload)));
     }
-
-    /// Extract kernel names from a given cubin file using cuobjdump.
-    /// * `cubin_path`: Path to the cubin file.
-    /// * `cuobjdump_path`: Path to the cuobjdump executable.
-    /// Returns a set of kernel names extracted from the cubin file.
-    fn extract_cubin_kernels(cubin_path: &str, cuobjdump_path: &str) -> HashSet<String> {
-        let mut output = Command::new(cuobjdump_path)
-            .arg("-elf")
-            .arg(cubin_path)
-            .stdout(Stdio::piped()) // Capture stdout
-            .spawn() // Start the command
-            .expect("failed to execute command");
-
-        let mut section_header_output = Vec::new();
-        let mut symtable_output = Vec::new();
-        // Use BufReader to read the output line by line
-        if let Some(stdout) = output.stdout.take() {
-            let reader = BufReader::new(stdout);
-            let mut is_section_start = false;
-            let mut is_symtab_start = false;
-            // TODO: make the following parsing more robust and elegant
-            for line in reader.lines() {
-                match line {
-                    Ok(line) => {
-                        if is_section_start {
-                            if line.trim() == "" {
-                                is_section_start = false;
-                            } else {
-                                section_header_output.push(line);
-                            }
-                        } else if is_symtab_start {
-                            if line.trim() == "" {
-                                break;
-                            } else {
-                                symtable_output.push(line);
-                            }
-                        } else if line.trim() == "Sections:" {
-                            is_section_start = true;
-                        } else if line.trim() == ".section .symtab" {
-                            is_symtab_start = true;
-                            is_section_start = false;
-                        }
-                    }
-                    Err(e) => eprintln!("Error reading line: {}", e),
-                }
-            }
-        }
-
-        let mut kernel_names = HashSet::new();
-        for line in &section_header_output[1..] {
-            let mut fields = line.split_whitespace();
-            let sh_name = fields.nth(9).unwrap().to_string();
-            if sh_name.starts_with(".text.") {
-                kernel_names.insert(sh_name.strip_prefix(".text.").unwrap().to_string());
-            }
-        }
-
-        // workaround for libcublas internal constants
-        for line in &symtable_output[1..] {
-            let mut fields = line.split_whitespace();
-
-            let opt_st_name = fields.nth(6);
-            let st_name = match opt_st_name {
-                Some(name) => name.to_string(),
-                None => {
-                    warn!(
-                        "Failed to parse symbol table line: {}, {}",
-                        line, cubin_path
-                    );
-                    continue;
-                }
-            };
-
-            if st_name == CUBLAS_INTERNAL_CONSTANT {
-                kernel_names.insert(st_name);
-                break;
-            }
-        }
-
-        output.wait().unwrap();
-
-        kernel_names
+    let cubin = Cubin::parse(payload).map_err(|e| FatbinError::BadCubin {
+        offset,
+        error: e.to_string(),
+    })?;
+    for (caller, callee) in cubin.calls {
+        call_graph.entry(caller).or_default().insert(callee);
     }
+    Ok((cubin.kernels, cubin.arch))
 }
 
-#[cfg(all(test, feature = "gpu"))]
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::elf::elf::Elf;
+    use crate::locator::units::compilation_units;
+    use crate::locator::writer::FatbinBuilder;
+    use crate::utils::mapped::MappedFile;
     use std::path::PathBuf;
 
     fn fixture(name: &str) -> PathBuf {
@@ -308,50 +427,21 @@ mod tests {
             .join(name)
     }
 
-    #[test]
-    fn test_extract_all_cubins() {
-        let _ = env_logger::try_init();
-        let so_path = fixture("libdemo.so");
-        let target_dir = tempdir().unwrap();
-        let cuobjdump_path = "/usr/local/cuda/bin/cuobjdump";
-
-        let cubin_paths = KernelLocator::extract_all_cubins(
-            so_path.to_str().unwrap(),
-            target_dir.path().to_str().unwrap(),
-            cuobjdump_path,
-        );
-
-        assert_eq!(cubin_paths.len(), 4);
-    }
-
-    #[test]
-    fn test_extract_cubin_kernels() {
-        let _ = env_logger::try_init();
-        let cubin_path = fixture("libdemo.3.sm_70.cubin");
-        let cuobjdump_path = "/usr/local/cuda/bin/cuobjdump";
-
-        let kernel_names =
-            KernelLocator::extract_cubin_kernels(cubin_path.to_str().unwrap(), cuobjdump_path);
-
-        assert_eq!(kernel_names.len(), 2);
-        assert!(kernel_names.contains(&"_Z12matrixMulGPUPiS_S_iii".to_string()));
-        assert!(kernel_names.contains(&"_Z16setScalarItemGPUiPiii".to_string()));
-    }
-
     #[test]
     fn test_get_element_span() {
         let _ = env_logger::try_init();
         let so_path = fixture("libdemo.so");
-        let cuobjdump_path = "/usr/local/cuda/bin/cuobjdump";
         let gpu_code_start_offset = 0x948d0;
         let gpu_code_size = 0x63e0;
 
+        let so_data = MappedFile::open(so_path.to_str().unwrap()).unwrap();
         let locator = KernelLocator::new(
             so_path.to_str().unwrap(),
-            gpu_code_start_offset,
-            gpu_code_size,
-            cuobjdump_path,
-        );
+            &so_data,
+            &[(gpu_code_start_offset, gpu_code_size)],
+            &HashMap::new(),
+        )
+        .unwrap();
 
         // region 0, element 0
         let span = locator.get_element_span(0, 0);
@@ -374,16 +464,17 @@ mod tests {
     fn get_element_kernels() {
         let _ = env_logger::try_init();
         let so_path = fixture("libdemo.so");
-        let cuobjdump_path = "/usr/local/cuda/bin/cuobjdump";
         let gpu_code_start_offset = 0x948d0;
         let gpu_code_size = 0x63e0;
 
+        let so_data = MappedFile::open(so_path.to_str().unwrap()).unwrap();
         let locator = KernelLocator::new(
             so_path.to_str().unwrap(),
-            gpu_code_start_offset,
-            gpu_code_size,
-            cuobjdump_path,
-        );
+            &so_data,
+            &[(gpu_code_start_offset, gpu_code_size)],
+            &HashMap::new(),
+        )
+        .unwrap();
 
         let kernels = locator.get_element_kernels(0, 0);
         assert_eq!(kernels.len(), 0);
@@ -402,23 +493,207 @@ mod tests {
         assert!(kernels.contains(&"_Z16setScalarItemGPUiPiii".to_string()));
     }
 
+    #[test]
+    fn test_compressed_element_kernels() {
+        let _ = env_logger::try_init();
+        let so_path = fixture("libcompressed.so");
+        let so_data = std::fs::read(&so_path).unwrap();
+        let locator = KernelLocator::new(
+            so_path.to_str().unwrap(),
+            &so_data,
+            &[(0x440, 0x4328)],
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        // the cubins are decompressed to list their kernels, the spans cover the compressed payloads
+        for j in 0..2 {
+            let kernels = locator.get_element_kernels(0, j);
+            assert_eq!(kernels.len(), 2);
+            assert!(kernels.contains("_Z12matrixMulGPUPiS_S_iii"));
+            assert!(kernels.contains("_Z16setScalarItemGPUiPiii"));
+        }
+        let span = locator.get_element_span(0, 0);
+        assert_eq!(
+            (span.start, span.end),
+            (0x440 + 16 + 72, 0x440 + 16 + 72 + 5520)
+        );
+    }
+
+    // a fatbin region with the sm_70 cubin of demo.cu and a compute_75 PTX element
+    fn ptx_region(ptx: &str) -> Vec<u8> {
+        let cubin = std::fs::read(fixture("libdemo.3.sm_70.cubin")).unwrap();
+        let mut builder = FatbinBuilder::new();
+        builder.add_cubin(70, &cubin);
+        builder.add_ptx(75, ptx);
+        builder.finish()
+    }
+
+    #[test]
+    fn test_ptx_element_kernels() {
+        let _ = env_logger::try_init();
+        let ptx = ".version 8.5\n.target sm_75\n.address_size 64\n\n\
+            .visible .entry _Z12matrixMulGPUPiS_S_iii(\n)\n{\n\tret;\n}\n";
+        let data = ptx_region(ptx);
+        let locator =
+            KernelLocator::new("ptx.so", &data, &[(0, data.len() as u64)], &HashMap::new())
+                .unwrap();
+        assert_eq!(locator.get_element_kernels(0, 0).len(), 2);
+        let kernels = locator.get_element_kernels(0, 1);
+        assert_eq!(kernels.len(), 1);
+        assert!(kernels.contains("_Z12matrixMulGPUPiS_S_iii"));
+
+        // the sm_70 SASS runs on 7.5, so the driver never compiles the PTX there
+        let detected: HashSet<String> = ["_Z12matrixMulGPUPiS_S_iii".to_string()].into();
+        let elements = locator.locate_elements(&detected, 75);
+        assert_eq!(elements[1].header.kind, ElementKind::Ptx);
+        assert_eq!(elements[1].arch, Arch::generic(75));
+        assert_eq!(elements[1].kernels, vec!["_Z12matrixMulGPUPiS_S_iii"]);
+        let deletable: Vec<bool> = elements.iter().map(|e| e.deletable).collect();
+        assert_eq!(deletable, vec![false, true]);
+
+        // on 8.0 no SASS runs, the PTX is compiled just in time and kept if one of its entries is used
+        let deletable: Vec<bool> = locator
+            .locate_elements(&detected, 80)
+            .iter()
+            .map(|e| e.deletable)
+            .collect();
+        assert_eq!(deletable, vec![true, false]);
+        assert!(locator.locate_removed_kernels(&detected, 80).is_empty());
+
+        // and removed like a cubin otherwise
+        let detected: HashSet<String> = ["_Z16setScalarItemGPUiPiii".to_string()].into();
+        let deletable: Vec<bool> = locator
+            .locate_elements(&detected, 80)
+            .iter()
+            .map(|e| e.deletable)
+            .collect();
+        assert_eq!(deletable, vec![true, true]);
+        let removed = locator.locate_removed_kernels(&detected, 80);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].mangled, "_Z12matrixMulGPUPiS_S_iii");
+        let deletable: Vec<bool> = locator
+            .locate_elements(&detected, 70)
+            .iter()
+            .map(|e| e.deletable)
+            .collect();
+        assert_eq!(deletable, vec![false, true]);
+    }
+
+    #[test]
+    fn test_reachable_kernels() {
+        let _ = env_logger::try_init();
+        // the kernel launched by calls.sm_70.cubin is defined in the cubin of the demo, with relocatable device code
+        let calls = std::fs::read(fixture("calls.sm_70.cubin")).unwrap();
+        let demo = std::fs::read(fixture("libdemo.3.sm_70.cubin")).unwrap();
+        let mut builder = FatbinBuilder::new();
+        builder.add_cubin(70, &calls);
+        builder.add_region();
+        builder.add_cubin(70, &demo);
+        let data = builder.finish();
+        let locator =
+            KernelLocator::new("rdc.so", &data, &[(0, data.len() as u64)], &HashMap::new())
+                .unwrap();
+
+        let detected: HashSet<String> = ["_Z6launchv".to_string()].into();
+        assert!(locator
+            .locate_deletable_file_spans(&detected, 70)
+            .is_empty());
+        let reachable: Vec<String> = locator
+            .locate_reachable_kernels(&detected)
+            .into_iter()
+            .map(|k| k.mangled)
+            .collect();
+        assert_eq!(
+            reachable,
+            vec!["_Z16setScalarItemGPUiPiii", "_Z5childv", "_Z6squarei"]
+        );
+
+        // nothing reaches the launching kernel from the ones it launches
+        let detected: HashSet<String> = ["_Z5childv".to_string()].into();
+        let elements = locator.locate_elements(&detected, 70);
+        let deletable: Vec<bool> = elements.iter().map(|e| e.deletable).collect();
+        assert_eq!(deletable, vec![false, true]);
+        let reachable = locator.locate_reachable_kernels(&detected);
+        assert_eq!(reachable.len(), 1);
+        assert_eq!(reachable[0].mangled, "_Z6squarei");
+        assert!(locator.locate_reachable_kernels(&HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn test_module_kernels() {
+        let _ = env_logger::try_init();
+        let so_path = fixture("libdemo.3.sm_70.cubin");
+        let data = std::fs::read(&so_path).unwrap();
+        let locator =
+            KernelLocator::from_module(so_path.to_str().unwrap(), &data, ElementKind::Cubin)
+                .unwrap();
+        let detected: HashSet<String> = ["_Z12matrixMulGPUPiS_S_iii".to_string()].into();
+
+        // a used cubin is kept whole, on GPUs its SASS runs on
+        let elements = locator.locate_elements(&detected, 75);
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].arch, Arch::generic(70));
+        assert_eq!(
+            elements[0].kernels,
+            vec!["_Z12matrixMulGPUPiS_S_iii", "_Z16setScalarItemGPUiPiii"]
+        );
+        assert!(!elements[0].deletable);
+        assert!(locator
+            .locate_deletable_file_spans(&detected, 75)
+            .is_empty());
+
+        // an unused cubin, or one the driver cannot load, is deleted as a whole
+        for (detected, capability) in [(HashSet::new(), 75), (detected, 80)] {
+            let spans = locator.locate_deletable_file_spans(&detected, capability);
+            assert_eq!(spans.len(), 1);
+            assert_eq!((spans[0].start, spans[0].end), (0, data.len() as u64));
+            let usage = locator.locate_unit_usage(&detected, capability);
+            assert_eq!(
+                (usage[0].kept_bytes, usage[0].removed_bytes),
+                (0, data.len() as u64)
+            );
+        }
+        assert_eq!(locator.locate_removed_kernels(&HashSet::new(), 75).len(), 2);
+
+        // PTX is compiled for any later GPU
+        let ptx = b".version 8.5\n.target sm_75\n.address_size 64\n\n.visible .entry _Z4stepv()\n{\n\tret;\n}\n";
+        let locator = KernelLocator::from_module("step.ptx", ptx, ElementKind::Ptx).unwrap();
+        let detected: HashSet<String> = ["_Z4stepv".to_string()].into();
+        assert!(locator
+            .locate_deletable_file_spans(&detected, 90)
+            .is_empty());
+        assert_eq!(locator.locate_deletable_file_spans(&detected, 70).len(), 1);
+
+        assert_eq!(
+            KernelLocator::from_module("step.ptx", b".version 8.5\n", ElementKind::Ptx)
+                .err()
+                .unwrap(),
+            FatbinError::UnknownArch(0)
+        );
+        assert!(KernelLocator::from_module("bad.cubin", b"\x7fELF", ElementKind::Cubin).is_err());
+    }
+
     #[test]
     fn test_get_deletable_file_spans() {
         let _ = env_logger::try_init();
         let so_path = fixture("libdemo.so");
-        let cuobjdump_path = "/usr/local/cuda/bin/cuobjdump";
         let gpu_code_start_offset = 0x948d0;
         let gpu_code_size = 0x63e0;
         let detected_kernels: HashSet<String> = vec!["_Z12matrixMulGPUPiS_S_iii"]
             .into_iter()
             .map(String::from)
             .collect();
+        let so_data = MappedFile::open(so_path.to_str().unwrap()).unwrap();
+        let elf = Elf::new(&so_data).unwrap();
+        let units = compilation_units(&elf.find_fatbin_symbols().unwrap());
         let locator = KernelLocator::new(
             so_path.to_str().unwrap(),
-            gpu_code_start_offset,
-            gpu_code_size,
-            cuobjdump_path,
-        );
+            &so_data,
+            &[(gpu_code_start_offset, gpu_code_size)],
+            &units,
+        )
+        .unwrap();
 
         let deletable_spans = locator.locate_deletable_file_spans(&detected_kernels, 75);
 
@@ -436,6 +711,35 @@ mod tests {
             (0x95098, 0x97f80)
         );
 
+        // the elements of the target capability have both kernels, one of them detected
+        assert!(locator
+            .locate_removed_kernels(&detected_kernels, 75)
+            .is_empty());
+        let removed_kernels = locator.locate_removed_kernels(&HashSet::new(), 75);
+        assert_eq!(removed_kernels.len(), 2);
+        assert_eq!(removed_kernels[0].mangled, "_Z12matrixMulGPUPiS_S_iii");
+        assert_eq!(
+            removed_kernels[1].to_string(),
+            "setScalarItemGPU(int, int*, int, int)"
+        );
+
+        let elements = locator.locate_elements(&detected_kernels, 75);
+        assert_eq!(elements.len(), 4);
+        assert!(elements.iter().all(|e| e.header.kind == ElementKind::Cubin));
+        let deletable: Vec<bool> = elements.iter().map(|e| e.deletable).collect();
+        assert_eq!(deletable, vec![true, true, true, false]);
+
+        // the first region comes from the device link step, the second from demo.cu
+        let usage = locator.locate_unit_usage(&detected_kernels, 75);
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].source_file.as_deref(), Some("demo.cu"));
+        assert_eq!(
+            (usage[0].kept_bytes, usage[0].removed_bytes),
+            (11656, 12008)
+        );
+        assert_eq!(usage[1].source_file.as_deref(), Some("link.stub"));
+        assert_eq!((usage[1].kept_bytes, usage[1].removed_bytes), (160, 1744));
+
         let deletable_spans = locator.locate_deletable_file_spans(&detected_kernels, 70);
         assert_eq!(deletable_spans.len(), 3);
         assert_eq!(
@@ -450,5 +754,12 @@ mod tests {
             (deletable_spans[2].start, deletable_spans[2].end),
             (0x97fc8, 0x9acb0)
         );
+
+        // neither sm_70 nor sm_75 SASS runs on 8.0 and there is no PTX, the driver loads nothing
+        let deletable_spans = locator.locate_deletable_file_spans(&detected_kernels, 80);
+        assert_eq!(deletable_spans.len(), 4);
+        assert!(locator
+            .locate_removed_kernels(&HashSet::new(), 80)
+            .is_empty());
     }
 }
@@ -1,2 +1,9 @@
-mod gpu_code;
+pub mod compat;
+pub mod cubin;
+pub mod decompress;
+pub mod device_files;
+pub mod gpu_code;
 pub mod locator;
+pub mod ptx;
+pub mod units;
+pub mod writer;
@@ -0,0 +1,175 @@
+use super::compat::Arch;
+use std::collections::HashSet;
+
+/// Get the entry points of a PTX module, i.e., the names of its .entry kernels and .visible .func functions.
+///
+/// The payload is PTX text, NUL-terminated and padded in fatbins. Declarations such as .extern .func
+/// prototypes name functions of other modules and are not listed.
+pub fn ptx_entries(ptx: &[u8]) -> HashSet<String> {
+    let end = ptx.iter().position(|&b| b == 0).unwrap_or(ptx.len());
+    let text = strip_comments(&String::from_utf8_lossy(&ptx[..end]));
+    let tokens = tokenize(&text);
+
+    let mut entries = HashSet::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let is_entry = tokens[i] == ".entry";
+        let is_function = tokens[i] == ".func" && i > 0 && tokens[i - 1] == ".visible";
+        i += 1;
+        if !is_entry && !is_function {
+            continue;
+        }
+        // a function may declare its return parameters before its name
+        if is_function && tokens.get(i) == Some(&"(") {
+            while i < tokens.len() && tokens[i] != ")" {
+                i += 1;
+            }
+            i += 1;
+        }
+        if let Some(name) = tokens.get(i).filter(|t| is_identifier(t)) {
+            entries.insert(name.to_string());
+        }
+    }
+    entries
+}
+
+/// Get the target architecture of a PTX module from its .target directive, e.g., sm_90a.
+///
+/// Further targets of the directive, e.g., texmode_independent, are ignored.
+pub fn ptx_target(ptx: &[u8]) -> Option<Arch> {
+    let end = ptx.iter().position(|&b| b == 0).unwrap_or(ptx.len());
+    let text = strip_comments(&String::from_utf8_lossy(&ptx[..end]));
+    let tokens = tokenize(&text);
+    let i = tokens.iter().position(|&t| t == ".target")?;
+    Arch::parse(tokens.get(i + 1)?)
+}
+
+// replace // and /* */ comments by a space, PTX has no string literals that could contain them
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    loop {
+        let line = rest.find("//");
+        let block = rest.find("/*");
+        let (start, terminator) = match (line, block) {
+            (Some(l), Some(b)) if b < l => (b, "*/"),
+            (Some(l), _) => (l, "\n"),
+            (None, Some(b)) => (b, "*/"),
+            (None, None) => break,
+        };
+        stripped.push_str(&rest[..start]);
+        stripped.push(' ');
+        rest = match rest[start + 2..].find(terminator) {
+            Some(end) => &rest[start + 2 + end + terminator.len()..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+// split into words, i.e., directives and identifiers, and single punctuation characters
+fn tokenize(text: &str) -> Vec<&str> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '%' | '.');
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if is_word(c) {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(s) = start.take() {
+            tokens.push(&text[s..i]);
+        }
+        if !c.is_whitespace() {
+            tokens.push(&text[i..i + c.len_utf8()]);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&text[s..]);
+    }
+    tokens
+}
+
+// PTX identifiers start with a letter, or with _, $ or % followed by more characters
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => true,
+        Some('_' | '$' | '%') => chars.next().is_some(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ptx_entries() {
+        let ptx = b"//
+// Generated by NVIDIA NVVM Compiler
+//
+.version 8.5
+.target sm_75
+.address_size 64
+
+.extern .func (.param .b32 func_retval0) vprintf(.param .b64 vprintf_param_0, .param .b64 vprintf_param_1);
+.global .align 4 .u32 counter;
+
+.visible .func (.param .b32 func_retval0) _Z6squarei(
+\t.param .b32 _Z6squarei_param_0
+)
+{
+\tret;
+}
+.func _Z6helperv()
+{
+\tret;
+}
+/* .entry _Z7retiredv() */
+.visible .entry _Z12matrixMulGPUPiS_S_iii(
+\t.param .u64 _Z12matrixMulGPUPiS_S_iii_param_0
+)
+.maxntid 256, 1, 1
+{
+\tret;
+}
+.weak .entry _Z16setScalarItemGPUiPiii(.param .u32 p0) { ret; } // .entry _Z4nextv
+.visible .func _Z4noopv()
+{
+\tret;
+}
+\0\0\0\0";
+        let mut entries: Vec<String> = ptx_entries(ptx).into_iter().collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                "_Z12matrixMulGPUPiS_S_iii",
+                "_Z16setScalarItemGPUiPiii",
+                "_Z4noopv",
+                "_Z6squarei",
+            ]
+        );
+
+        assert!(ptx_entries(b"").is_empty());
+        assert!(ptx_entries(b".version 8.5\n.target sm_75\n.entry").is_empty());
+        assert!(ptx_entries(b".entry /* unterminated").is_empty());
+    }
+
+    #[test]
+    fn test_ptx_target() {
+        assert_eq!(
+            ptx_target(b"// .target sm_50\n.version 8.5\n.target sm_75\n.address_size 64\n\0"),
+            Some(Arch::generic(75))
+        );
+        assert_eq!(
+            ptx_target(b".version 8.7\n.target sm_100f, texmode_independent\n")
+                .map(|a| a.to_string()),
+            Some("100f".to_string())
+        );
+        assert_eq!(ptx_target(b".version 8.5\n.address_size 64\n"), None);
+        assert_eq!(ptx_target(b".target"), None);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::elf::elf::FatbinSymbol;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Bytes kept and removed of the fatbin regions of one compilation unit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnitUsage {
+    /// The source file of the compilation unit, None for regions of unknown origin
+    pub source_file: Option<String>,
+    pub regions: usize,
+    pub kept_bytes: u64,
+    pub removed_bytes: u64,
+}
+
+/// Get the source file a compilation unit is named after.
+///
+/// nvcc names the intermediate files of a compilation unit after its source file, e.g.,
+/// tmpxft_000040a0_00000000-6_demo.cudafe1.cpp for demo.cu, and mangles the same name into the
+/// __fatbinwrap_* symbols, e.g., __fatbinwrap_38_tmpxft_000040a0_00000000_6_demo_cpp1_ii_eb0e2c81.
+/// Since dots are mangled to underscores, demo_kernels.cu and demo.kernels.cu cannot be told apart there.
+/// Other names, e.g., link.stub of the device link step, are returned unchanged.
+pub fn source_file(name: &str) -> String {
+    let unmangled = match name.strip_prefix("__fatbinwrap_") {
+        Some(rest) => rest
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .trim_start_matches('_'),
+        None => name,
+    };
+    let Some(rest) = unmangled.strip_prefix("tmpxft_") else {
+        return name.to_string();
+    };
+    // <process id>_<counter>-<stage>_<base><suffix>, with '-' mangled to '_' in symbols
+    let parts: Vec<&str> = rest.splitn(4, ['_', '-']).collect();
+    if parts.len() != 4
+        || !parts[..3]
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return name.to_string();
+    }
+    let base = parts[3];
+    let end = [".cudafe1.", "_cudafe1_", ".cpp1.", "_cpp1_"]
+        .iter()
+        .filter_map(|suffix| base.rfind(suffix))
+        .max()
+        .unwrap_or(base.len());
+    if end == 0 {
+        return name.to_string();
+    }
+    format!("{}.cu", &base[..end])
+}
+
+/// Map the file offset of each fatbin region to the source file of its compilation unit.
+///
+/// A symbol names the unit through the STT_FILE symbol it is scoped under, or through its own name
+/// if it is a __fatbinwrap_* symbol outside any file. The first symbol of a region is used.
+pub fn compilation_units(symbols: &[FatbinSymbol]) -> HashMap<u64, String> {
+    let mut units = HashMap::new();
+    for symbol in symbols.iter() {
+        let unit = match &symbol.file {
+            Some(file) => source_file(file),
+            None if symbol.name.starts_with("__fatbinwrap_") => source_file(&symbol.name),
+            None => continue,
+        };
+        units.entry(symbol.offset).or_insert(unit);
+    }
+    units
+}
+
+/// Sum up the bytes kept and removed per compilation unit, the units with the most removed bytes first.
+/// `regions` yields the source file, size and removed bytes of each fatbin region.
+pub fn unit_usage<'a, I>(regions: I) -> Vec<UnitUsage>
+where
+    I: IntoIterator<Item = (Option<&'a str>, u64, u64)>,
+{
+    let mut usage: Vec<UnitUsage> = vec![];
+    for (source_file, size, removed) in regions {
+        let index = match usage
+            .iter()
+            .position(|u| u.source_file.as_deref() == source_file)
+        {
+            Some(index) => index,
+            None => {
+                usage.push(UnitUsage {
+                    source_file: source_file.map(str::to_string),
+                    regions: 0,
+                    kept_bytes: 0,
+                    removed_bytes: 0,
+                });
+                usage.len() - 1
+            }
+        };
+        usage[index].regions += 1;
+        usage[index].kept_bytes += size.saturating_sub(removed);
+        usage[index].removed_bytes += removed;
+    }
+    usage.sort_by(|a, b| {
+        b.removed_bytes
+            .cmp(&a.removed_bytes)
+            .then_with(|| a.source_file.cmp(&b.source_file))
+    });
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::elf::Elf;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_source_file() {
+        for (name, expected) in [
+            ("tmpxft_000040a0_00000000-6_demo.cudafe1.cpp", "demo.cu"),
+            ("tmpxft_000040a0_00000000-6_demo.cpp1.ii", "demo.cu"),
+            (
+                "__fatbinwrap_38_tmpxft_000040a0_00000000_6_demo_cpp1_ii_eb0e2c81",
+                "demo.cu",
+            ),
+            (
+                "__fatbinwrap_46_tmpxft_00001a2b_00000001_7_batch_norm_cpp1_ii_1f2e3d4c",
+                "batch_norm.cu",
+            ),
+            ("link.stub", "link.stub"),
+            (
+                "tmpxft_nothex_00000000-6_demo.cudafe1.cpp",
+                "tmpxft_nothex_00000000-6_demo.cudafe1.cpp",
+            ),
+            ("tmpxft_000040a0_00000000-6_", "tmpxft_000040a0_00000000-6_"),
+        ] {
+            assert_eq!(source_file(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_compilation_units() {
+        let data = std::fs::read(fixture("libunits.so")).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        let units = compilation_units(&elf.find_fatbin_symbols().unwrap());
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[&0x440], "link.stub");
+        assert_eq!(units[&0x1350], "matmul.cu");
+
+        // a wrapper outside any file is named after itself
+        let symbols = [FatbinSymbol {
+            offset: 0x440,
+            name: "__fatbinwrap_38_tmpxft_000040a0_00000000_6_demo_cpp1_ii_eb0e2c81".to_string(),
+            file: None,
+        }];
+        assert_eq!(compilation_units(&symbols)[&0x440], "demo.cu");
+    }
+
+    #[test]
+    fn test_unit_usage() {
+        let usage = unit_usage([
+            (Some("a.cu"), 100, 40),
+            (None, 50, 0),
+            (Some("b.cu"), 80, 80),
+            (Some("a.cu"), 60, 50),
+        ]);
+        assert_eq!(
+            usage,
+            vec![
+                UnitUsage {
+                    source_file: Some("a.cu".to_string()),
+                    regions: 2,
+                    kept_bytes: 70,
+                    removed_bytes: 90,
+                },
+                UnitUsage {
+                    source_file: Some("b.cu".to_string()),
+                    regions: 1,
+                    kept_bytes: 0,
+                    removed_bytes: 80,
+                },
+                UnitUsage {
+                    source_file: None,
+                    regions: 1,
+                    kept_bytes: 50,
+                    removed_bytes: 0,
+                },
+            ]
+        );
+    }
+}
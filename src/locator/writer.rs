@@ -0,0 +1,300 @@
+use super::gpu_code::{
+    ElementFlag, ElementFlags, ElementHeader, ElementKind, GPUCode, RegionHeader,
+};
+use crate::elf::elf::FATBIN_MAGIC;
+
+// nvcc writes element headers of 72 bytes, the fields past the uncompressed size are zero
+const ELEMENT_HEADER_SIZE: usize = 72;
+const ELEMENT_HEADER_VERSION: u16 = 0x101;
+// element headers and payloads are padded to 8 bytes, so that every region stays aligned
+const ALIGN: usize = 8;
+
+impl ElementHeader {
+    /// Create the header of an uncompressed element of 64-bit code built on Linux, e.g., for `FatbinBuilder`
+    ///
+    /// `version` is the major and minor code version. The sizes are set by `FatbinBuilder::add_element`.
+    #[allow(dead_code)]
+    pub fn new(kind: ElementKind, capability: u32, version: (u16, u16)) -> ElementHeader {
+        ElementHeader {
+            kind,
+            version: ELEMENT_HEADER_VERSION,
+            offset: 0,
+            size: 0,
+            compressed_size: 0,
+            major_version: version.0,
+            minor_version: version.1,
+            capability,
+            identifier: None,
+            flags: ElementFlags(ElementFlag::Address64.bit() | ElementFlag::Linux.bit()),
+            options: None,
+            uncompressed_size: 0,
+        }
+    }
+
+    /// Encode the header followed by its identifier and options, the inverse of `parse`.
+    ///
+    /// The header size is recomputed, `offset` is ignored. Bytes `parse` does not decode are written as zero.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0u8; ELEMENT_HEADER_SIZE];
+        for (field, string) in [(32, &self.identifier), (48, &self.options)] {
+            let Some(string) = string else {
+                continue;
+            };
+            let string_offset = data.len() as u32;
+            data[field..field + 4].copy_from_slice(&string_offset.to_le_bytes());
+            data[field + 4..field + 8].copy_from_slice(&(string.len() as u32).to_le_bytes());
+            data.extend(string.as_bytes());
+            data.push(0);
+            data.resize(data.len().next_multiple_of(ALIGN), 0);
+        }
+
+        let kind = match self.kind {
+            ElementKind::Ptx => 1,
+            ElementKind::Cubin => 2,
+            ElementKind::LtoIr => 4,
+            ElementKind::Other(kind) => kind,
+        };
+        let header_size = data.len() as u32;
+        data[0..2].copy_from_slice(&kind.to_le_bytes());
+        data[2..4].copy_from_slice(&self.version.to_le_bytes());
+        data[4..8].copy_from_slice(&header_size.to_le_bytes());
+        data[8..16].copy_from_slice(&self.size.to_le_bytes());
+        data[16..20].copy_from_slice(&self.compressed_size.to_le_bytes());
+        data[24..26].copy_from_slice(&self.minor_version.to_le_bytes());
+        data[26..28].copy_from_slice(&self.major_version.to_le_bytes());
+        data[28..32].copy_from_slice(&self.capability.to_le_bytes());
+        data[40..48].copy_from_slice(&self.flags.0.to_le_bytes());
+        data[56..64].copy_from_slice(&self.uncompressed_size.to_le_bytes());
+        data
+    }
+}
+
+impl GPUCode {
+    /// Write the regions back to fatbin bytes, e.g., after removing elements or regions.
+    ///
+    /// `gpu_code_data` is the GPU code data the regions were parsed from, payloads are copied from it as stored,
+    /// compressed payloads stay compressed. Region sizes and element offsets are recomputed.
+    pub fn to_bytes(&self, gpu_code_data: &[u8]) -> Vec<u8> {
+        let mut builder = FatbinBuilder::new();
+        for region in self.regions(gpu_code_data, 0) {
+            builder.add_region();
+            for element in region.elements() {
+                let (header, stored) = (element.header(), element.stored);
+                let payload = if header.is_compressed() {
+                    &stored[..(header.compressed_size as usize).min(stored.len())]
+                } else {
+                    stored
+                };
+                builder.add_element(header.clone(), payload);
+            }
+        }
+        builder.finish()
+    }
+}
+
+/// Assembles a fatbin from element payloads, region by region
+///
+/// Each region gets a header with its size, each element a header with its payload size, and everything is
+/// padded to 8 bytes like nvcc does. The result can be parsed by `GPUCode::new`.
+pub struct FatbinBuilder {
+    regions: Vec<Vec<u8>>,
+}
+
+impl FatbinBuilder {
+    /// Create a builder without any region
+    pub fn new() -> FatbinBuilder {
+        FatbinBuilder { regions: vec![] }
+    }
+
+    /// Start a new region, the elements added next go into it
+    pub fn add_region(&mut self) {
+        self.regions.push(vec![]);
+    }
+
+    /// Add an element with the given header and payload to the current region, starting one if there is none
+    ///
+    /// A compressed payload, by the header, is stored as given and must decompress to `uncompressed_size`.
+    /// The header size, payload size and compressed size of the header are set from the payload.
+    pub fn add_element(&mut self, mut header: ElementHeader, payload: &[u8]) {
+        header.size = payload.len().next_multiple_of(ALIGN) as u64;
+        if header.is_compressed() {
+            header.compressed_size = payload.len() as u32;
+        }
+        let header_data = header.to_bytes();
+        if self.regions.is_empty() {
+            self.add_region();
+        }
+        let region = self.regions.last_mut().unwrap();
+        region.extend(header_data);
+        region.extend(payload);
+        region.resize(region.len().next_multiple_of(ALIGN), 0);
+    }
+
+    /// Add a cubin for the given compute capability, e.g., 70 for sm_70, to the current region
+    #[allow(dead_code)]
+    pub fn add_cubin(&mut self, capability: u32, cubin: &[u8]) {
+        // the code version of cubins as nvcc writes it
+        self.add_element(
+            ElementHeader::new(ElementKind::Cubin, capability, (1, 7)),
+            cubin,
+        );
+    }
+
+    /// Add PTX for the given virtual architecture, e.g., 70 for compute_70, to the current region
+    ///
+    /// The code version is taken from the .version directive, the payload is NUL-terminated.
+    #[allow(dead_code)]
+    pub fn add_ptx(&mut self, capability: u32, ptx: &str) {
+        let version = ptx
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(".version"))
+            .and_then(|version| version.trim().split_once('.'))
+            .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+            .unwrap_or((0, 0));
+        let mut payload = ptx.as_bytes().to_vec();
+        payload.push(0);
+        self.add_element(
+            ElementHeader::new(ElementKind::Ptx, capability, version),
+            &payload,
+        );
+    }
+
+    /// Return the fatbin bytes
+    pub fn finish(self) -> Vec<u8> {
+        let mut data = vec![];
+        for elements in self.regions {
+            data.extend(FATBIN_MAGIC.to_le_bytes());
+            data.extend(1u16.to_le_bytes());
+            data.extend((RegionHeader::size() as u16).to_le_bytes());
+            data.extend((elements.len() as u64).to_le_bytes());
+            data.extend(elements);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locator::cubin::Cubin;
+    use crate::locator::ptx::ptx_entries;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let _ = env_logger::try_init();
+        // the .nv_fatbin of libdemo.so, the identifiers of libunits.so and the compressed elements of libcompressed.so
+        for (name, start, size) in [
+            ("libdemo.so", 0x948d0, 0x63e0),
+            ("libunits.so", 0xbb0, 0x788),
+            ("libcompressed.so", 0x440, 0x4328),
+        ] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let gpu_code_data = &data[start..start + size];
+            let gpu_code = GPUCode::new(gpu_code_data).unwrap();
+            assert!(
+                gpu_code.to_bytes(gpu_code_data) == gpu_code_data,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_remove_element() {
+        let _ = env_logger::try_init();
+        let data = std::fs::read(fixture("libdemo.so")).unwrap();
+        let gpu_code_data = &data[0x948d0..0x948d0 + 0x63e0];
+        let mut gpu_code = GPUCode::new(gpu_code_data).unwrap();
+
+        // drop the sm_70 cubin of demo.cu, the region and the elements after it move up
+        let removed = gpu_code.regions[1].elements.remove(0);
+        let written = gpu_code.to_bytes(gpu_code_data);
+        let removed_size = removed.header.offset as u64 + removed.header.size;
+        assert_eq!(written.len() as u64, 0x63e0 - removed_size);
+        let rewritten = GPUCode::new(&written).unwrap();
+        assert_eq!(rewritten.regions.len(), 2);
+        assert_eq!(rewritten.regions[1].header.fat_size, 0x5c60 - removed_size);
+        assert_eq!(rewritten.regions[1].elements.len(), 1);
+        let (kept, original) = (
+            &rewritten.regions[1].elements[0],
+            &gpu_code.regions[1].elements[0],
+        );
+        assert_eq!(kept.header, original.header);
+        assert_eq!(kept.offset, 0x770 + 16);
+        assert_eq!(
+            kept.payload(&written).unwrap(),
+            original.payload(gpu_code_data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let _ = env_logger::try_init();
+        let cubin = std::fs::read(fixture("libdemo.3.sm_70.cubin")).unwrap();
+        let ptx = ".version 8.5\n.target sm_75\n.address_size 64\n\n.visible .entry _Z4stepv()\n{\n\tret;\n}\n";
+        let mut builder = FatbinBuilder::new();
+        builder.add_cubin(70, &cubin);
+        builder.add_ptx(75, ptx);
+        builder.add_region();
+        let mut header = ElementHeader::new(ElementKind::Cubin, 80, (1, 7));
+        header.identifier = Some("kernels/scale.cu".to_string());
+        header.options = Some("-O3".to_string());
+        header.flags.0 |= ElementFlag::Compressed.bit();
+        header.uncompressed_size = 5;
+        // an LZ4 block of "hello"
+        builder.add_element(header, b"\x50hello");
+        let data = builder.finish();
+
+        let gpu_code = GPUCode::new(&data).unwrap();
+        assert_eq!(gpu_code.regions.len(), 2);
+        let elements = &gpu_code.regions[0].elements;
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].header.kind, ElementKind::Cubin);
+        assert_eq!(elements[0].header.capability, 70);
+        assert_eq!(elements[0].header.offset, 72);
+        assert_eq!(
+            elements[0].header.size,
+            cubin.len().next_multiple_of(8) as u64
+        );
+        let payload = elements[0].payload(&data).unwrap();
+        assert_eq!(&payload[..cubin.len()], &cubin[..]);
+        assert_eq!(Cubin::parse(&payload).unwrap().kernels.len(), 2);
+
+        assert_eq!(elements[1].header.kind, ElementKind::Ptx);
+        assert_eq!(
+            (
+                elements[1].header.capability,
+                elements[1].header.major_version,
+                elements[1].header.minor_version
+            ),
+            (75, 8, 5)
+        );
+        let entries = ptx_entries(&elements[1].payload(&data).unwrap());
+        assert!(entries.contains("_Z4stepv"));
+
+        let element = &gpu_code.regions[1].elements[0];
+        assert_eq!(
+            element.header.identifier.as_deref(),
+            Some("kernels/scale.cu")
+        );
+        assert_eq!(element.header.options.as_deref(), Some("-O3"));
+        assert_eq!(element.header.offset, 72 + 24 + 8);
+        assert_eq!(
+            (element.header.size, element.header.compressed_size),
+            (8, 6)
+        );
+        assert_eq!(&element.payload(&data).unwrap()[..], b"hello");
+
+        // the header encodes exactly what it decodes from
+        let header_data = element.header.to_bytes();
+        assert_eq!(ElementHeader::parse(&header_data), element.header);
+    }
+}
@@ -1,12 +1,21 @@
-use clap::{Parser, Subcommand};
-use log::{debug, info, warn};
+use clap::{Parser, Subcommand, ValueEnum};
+use log::{debug, error, info, warn};
 use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::env;
 
 mod tracer;
-use crate::elf::elf::ELF64;
+use crate::elf::archive::{list_objects, open_object, ObjectPath};
+use crate::elf::editor::DynamicEditor;
+use crate::elf::elf::{Elf, HostIsa};
+use crate::elf::identity::LibraryIdentity;
+use crate::elf::resolver::DependencyResolver;
+use crate::locator::device_files::{find_device_files, DeviceFileKind};
+use crate::locator::gpu_code::{ElementKind, GPUCode};
 use crate::locator::locator::KernelLocator;
+use crate::locator::units::compilation_units;
 use crate::tracer::tracer::{TraceReport, Tracer};
+use crate::utils::report_names::{path_hash, report_names};
 use crate::utils::utils::get_compute_capabilities;
 
 mod elf;
@@ -47,13 +56,29 @@ enum Command {
         #[arg(short, long)]
         report_path: String,
 
-        /// cuobjdump path, default to /usr/local/cuda/bin/cuobjdump
-        #[arg(short, long, default_value = "/usr/local/cuda/bin/cuobjdump")]
-        cuobjdump_path: String,
+        /// Deprecated and ignored, cubins are parsed in-process
+        #[arg(short, long, hide = true)]
+        cuobjdump_path: Option<String>,
 
         /// Output dir to save the located unused device code segments
         #[arg(short, long)]
         output_dir: String,
+
+        /// Additional libraries, ar archives or wheels to locate, e.g., packages not installed yet, archives and
+        /// wheels are expanded to their ELF members. Standalone .fatbin, .cubin and .ptx files, e.g., of Triton or
+        /// NVRTC caches, are located as well, directories are searched for them
+        #[arg(short, long)]
+        input: Vec<String>,
+
+        /// Target compute capability, e.g., 90 for sm_90, defaults to the one recorded in the report, then to
+        /// the GPU of this host
+        #[arg(long)]
+        compute_capability: Option<u32>,
+
+        /// Also save the standalone device code files none of whose code is used to unused_files.json in the
+        /// output dir, e.g., to clear them from caches
+        #[arg(long)]
+        list_unused: bool,
     },
 
     /// Rewrite the unused device code segments to 0x1 in the shared libraries, based on the output of the locate command
@@ -65,6 +90,75 @@ enum Command {
         /// Output dir to save the reconstructed shared libraries
         #[arg(short, long)]
         output_dir: String, // Output dir
+
+        /// Also cut the unused tail of .nv_fatbin off the files, making them smaller on disk
+        #[arg(long)]
+        shrink: bool,
+    },
+
+    /// Edit the DT_NEEDED, DT_RUNPATH and DT_RPATH entries of an executable or shared library, e.g., to load
+    /// reconstructed shared libraries without setting LD_LIBRARY_PATH
+    Patch {
+        /// The executable or shared library to edit
+        #[arg(short, long)]
+        binary_path: String,
+
+        /// The file path to save the edited file, if not set, the file is edited in place
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Set DT_RUNPATH, replacing any DT_RUNPATH and DT_RPATH, e.g., '$ORIGIN/../reconstructed'
+        #[arg(long)]
+        set_runpath: Option<String>,
+
+        /// Set DT_RPATH instead of DT_RUNPATH with --set-runpath, so it also applies to indirect dependencies
+        #[arg(long)]
+        force_rpath: bool,
+
+        /// Remove DT_RUNPATH and DT_RPATH
+        #[arg(long)]
+        remove_runpath: bool,
+
+        /// Add a DT_NEEDED entry after the existing ones
+        #[arg(long)]
+        add_needed: Vec<String>,
+
+        /// Remove a DT_NEEDED entry
+        #[arg(long)]
+        remove_needed: Vec<String>,
+
+        /// Rename a DT_NEEDED entry, given as OLD=NEW
+        #[arg(long)]
+        replace_needed: Vec<String>,
+    },
+
+    /// Resolve the static dependency graph of an executable or shared library, without running it
+    Deps {
+        /// The executable or shared library to resolve
+        #[arg(short, long)]
+        binary_path: String,
+
+        /// The file path to save the dependency graph
+        #[arg(short, long)]
+        output: String,
+
+        /// Output format of the dependency graph
+        #[arg(short, long, value_enum, default_value_t = GraphFormat::Json)]
+        format: GraphFormat,
+
+        /// Tracing report path, if set, loaded shared libraries outside the static graph are reported as dlopen-only
+        #[arg(short, long)]
+        report_path: Option<String>,
+    },
+
+    /// List the fatbins embedded in libraries, ar archives or wheels, without running anything
+    Inventory {
+        /// Libraries, ar archives or wheels, archives and wheels are expanded to their ELF members
+        paths: Vec<String>,
+
+        /// The file path to save the inventory
+        #[arg(short, long)]
+        output: String,
     },
 
     /// A convenient command to run trace and locate sequentially
@@ -77,9 +171,9 @@ enum Command {
         #[arg(short, long, value_parser, num_args = 0.., value_delimiter = ' ')]
         env: Vec<String>,
 
-        /// cuobjdump path, default to /usr/local/cuda/bin/cuobjdump
-        #[arg(short, long, default_value = "/usr/local/cuda/bin/cuobjdump")]
-        cuobjdump_path: String,
+        /// Deprecated and ignored, cubins are parsed in-process
+        #[arg(short, long, hide = true)]
+        cuobjdump_path: Option<String>,
 
         /// Output dir to save the tracing report and located unused device code segments
         #[arg(short, long, default_value = "./nml_workspace")]
@@ -91,9 +185,34 @@ enum Command {
     },
 }
 
+// Warn about the --cuobjdump-path flag, kept so that existing scripts still parse
+fn warn_cuobjdump_path(cuobjdump_path: &Option<String>) {
+    if let Some(path) = cuobjdump_path {
+        warn!(
+            "--cuobjdump-path {} is deprecated and ignored, cubins are parsed without cuobjdump",
+            path
+        );
+    }
+}
+
+/// Output format of the dependency graph of the deps command
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    /// The libraries, their edges and unresolved names, as JSON
+    Json,
+    /// A Graphviz digraph
+    Dot,
+}
+
 // Run the tracer
 fn trace(loader_path: &str, env: &Vec<String>, cmd: &Vec<String>, output: &str) {
-    let tracer = Tracer::new(&loader_path);
+    let tracer = match Tracer::new(loader_path) {
+        Ok(tracer) => tracer,
+        Err(e) => {
+            error!("Failed to parse loader {}: {}", loader_path, e);
+            return;
+        }
+    };
     let mut runtime_env = vec![];
     if env.len() == 0 {
         for (key, value) in env::vars() {
@@ -106,67 +225,533 @@ fn trace(loader_path: &str, env: &Vec<String>, cmd: &Vec<String>, output: &str)
 }
 
 // Run the locator
-fn locate(report_path: &str, cuobjdump_path: &str, output_dir: &str) {
+fn locate(
+    report_path: &str,
+    output_dir: &str,
+    inputs: &[String],
+    compute_capability: Option<u32>,
+    list_unused: bool,
+) {
     let report_file = std::fs::File::open(report_path).unwrap();
     let trace_report: TraceReport = serde_json::from_reader(report_file).unwrap();
     let loaded_sos = trace_report.loaded_sos;
+    let so_identities = trace_report.so_identities;
     let detected_kernels = trace_report.detected_kernels;
-    let compute_capabilities = get_compute_capabilities();
-    if compute_capabilities.len() == 0 {
-        warn!(
-            "No GPU detected or GPU feature not enabled, skip locating unused device code segments"
+    // the report may come from another host, e.g., a Grace Hopper machine analyzed on an x86 build host
+    let mut compute_capabilities = match compute_capability {
+        Some(compute_capability) => vec![compute_capability],
+        None if !trace_report.compute_capabilities.is_empty() => trace_report.compute_capabilities,
+        None => get_compute_capabilities(),
+    };
+    // reports of older versions list every GPU of the host
+    compute_capabilities.sort_unstable();
+    compute_capabilities.dedup();
+    let target_compute_capability = match compute_capabilities[..] {
+        [] => {
+            warn!(
+                "No GPU detected or GPU feature not enabled, skip locating unused device code segments"
+            );
+            return;
+        }
+        [compute_capability] => compute_capability,
+        _ => {
+            error!(
+                "GPUs of compute capabilities {:?} were traced, pass --compute-capability to choose the target",
+                compute_capabilities
+            );
+            return;
+        }
+    };
+    std::fs::create_dir_all(output_dir).unwrap();
+
+    let (device_files, objects) = split_inputs(inputs);
+    let mut so_paths: Vec<String> = loaded_sos.into_iter().collect();
+    so_paths.extend(expand_inputs(&objects));
+    // span files are named by file name, or relative path for files found in directories, objects of the same
+    // name in different places are told apart by path
+    let mut names = report_names(
+        so_paths
+            .iter()
+            .map(|p| (p.as_str(), p.split('/').next_back().unwrap()))
+            .chain(device_files.iter().map(|(p, n)| (p.as_str(), n.as_str()))),
+    );
+    let device_file_names = names.split_off(so_paths.len());
+    for (so_path, name) in so_paths.iter().zip(names) {
+        let Some(name) = name else {
+            warn!(
+                "Skipping {}: listed twice or its span file name is taken",
+                so_path
+            );
+            continue;
+        };
+        // the same mapping backs the identity, the ELF parser and the locator
+        let so_data = match open_object(so_path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Skipping {}: {}", so_path, e);
+                continue;
+            }
+        };
+        let identity = LibraryIdentity::new(&so_data);
+        if let Some(traced) = so_identities.get(so_path) {
+            if *traced != identity {
+                warn!(
+                    "Skipping {}: changed since trace, traced {}, now {}",
+                    so_path, traced, identity
+                );
+                continue;
+            }
+        }
+        let elf = match Elf::new(&so_data) {
+            Ok(elf) => elf,
+            Err(e) => {
+                warn!("Skipping {}: {}", so_path, e);
+                continue;
+            }
+        };
+        debug!(
+            "Parsed {}: {}, ELFCLASS{}, {} endian",
+            so_path,
+            elf.host_isa(),
+            if elf.is_64bit() { 64 } else { 32 },
+            if elf.is_big_endian() { "big" } else { "little" }
+        );
+        if let HostIsa::Other(_) = elf.host_isa() {
+            warn!("{}: {} is not a CUDA host", so_path, elf.host_isa());
+        }
+        let fatbins = match elf.find_fatbins() {
+            Ok(fatbins) => fatbins,
+            Err(e) => {
+                warn!("Skipping {}: {}", so_path, e);
+                continue;
+            }
+        };
+        if fatbins.is_empty() {
+            continue;
+        }
+        for fatbin in fatbins.iter() {
+            if let Ok(addr) = elf.offset_to_addr(fatbin.offset) {
+                debug!(
+                    "{}: fatbin ({:?}) at offset {:#x}, vaddr {:#x}, size {:#x}",
+                    so_path, fatbin.source, fatbin.offset, addr, fatbin.size
+                );
+            }
+        }
+        let gpu_code_spans: Vec<(u64, u64)> = fatbins.iter().map(|f| (f.offset, f.size)).collect();
+        // stripped libraries have no symbols to tell the compilation units apart
+        let units = match elf.find_fatbin_symbols() {
+            Ok(symbols) => compilation_units(&symbols),
+            Err(e) => {
+                warn!("{}: no compilation units: {}", so_path, e);
+                Default::default()
+            }
+        };
+        let locator = match KernelLocator::new(so_path, &so_data, &gpu_code_spans, &units) {
+            Ok(locator) => locator,
+            Err(e) => {
+                warn!("Skipping {}: {}", so_path, e);
+                continue;
+            }
+        };
+        let output_path = format!("{}/{}.json", output_dir, name);
+        write_spans(
+            &output_path,
+            so_path,
+            &identity,
+            &locator,
+            &detected_kernels,
+            target_compute_capability,
         );
-        return;
     }
-    // TODO: support multi capabilities
-    assert_eq!(compute_capabilities.len(), 1);
-    let target_compute_capability = compute_capabilities[0];
-    std::fs::create_dir_all(output_dir).unwrap();
 
-    for so_path in loaded_sos.iter() {
-        let so_data = std::fs::read(so_path).unwrap();
-        let elf = ELF64::new(&so_data);
-        if !elf.has_gpu_code() {
+    // standalone device code is loaded by the application itself, e.g., with cuModuleLoad, and not traced
+    let mut unused_files = vec![];
+    for ((path, _), name) in device_files.iter().zip(device_file_names) {
+        let Some(name) = name else {
+            warn!(
+                "Skipping {}: listed twice or its span file name is taken",
+                path
+            );
             continue;
+        };
+        let data = match open_object(path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        let located = match DeviceFileKind::detect(&data) {
+            Some(DeviceFileKind::Fatbin) => {
+                KernelLocator::new(path, &data, &[(0, data.len() as u64)], &HashMap::new())
+            }
+            Some(DeviceFileKind::Cubin) => {
+                KernelLocator::from_module(path, &data, ElementKind::Cubin)
+            }
+            Some(DeviceFileKind::Ptx) => KernelLocator::from_module(path, &data, ElementKind::Ptx),
+            None => {
+                warn!("Skipping {}: not a fatbin, cubin or PTX file", path);
+                continue;
+            }
+        };
+        let locator = match located {
+            Ok(locator) => locator,
+            Err(e) => {
+                warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        let output_path = format!("{}/{}.json", output_dir, name);
+        let identity = LibraryIdentity::new(&data);
+        let unused = write_spans(
+            &output_path,
+            path,
+            &identity,
+            &locator,
+            &detected_kernels,
+            target_compute_capability,
+        );
+        if unused {
+            debug!("{}: no device code used", path);
+            unused_files.push(json!({"path": path, "size": data.len()}));
         }
-        let gpu_code_offset = elf.get_gpu_code_offset().unwrap();
-        let gpu_code_size = elf.get_gpu_code_size().unwrap();
-        let locator = KernelLocator::new(so_path, gpu_code_offset, gpu_code_size, cuobjdump_path);
-        let spans =
-            locator.locate_deletable_file_spans(&detected_kernels, target_compute_capability);
-        let output_path = format!(
-            "{}/{}.json",
-            output_dir,
-            so_path.split('/').last().unwrap().to_string()
+    }
+    if list_unused {
+        info!(
+            "{} of {} device code files unused",
+            unused_files.len(),
+            device_files.len()
         );
-        let output_file = std::fs::File::create(output_path).unwrap();
-        serde_json::to_writer_pretty(
-            output_file,
-            &json!({
-                "so_path": so_path,
-                "spans": spans
-            }),
-        )
-        .unwrap();
+        let output_file =
+            std::fs::File::create(format!("{}/unused_files.json", output_dir)).unwrap();
+        serde_json::to_writer_pretty(output_file, &unused_files).unwrap();
+    }
+}
+
+// Write the located spans of a file, returns whether none of its device code is used, i.e., all of it is deletable
+fn write_spans(
+    output_path: &str,
+    so_path: &str,
+    identity: &LibraryIdentity,
+    locator: &KernelLocator,
+    detected_kernels: &HashSet<String>,
+    target_compute_capability: u32,
+) -> bool {
+    let spans = locator.locate_deletable_file_spans(detected_kernels, target_compute_capability);
+    let removed_kernels =
+        locator.locate_removed_kernels(detected_kernels, target_compute_capability);
+    for kernel in removed_kernels.iter() {
+        debug!("{}: removing kernel {}", so_path, kernel);
+    }
+    let reachable_kernels = locator.locate_reachable_kernels(detected_kernels);
+    for kernel in reachable_kernels.iter() {
+        debug!(
+            "{}: keeping kernel {}, reachable from a detected one",
+            so_path, kernel
+        );
+    }
+    let unit_usage = locator.locate_unit_usage(detected_kernels, target_compute_capability);
+    for usage in unit_usage.iter() {
+        debug!(
+            "{}: {}: {} bytes kept, {} bytes removed",
+            so_path,
+            usage
+                .source_file
+                .as_deref()
+                .unwrap_or("unknown compilation unit"),
+            usage.kept_bytes,
+            usage.removed_bytes
+        );
+    }
+    let elements = locator.locate_elements(detected_kernels, target_compute_capability);
+    let unused = elements.iter().all(|e| e.deletable);
+    let output_file = std::fs::File::create(output_path).unwrap();
+    serde_json::to_writer_pretty(
+        output_file,
+        &json!({
+            "so_path": so_path,
+            "identity": identity,
+            "spans": spans,
+            "removed_kernels": removed_kernels,
+            "reachable_kernels": reachable_kernels,
+            "elements": elements,
+            "compilation_units": unit_usage
+        }),
+    )
+    .unwrap();
+    unused
+}
+
+// Split the inputs into standalone device code files, each with the name of its report, and other objects,
+// directories are searched for device code files
+fn split_inputs(inputs: &[String]) -> (Vec<(String, String)>, Vec<String>) {
+    let mut device_files = vec![];
+    let mut objects = vec![];
+    for input in inputs.iter() {
+        if std::path::Path::new(input).is_dir() {
+            match find_device_files(input) {
+                Ok(files) => device_files.extend(files),
+                Err(e) => warn!("Skipping {}: {}", input, e),
+            }
+            continue;
+        }
+        let is_device_file = open_object(input)
+            .map(|data| DeviceFileKind::detect(&data).is_some())
+            .unwrap_or(false);
+        if is_device_file {
+            let name = input.split('/').next_back().unwrap().to_string();
+            device_files.push((input.clone(), name));
+        } else {
+            objects.push(input.clone());
+        }
+    }
+    (device_files, objects)
+}
+
+// Expand archives and wheels to their ELF members, inputs that cannot be read are skipped
+fn expand_inputs(inputs: &[String]) -> Vec<String> {
+    let mut objects = vec![];
+    for input in inputs.iter() {
+        match list_objects(input) {
+            Ok(members) => objects.extend(members),
+            Err(e) => warn!("Skipping {}: {}", input, e),
+        }
+    }
+    objects
+}
+
+// List the fatbins of every object
+fn inventory(paths: &[String], output: &str) {
+    let mut objects = vec![];
+    for path in expand_inputs(paths).iter() {
+        let data = match open_object(path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        let found = Elf::new(&data).and_then(|elf| Ok((elf.host_isa(), elf.find_fatbins()?)));
+        let (isa, fatbins) = match found {
+            Ok(found) => found,
+            Err(e) => {
+                warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        let fatbin_size: u64 = fatbins.iter().map(|f| f.size).sum();
+        // the decoded header of every element, with its region, the file offset it starts at and the file span of its payload
+        let fatbins: Vec<serde_json::Value> = fatbins
+            .iter()
+            .map(|f| {
+                let gpu_code_data = &data[f.offset as usize..(f.offset + f.size) as usize];
+                let elements: Vec<serde_json::Value> = match GPUCode::new(gpu_code_data) {
+                    Ok(gpu_code) => gpu_code
+                        .elements(gpu_code_data, f.offset)
+                        .map(|e| {
+                            json!({
+                                "region": e.region_index,
+                                "index": e.index,
+                                "offset": e.offset,
+                                "span": e.span,
+                                "header": e.header()
+                            })
+                        })
+                        .collect(),
+                    Err(e) => {
+                        warn!("{}: fatbin at {:#x}: {}", path, f.offset, e);
+                        vec![]
+                    }
+                };
+                let mut fatbin = serde_json::to_value(f).unwrap();
+                fatbin["elements"] = json!(elements);
+                fatbin
+            })
+            .collect();
+        info!(
+            "{} ({}): {} fatbins, {} of {} bytes",
+            path,
+            isa,
+            fatbins.len(),
+            fatbin_size,
+            data.len()
+        );
+        objects.push(json!({
+            "path": path,
+            "isa": isa,
+            "size": data.len(),
+            "fatbin_size": fatbin_size,
+            "fatbins": fatbins
+        }));
+    }
+    let output_file = std::fs::File::create(output).unwrap();
+    serde_json::to_writer_pretty(output_file, &objects).unwrap();
+}
+
+// The edits of the patch command
+struct PatchOptions<'a> {
+    set_runpath: Option<&'a str>,
+    force_rpath: bool,
+    remove_runpath: bool,
+    add_needed: &'a [String],
+    remove_needed: &'a [String],
+    replace_needed: &'a [String],
+}
+
+// Edit the dynamic section of an ELF file
+fn patch(binary_path: &str, output: &str, options: &PatchOptions) {
+    let data = match std::fs::read(binary_path) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("Failed to read {}: {}", binary_path, e);
+            return;
+        }
+    };
+    let mut editor = match DynamicEditor::new(data) {
+        Ok(editor) => editor,
+        Err(e) => {
+            error!("Failed to parse {}: {}", binary_path, e);
+            return;
+        }
+    };
+    if options.remove_runpath && !editor.remove_runpath() {
+        warn!("{} has no DT_RUNPATH or DT_RPATH", binary_path);
+    }
+    match (options.set_runpath, options.force_rpath) {
+        (Some(rpath), true) => editor.set_rpath(rpath),
+        (Some(runpath), false) => editor.set_runpath(runpath),
+        (None, _) => {}
+    }
+    for name in options.remove_needed.iter() {
+        if !editor.remove_needed(name) {
+            warn!("{} does not need {}", binary_path, name);
+        }
+    }
+    for replacement in options.replace_needed.iter() {
+        let Some((name, new_name)) = replacement.split_once('=') else {
+            error!("Invalid --replace-needed {}, expected OLD=NEW", replacement);
+            return;
+        };
+        if !editor.replace_needed(name, new_name) {
+            warn!("{} does not need {}", binary_path, name);
+        }
+    }
+    for name in options.add_needed.iter() {
+        if !editor.add_needed(name) {
+            warn!("{} already needs {}", binary_path, name);
+        }
+    }
+    match editor.finish() {
+        Ok(data) => std::fs::write(output, data).unwrap(),
+        Err(e) => error!("Failed to edit {}: {}", binary_path, e),
+    }
+}
+
+// Resolve the dependency graph
+fn deps(binary_path: &str, output: &str, format: GraphFormat, report_path: Option<&str>) {
+    let resolver = DependencyResolver::from_env();
+    let graph = match resolver.resolve(binary_path) {
+        Ok(graph) => graph,
+        Err(e) => {
+            error!("Failed to resolve {}: {}", binary_path, e);
+            return;
+        }
+    };
+    info!(
+        "Resolved {} shared libraries of {}",
+        graph.libraries().len(),
+        binary_path
+    );
+    for name in graph.unresolved() {
+        warn!("Unresolved dependency: {}", name);
+    }
+
+    match format {
+        GraphFormat::Dot => std::fs::write(output, graph.to_dot()).unwrap(),
+        GraphFormat::Json => {
+            let output_file = std::fs::File::create(output).unwrap();
+            serde_json::to_writer_pretty(output_file, &graph).unwrap();
+        }
+    }
+
+    if let Some(report_path) = report_path {
+        let report_file = std::fs::File::open(report_path).unwrap();
+        let trace_report: TraceReport = serde_json::from_reader(report_file).unwrap();
+        for so_path in graph.dlopen_only(&trace_report.loaded_sos) {
+            info!("Loaded through dlopen only: {}", so_path);
+        }
     }
 }
 
 // Run the reconstructor
-fn reconstruct(span_path: &str, output_dir: &str) {
+fn reconstruct(span_path: &str, output_dir: &str, shrink: bool) {
     let span_file = std::fs::File::open(span_path).unwrap();
     let span_json: serde_json::Value = serde_json::from_reader(span_file).unwrap();
     debug!("Span json: {:?}", span_json);
     let so_path = span_json["so_path"].as_str().unwrap();
-    let spans: Vec<locator::locator::ElementSpan> =
+    let spans: Vec<locator::gpu_code::ElementSpan> =
         serde_json::from_value(span_json["spans"].clone()).unwrap();
-    std::fs::create_dir_all(output_dir).unwrap();
-    let dst_so_path = format!(
-        "{}/{}",
-        output_dir,
-        so_path.split('/').last().unwrap().to_string()
-    );
-    let reconstructor = reconstructor::reconstructor::Reconstructor::new(so_path, &dst_so_path);
+    // spans of archive members are relative to the member, there is no file to rewrite
+    if ObjectPath::parse(so_path).is_member() {
+        error!(
+            "Cannot rewrite {}: archive and wheel members must be extracted first",
+            so_path
+        );
+        return;
+    }
+    // spans are file offsets, only valid for the exact bytes that were located
+    let located: Option<LibraryIdentity> =
+        serde_json::from_value(span_json["identity"].clone()).unwrap_or(None);
+    match located {
+        Some(located) => {
+            let identity = match LibraryIdentity::from_path(so_path) {
+                Ok(identity) => identity,
+                Err(e) => {
+                    error!("Failed to identify {}: {}", so_path, e);
+                    return;
+                }
+            };
+            if identity != located {
+                error!(
+                    "{} changed since locate, located {}, now {}",
+                    so_path, located, identity
+                );
+                return;
+            }
+        }
+        None => warn!("No identity recorded for {}, skipping the check", so_path),
+    }
+    // the reconstructed library keeps its file name, which DT_NEEDED refers to; libraries whose span file had
+    // to be named apart, e.g., libfoo.so of two wheels, go to a directory of their own named by path hash
+    let file_name = so_path.split('/').next_back().unwrap();
+    let report_name = std::path::Path::new(span_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned());
+    let dst_dir = if report_name.as_deref() == Some(file_name) {
+        output_dir.to_string()
+    } else {
+        format!("{}/{}", output_dir, path_hash(so_path))
+    };
+    if let Err(e) = std::fs::create_dir_all(&dst_dir) {
+        error!("Failed to create {}: {}", dst_dir, e);
+        return;
+    }
+    let dst_so_path = format!("{}/{}", dst_dir, file_name);
+    let reconstructor =
+        match reconstructor::reconstructor::Reconstructor::new(so_path, &dst_so_path) {
+            Ok(reconstructor) => reconstructor,
+            Err(e) => {
+                error!("Failed to copy {} to {}: {}", so_path, dst_so_path, e);
+                return;
+            }
+        };
     reconstructor.rewrite(&spans);
+    if shrink {
+        match reconstructor.shrink(&spans) {
+            Ok(saved) => info!("Shrank {} by {} bytes", dst_so_path, saved),
+            Err(e) => error!("Failed to shrink {}: {}", dst_so_path, e),
+        }
+    }
 }
 
 fn main() {
@@ -187,18 +772,63 @@ fn main() {
             report_path,
             cuobjdump_path,
             output_dir,
+            input,
+            compute_capability,
+            list_unused,
         } => {
+            warn_cuobjdump_path(&cuobjdump_path);
             info!("Tracing report path: {}", report_path);
-            info!("cuobjdump path: {}", cuobjdump_path);
-            locate(&report_path, &cuobjdump_path, &output_dir);
+            locate(
+                &report_path,
+                &output_dir,
+                &input,
+                compute_capability,
+                list_unused,
+            );
         }
         Command::Reconstruct {
             span_path,
             output_dir,
+            shrink,
         } => {
             info!("Span path: {}", span_path);
             info!("Reconstructed so will be saved to: {}", output_dir);
-            reconstruct(&span_path, &output_dir);
+            reconstruct(&span_path, &output_dir, shrink);
+        }
+        Command::Deps {
+            binary_path,
+            output,
+            format,
+            report_path,
+        } => {
+            info!("Dependency graph will be saved to: {}", output);
+            deps(&binary_path, &output, format, report_path.as_deref());
+        }
+        Command::Patch {
+            binary_path,
+            output,
+            set_runpath,
+            force_rpath,
+            remove_runpath,
+            add_needed,
+            remove_needed,
+            replace_needed,
+        } => {
+            let output = output.unwrap_or_else(|| binary_path.clone());
+            info!("Edited file will be saved to: {}", output);
+            let options = PatchOptions {
+                set_runpath: set_runpath.as_deref(),
+                force_rpath,
+                remove_runpath,
+                add_needed: &add_needed,
+                remove_needed: &remove_needed,
+                replace_needed: &replace_needed,
+            };
+            patch(&binary_path, &output, &options);
+        }
+        Command::Inventory { paths, output } => {
+            info!("Fatbin inventory will be saved to: {}", output);
+            inventory(&paths, &output);
         }
         Command::Debloat {
             loader_path,
@@ -207,6 +837,7 @@ fn main() {
             output_dir,
             cmd,
         } => {
+            warn_cuobjdump_path(&cuobjdump_path);
             // create output dir
             std::fs::create_dir_all(&output_dir).unwrap();
 
@@ -214,7 +845,7 @@ fn main() {
             trace(&loader_path, &env, &cmd, &trace_output_file);
 
             let span_path = format!("{}/spans", output_dir);
-            locate(&trace_output_file, &cuobjdump_path, &span_path);
+            locate(&trace_output_file, &span_path, &[], None, false);
         }
     }
 }
@@ -1,4 +1,8 @@
-use crate::locator::locator::ElementSpan;
+use crate::elf::elf::{Elf, ElfError};
+use crate::elf::rewriter::SectionRewriter;
+use crate::locator::gpu_code::{ElementSpan, GPUCode};
+use crate::utils::mapped::MappedFileMut;
+use std::collections::HashMap;
 
 /// Reconstructor is responsible for rewriting the shared object file based on the identified spans.
 ///
@@ -9,23 +13,205 @@ pub struct Reconstructor<'path> {
 
 impl<'path> Reconstructor<'path> {
     /// Create a new Reconstructor instance.
-    /// Copies the src_so_path to dst_so_path.
-    pub fn new(src_so_path: &'path str, dst_so_path: &'path str) -> Self {
+    /// Copies the src_so_path to dst_so_path, failing if the copy fails.
+    pub fn new(src_so_path: &'path str, dst_so_path: &'path str) -> std::io::Result<Self> {
         // copy the src_so_path to dst_so_path
-        std::fs::copy(src_so_path, dst_so_path).unwrap();
-        Self { dst_so_path }
+        std::fs::copy(src_so_path, dst_so_path)?;
+        Ok(Self { dst_so_path })
     }
 
     /// Rewrite the destination shared object file based on the provided spans.
+    /// The file is patched in place through a memory mapping, only the pages of the spans are touched.
     pub fn rewrite(&self, spans: &[ElementSpan]) {
-        let mut so_data = std::fs::read(self.dst_so_path).unwrap();
+        let mut so_data = MappedFileMut::open(self.dst_so_path).unwrap();
         for span in spans.iter() {
-            let start = span.start as usize;
-            let end = span.end as usize;
-            for i in start..end {
-                so_data[i] = 0x01;
-            }
+            so_data[span.start as usize..span.end as usize].fill(0x01);
         }
-        std::fs::write(self.dst_so_path, so_data).unwrap();
+        so_data.flush().unwrap();
+    }
+
+    /// Shrink the .nv_fatbin section of the destination shared object file, after `rewrite`.
+    /// The elements whose payloads are covered by the spans are dropped and the fatbin is compacted, every region
+    /// stays, possibly empty, with the wrappers, relocations and symbols pointing at it moved along. The freed
+    /// tail of the section is then cut off the file, moving the sections after it, e.g., .eh_frame. The file is
+    /// rewritten through a memory mapping and truncated. Returns the number of bytes the file shrank by.
+    pub fn shrink(&self, spans: &[ElementSpan]) -> Result<u64, ElfError> {
+        let mut data = MappedFileMut::open(self.dst_so_path)?;
+        let elf = Elf::new(&data)?;
+        let (Some(start), Some(section)) = (
+            elf.get_section_offset(".nv_fatbin")?,
+            elf.get_section_data(".nv_fatbin")?,
+        ) else {
+            return Ok(0);
+        };
+        let addr = elf.offset_to_addr(start)?;
+        let malformed = |_| ElfError::Unsupported("malformed fatbin in .nv_fatbin");
+        let mut gpu_code = GPUCode::new(section).map_err(malformed)?;
+        let original_offsets: Vec<u64> = gpu_code.regions.iter().map(|r| r.offset).collect();
+        let deleted = |payload_start: u64, payload_end: u64| {
+            spans
+                .iter()
+                .any(|span| span.start <= payload_start && payload_end <= span.end)
+        };
+        for region in gpu_code.regions.iter_mut() {
+            region.elements.retain(|element| {
+                let payload_start = start + element.offset + element.header.offset as u64;
+                !deleted(payload_start, payload_start + element.header.size)
+            });
+        }
+        let compacted = gpu_code.to_bytes(section);
+        if compacted.len() > section.len() {
+            return Err(ElfError::Unsupported(
+                "compacted fatbin larger than .nv_fatbin",
+            ));
+        }
+        let moves: HashMap<u64, u64> = GPUCode::new(&compacted)
+            .map_err(malformed)?
+            .regions
+            .iter()
+            .zip(original_offsets)
+            .filter(|(region, offset)| region.offset != *offset)
+            .map(|(region, offset)| (addr + offset, addr + region.offset))
+            .collect();
+
+        let (start, end) = (start as usize, start as usize + section.len());
+        data[start..start + compacted.len()].copy_from_slice(&compacted);
+        data[start + compacted.len()..end].fill(0);
+        let mut rewriter = SectionRewriter::new(&mut data)?;
+        rewriter.move_pointers(&moves)?;
+        let saved = rewriter.shrink_section(".nv_fatbin", compacted.len() as u64)?;
+        let len = data.len() as u64 - saved;
+        data.truncate(len)?;
+        Ok(saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // the spans of all elements but the last of each region
+    fn all_but_last(path: &str) -> (Vec<ElementSpan>, Vec<Vec<u8>>) {
+        let data = std::fs::read(path).unwrap();
+        let elf = Elf::new(&data).unwrap();
+        let start = elf.get_section_offset(".nv_fatbin").unwrap().unwrap();
+        let section = elf.get_section_data(".nv_fatbin").unwrap().unwrap();
+        let gpu_code = GPUCode::new(section).unwrap();
+        let spans = gpu_code
+            .regions(section, start)
+            .flat_map(|region| {
+                let mut spans: Vec<ElementSpan> = region.elements().map(|e| e.span).collect();
+                spans.pop();
+                spans
+            })
+            .collect();
+        let kept = gpu_code
+            .regions(section, start)
+            .map(|region| region.elements().last().unwrap().stored.to_vec())
+            .collect();
+        (spans, kept)
+    }
+
+    #[test]
+    fn test_new_missing_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("libmissing.so");
+        let src = fixture("libmissing.so");
+        assert!(Reconstructor::new(&src, dst.to_str().unwrap()).is_err());
+        assert!(!dst.exists());
+    }
+
+    #[test]
+    fn test_shrink_library() {
+        let _ = env_logger::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("libbloated.so");
+        let (src, dst) = (fixture("libbloated.so"), dst.to_str().unwrap().to_string());
+        let original = std::fs::read(&src).unwrap();
+        let elf = Elf::new(&original).unwrap();
+        let (spans, kept) = all_but_last(&src);
+        assert!(!spans.is_empty());
+
+        let reconstructor = Reconstructor::new(&src, &dst).unwrap();
+        reconstructor.rewrite(&spans);
+        let saved = reconstructor.shrink(&spans).unwrap();
+        let data = std::fs::read(&dst).unwrap();
+        assert!(saved >= 0x1000);
+        assert_eq!(data.len() as u64, original.len() as u64 - saved);
+
+        // the regions keep one element each, moved to the front of the section
+        let shrunk = Elf::new(&data).unwrap();
+        let section = shrunk.get_section_data(".nv_fatbin").unwrap().unwrap();
+        let start = shrunk.get_section_offset(".nv_fatbin").unwrap().unwrap();
+        let compacted = GPUCode::new(section).unwrap();
+        let regions = compacted
+            .regions(section, start)
+            .map(|region| {
+                let elements: Vec<Vec<u8>> = region.elements().map(|e| e.stored.to_vec()).collect();
+                assert_eq!(elements, vec![kept[region.index].clone()]);
+            })
+            .count();
+        assert_eq!(regions, 8);
+        assert_eq!(shrunk.get_soname().unwrap(), elf.get_soname().unwrap());
+        assert_eq!(shrunk.get_build_id().unwrap(), elf.get_build_id().unwrap());
+    }
+
+    #[test]
+    fn test_shrink_nvcc_library() {
+        // nvcc puts __nv_module_id, .eh_frame_hdr, .eh_frame and .gcc_except_table after .nv_fatbin
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("libdemo.so");
+        let (src, dst) = (fixture("libdemo.so"), dst.to_str().unwrap().to_string());
+        let original = std::fs::read(&src).unwrap();
+        let elf = Elf::new(&original).unwrap();
+        let (spans, kept) = all_but_last(&src);
+        assert!(!spans.is_empty());
+
+        let reconstructor = Reconstructor::new(&src, &dst).unwrap();
+        reconstructor.rewrite(&spans);
+        let saved = reconstructor.shrink(&spans).unwrap();
+        let data = std::fs::read(&dst).unwrap();
+        assert!(saved >= 0x1000 && saved.is_multiple_of(0x1000));
+        assert_eq!(data.len() as u64, original.len() as u64 - saved);
+
+        let shrunk = Elf::new(&data).unwrap();
+        let section = shrunk.get_section_data(".nv_fatbin").unwrap().unwrap();
+        let start = shrunk.get_section_offset(".nv_fatbin").unwrap().unwrap();
+        let compacted = GPUCode::new(section).unwrap();
+        for region in compacted.regions(section, start) {
+            let elements: Vec<Vec<u8>> = region.elements().map(|e| e.stored.to_vec()).collect();
+            assert_eq!(elements, vec![kept[region.index].clone()]);
+        }
+        // the sections after .nv_fatbin moved in the file but read the same at their addresses
+        for name in [
+            "__nv_module_id",
+            ".eh_frame_hdr",
+            ".eh_frame",
+            ".gcc_except_table",
+        ] {
+            let read = |elf: &Elf, data: &[u8]| {
+                let offset = elf.get_section_offset(name).unwrap().unwrap() as usize;
+                let addr = elf.offset_to_addr(offset as u64).unwrap();
+                let size = elf.get_section_size(name).unwrap().unwrap() as usize;
+                (addr, data[offset..offset + size].to_vec())
+            };
+            assert!(read(&shrunk, &data) == read(&elf, &original), "{}", name);
+        }
+        assert_eq!(shrunk.get_soname().unwrap(), elf.get_soname().unwrap());
+        assert_eq!(shrunk.get_build_id().unwrap(), elf.get_build_id().unwrap());
+        assert_eq!(
+            shrunk.find_fatbins().unwrap().len(),
+            elf.find_fatbins().unwrap().len()
+        );
     }
 }
@@ -1,6 +1,9 @@
-use crate::elf::elf::ELF64;
+use crate::elf::elf::{Elf, ElfError};
+use crate::elf::identity::LibraryIdentity;
+use crate::utils::demangle::kernel_names;
+use crate::utils::utils::get_compute_capabilities;
 use libc::{c_char, PTRACE_EVENT_CLONE, PTRACE_EVENT_EXEC, PTRACE_EVENT_FORK, PTRACE_EVENT_VFORK};
-use log::{debug, info};
+use log::{debug, info, warn};
 use nix::sys::ptrace::{self, AddressType};
 use nix::sys::signal::Signal;
 use nix::sys::wait::{waitpid, WaitStatus};
@@ -8,7 +11,7 @@ use nix::unistd::{fork, ForkResult, Pid};
 use proc_maps::MapRange;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
 use std::io::{BufRead, BufReader, Write};
 use std::os::raw::c_int;
@@ -33,27 +36,43 @@ impl Tracer {
     /// Create a new Tracer instance.
     ///
     /// * `loader_path`: the path to the system loader, e.g., /usr/lib/x86_64-linux-gnu/ld-2.31.so
-    pub fn new(loader_path: &str) -> Tracer {
-        let data = std::fs::read(loader_path).expect("Failed to read loader");
-        let _loader_elf = ELF64::new(&data);
-        let _dl_debug_state_addr = _loader_elf
-            .get_symbol_addr(b"_dl_debug_state")
-            .expect("Failed to get _dl_debug_state offset");
-        let _r_debug_addr = _loader_elf
-            .get_symbol_addr(b"_r_debug")
-            .expect("Failed to get _r_debug offset");
+    pub fn new(loader_path: &str) -> Result<Tracer, ElfError> {
+        let data = std::fs::read(loader_path)?;
+        let _loader_elf = Elf::new(&data)?;
+        let symbol_addrs =
+            _loader_elf.get_symbol_addrs([&b"_dl_debug_state"[..], &b"_r_debug"[..]])?;
+        let _dl_debug_state_addr = symbol_addrs[0]
+            .ok_or_else(|| ElfError::MissingSymbol("_dl_debug_state".to_string()))?;
+        let _r_debug_addr =
+            symbol_addrs[1].ok_or_else(|| ElfError::MissingSymbol("_r_debug".to_string()))?;
+
+        // both are versioned, e.g., GLIBC_PRIVATE, log which definition is used
+        for symbol in [&b"_dl_debug_state"[..], &b"_r_debug"[..]] {
+            if let Some(info) = _loader_elf.get_symbol_info(symbol)? {
+                debug!(
+                    "Using {}@{} at {:#x}, {} of {} definitions",
+                    info.name,
+                    info.version.as_deref().unwrap_or("(unversioned)"),
+                    info.addr,
+                    if info.hidden { "hidden" } else { "default" },
+                    _loader_elf.get_symbol_versions(symbol)?.len()
+                );
+            }
+        }
 
         let _dl_debug_state_offset = _loader_elf
-            .get_symbol_offset(b"_dl_debug_state")
-            .expect("Failed to get _dl_debug_state offset");
-        let _dl_debug_state_first_byte = data[_dl_debug_state_offset as usize];
+            .get_symbol_offset(b"_dl_debug_state")?
+            .ok_or_else(|| ElfError::MissingSymbol("_dl_debug_state".to_string()))?;
+        let _dl_debug_state_first_byte = *data
+            .get(_dl_debug_state_offset as usize)
+            .ok_or(ElfError::Truncated)?;
 
-        Tracer {
+        Ok(Tracer {
             _dl_debug_state_addr,
             _dl_debug_state_first_byte,
             _r_debug_addr,
             loader_path: loader_path.to_string(),
-        }
+        })
     }
 
     /// Trace the target process and its children to detect loaded shared libraries and used kernels.
@@ -114,6 +133,18 @@ impl Tracer {
                 }
                 info!("Tracing finished");
 
+                // record which exact bytes were loaded
+                let mut so_identities = HashMap::new();
+                for so_path in loaded_sos.iter() {
+                    match LibraryIdentity::from_path(so_path) {
+                        Ok(identity) => {
+                            debug!("{}: {}", so_path, identity);
+                            so_identities.insert(so_path.clone(), identity);
+                        }
+                        Err(e) => warn!("Failed to identify {}: {}", so_path, e),
+                    }
+                }
+
                 // read the kernel log file to get the detected kernels
                 let mut detected_kernels = HashSet::new();
                 kernel_log_file.as_file_mut().flush().unwrap();
@@ -127,16 +158,32 @@ impl Tracer {
                     detected_kernels.insert(line);
                 }
 
+                let detected_kernel_names = kernel_names(&detected_kernels);
+                for kernel in detected_kernel_names.iter() {
+                    debug!("detected kernel: {}", kernel);
+                }
+
+                // the report may be located on another host, e.g., an x86 build host without GPU
+                // multi-GPU hosts record each capability once, e.g., [90] for 8 H100s
+                let mut compute_capabilities = get_compute_capabilities();
+                compute_capabilities.sort_unstable();
+                compute_capabilities.dedup();
+
                 let kernel_report = json!(
                     {
                         "loaded_sos": loaded_sos,
+                        "so_identities": so_identities,
                         "detected_kernels": detected_kernels,
+                        "detected_kernel_names": detected_kernel_names,
+                        "compute_capabilities": compute_capabilities,
                     }
                 );
 
                 let trace_report = TraceReport {
                     detected_kernels,
                     loaded_sos,
+                    so_identities,
+                    compute_capabilities,
                 };
 
                 serde_json::to_writer_pretty(
@@ -407,4 +454,10 @@ struct RDebug {
 pub struct TraceReport {
     pub detected_kernels: HashSet<String>,
     pub loaded_sos: HashSet<String>,
+    /// Identity of each loaded shared library when it was traced, absent in reports of older versions
+    #[serde(default)]
+    pub so_identities: HashMap<String, LibraryIdentity>,
+    /// Compute capabilities of the GPUs of the traced host, absent in reports of older versions
+    #[serde(default)]
+    pub compute_capabilities: Vec<u32>,
 }
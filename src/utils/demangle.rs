@@ -0,0 +1,1634 @@
+use serde::Serialize;
+use std::fmt;
+use std::rc::Rc;
+
+// deeper nesting is rejected instead of risking a stack overflow on malformed names
+const MAX_DEPTH: usize = 256;
+// substitutions can make the output grow exponentially with the input
+const MAX_OUTPUT: usize = 1 << 20;
+
+/// A kernel name as shown in reports
+///
+/// The mangled name is the symbol name, which all lookups match on, the demangled one is for display.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct KernelName {
+    pub mangled: String,
+    /// None if the name is not mangled, e.g., an extern "C" kernel, or cannot be demangled
+    pub demangled: Option<String>,
+}
+
+impl KernelName {
+    pub fn new(mangled: &str) -> KernelName {
+        KernelName {
+            mangled: mangled.to_string(),
+            demangled: demangle(mangled),
+        }
+    }
+}
+
+impl fmt::Display for KernelName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.demangled.as_deref().unwrap_or(&self.mangled))
+    }
+}
+
+/// Demangle the given kernel names, sorted by mangled name
+pub fn kernel_names<'a>(mangled: impl IntoIterator<Item = &'a String>) -> Vec<KernelName> {
+    let mut names: Vec<KernelName> = mangled.into_iter().map(|m| KernelName::new(m)).collect();
+    names.sort();
+    names
+}
+
+/// Demangle an Itanium C++ ABI symbol name, formatted like c++filt
+///
+/// E.g., `_Z12matrixMulGPUPiS_S_iii` is demangled to `matrixMulGPU(int*, int*, int*, int, int, int)`.
+/// Returns None if the name is not mangled, is malformed, or uses a construct that is not supported,
+/// e.g., decltype and most expressions beyond the ones found in template arguments.
+pub fn demangle(mangled: &str) -> Option<String> {
+    let mut parser = Parser {
+        input: mangled.strip_prefix("_Z")?.as_bytes(),
+        pos: 0,
+        subs: vec![],
+        sub_params: vec![],
+        sub_param: vec![],
+        template_params: vec![],
+        params_history: vec![vec![]],
+        param_refs: vec![],
+        depth: 0,
+    };
+    let encoding = parser.parse_encoding()?;
+    let mut printer = Printer::default();
+    printer.print(&encoding);
+    // suffixes of compiler generated clones, e.g., .constprop.0
+    while parser.consume(b'.') {
+        let start = parser.pos - 1;
+        if parser.take_while(|c| c.is_ascii_alphabetic() || c == b'_') == 0
+            && parser.take_while(|c| c.is_ascii_digit()) == 0
+        {
+            return None;
+        }
+        while parser.peek() == Some(b'.') && parser.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
+            parser.pos += 1;
+            parser.take_while(|c| c.is_ascii_digit());
+        }
+        let suffix = std::str::from_utf8(&parser.input[start..parser.pos]).ok()?;
+        printer.out.push_str(&format!(" [clone {}]", suffix));
+    }
+    if parser.pos != parser.input.len() || printer.failed {
+        return None;
+    }
+    Some(printer.out)
+}
+
+#[derive(Debug)]
+enum Node {
+    /// An identifier, a builtin type, or any other name printed as is
+    Name(String),
+    /// A name in a scope, e.g., std::vector
+    Nested(Rc<Node>, Rc<Node>),
+    /// A template name with its arguments
+    Template(Rc<Node>, Vec<Rc<Node>>),
+    /// A type with qualifiers, e.g., " const"
+    Qualified(Rc<Node>, String),
+    /// A pointer or reference, with its declarator, i.e., "*", "&" or "&&"
+    Pointer(Rc<Node>, &'static str),
+    /// A pointer to a member of a class: the class and the member type
+    MemberPointer(Rc<Node>, Rc<Node>),
+    /// A function type: return type, parameters and qualifiers
+    Function(Rc<Node>, Vec<Rc<Node>>, String),
+    /// A function: name, return type, printed for templates only, parameters and qualifiers
+    Encoding(Rc<Node>, Option<Rc<Node>>, Vec<Rc<Node>>, String),
+    /// An array type and its dimension
+    Array(Rc<Node>, String),
+    /// Template arguments packed together, e.g., of a variadic template
+    ArgPack(Vec<Rc<Node>>),
+    /// A template parameter referring to packed arguments, which pack expansions expand
+    Pack(Vec<Rc<Node>>),
+    /// A pack expansion, printed once for each element of the pack it contains
+    Expansion(Rc<Node>),
+    /// A special name, e.g., "vtable for "
+    Special(&'static str, Rc<Node>),
+}
+
+fn name(s: impl Into<String>) -> Rc<Node> {
+    Rc::new(Node::Name(s.into()))
+}
+
+fn std_name(s: &str) -> Rc<Node> {
+    Rc::new(Node::Nested(name("std"), name(s)))
+}
+
+// print a node on its own, e.g., the type of a literal
+fn to_string(node: &Rc<Node>) -> String {
+    let mut printer = Printer::default();
+    printer.print(node);
+    printer.out
+}
+
+// the name a constructor or destructor of the given class is printed with
+fn base_name(node: &Node) -> Option<String> {
+    match node {
+        // without ABI tags
+        Node::Name(s) => Some(s.split("[abi:").next()?.to_string()),
+        Node::Nested(_, node) | Node::Template(node, _) => base_name(node),
+        _ => None,
+    }
+}
+
+// the mangled operator names: code, name, and arity in expressions
+const OPERATORS: &[(&str, &str, usize)] = &[
+    ("nw", "new", 1),
+    ("na", "new[]", 1),
+    ("dl", "delete", 1),
+    ("da", "delete[]", 1),
+    ("ps", "+", 1),
+    ("ng", "-", 1),
+    ("ad", "&", 1),
+    ("de", "*", 1),
+    ("co", "~", 1),
+    ("pl", "+", 2),
+    ("mi", "-", 2),
+    ("ml", "*", 2),
+    ("dv", "/", 2),
+    ("rm", "%", 2),
+    ("an", "&", 2),
+    ("or", "|", 2),
+    ("eo", "^", 2),
+    ("aS", "=", 2),
+    ("pL", "+=", 2),
+    ("mI", "-=", 2),
+    ("mL", "*=", 2),
+    ("dV", "/=", 2),
+    ("rM", "%=", 2),
+    ("aN", "&=", 2),
+    ("oR", "|=", 2),
+    ("eO", "^=", 2),
+    ("ls", "<<", 2),
+    ("rs", ">>", 2),
+    ("lS", "<<=", 2),
+    ("rS", ">>=", 2),
+    ("eq", "==", 2),
+    ("ne", "!=", 2),
+    ("lt", "<", 2),
+    ("gt", ">", 2),
+    ("le", "<=", 2),
+    ("ge", ">=", 2),
+    ("ss", "<=>", 2),
+    ("nt", "!", 1),
+    ("aa", "&&", 2),
+    ("oo", "||", 2),
+    ("pp", "++", 1),
+    ("mm", "--", 1),
+    ("cm", ",", 2),
+    ("pm", "->*", 2),
+    ("pt", "->", 2),
+    ("cl", "()", 2),
+    ("ix", "[]", 2),
+    ("qu", "?", 3),
+];
+
+// what the name being parsed ends with, which decides how its encoding is printed
+#[derive(Default)]
+struct NameState {
+    ends_with_template_args: bool,
+    is_ctor_dtor_conversion: bool,
+    // the CV and ref qualifiers of a member function
+    qualifiers: String,
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    // the components S_, S0_, S1_, ... refer to
+    subs: Vec<Rc<Node>>,
+    // the template params each substitution contains, as (generation, index) pairs
+    sub_params: Vec<Vec<(usize, usize)>>,
+    // the index of the template param each substitution is, if it is one on its own
+    sub_param: Vec<Option<usize>>,
+    // the arguments T_, T0_, T1_, ... refer to, of the last template args of the function name
+    template_params: Vec<Rc<Node>>,
+    // every value of template_params so far, indexed by generation, the last one is the current one
+    // they are replaced, e.g., by the function a lambda in template args is local to
+    params_history: Vec<Vec<Rc<Node>>>,
+    // the input positions at which template params were referenced, directly or in a substitution,
+    // with their generation and index
+    param_refs: Vec<(usize, usize, usize)>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.pos + offset).copied()
+    }
+
+    fn consume(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn consume_str(&mut self, s: &str) -> bool {
+        let found = self.input[self.pos..].starts_with(s.as_bytes());
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        self.consume(c).then_some(())
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    // add a substitution candidate parsed from the given input position on
+    // `param` is the index of the template param the candidate is, if it is one on its own
+    fn push_sub(&mut self, node: Rc<Node>, start: usize, param: Option<usize>) {
+        let mut params: Vec<(usize, usize)> = self
+            .param_refs
+            .iter()
+            .rev()
+            .take_while(|(pos, ..)| *pos >= start)
+            .map(|&(_, generation, index)| (generation, index))
+            .collect();
+        params.sort_unstable();
+        params.dedup();
+        self.subs.push(node);
+        self.sub_params.push(params);
+        self.sub_param.push(param);
+    }
+
+    fn pop_sub(&mut self) {
+        self.subs.pop();
+        self.sub_params.pop();
+        self.sub_param.pop();
+    }
+
+    fn at_end_of_encoding(&self) -> bool {
+        matches!(self.peek(), None | Some(b'E') | Some(b'.'))
+    }
+
+    fn enter(&mut self) -> Option<()> {
+        self.depth += 1;
+        (self.depth <= MAX_DEPTH).then_some(())
+    }
+
+    fn number(&mut self) -> Option<u64> {
+        let start = self.pos;
+        if self.take_while(|c| c.is_ascii_digit()) == 0 {
+            return None;
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    // a number that may be negative, written with an n prefix
+    fn signed_number(&mut self) -> Option<String> {
+        let negative = self.consume(b'n');
+        let number = self.number()?;
+        Some(format!("{}{}", if negative { "-" } else { "" }, number))
+    }
+
+    // the optional number of an unnamed type or lambda, printed from 1
+    fn discriminator_number(&mut self) -> Option<u64> {
+        let number = match self.peek()? {
+            b'_' => 1,
+            _ => self.number()? + 2,
+        };
+        self.expect(b'_')?;
+        Some(number)
+    }
+
+    fn source_name(&mut self) -> Option<String> {
+        let length = self.number()? as usize;
+        let bytes = self.input.get(self.pos..self.pos.checked_add(length)?)?;
+        self.pos += length;
+        let identifier = std::str::from_utf8(bytes).ok()?;
+        if identifier.starts_with("_GLOBAL__N") {
+            return Some("(anonymous namespace)".to_string());
+        }
+        Some(identifier.to_string())
+    }
+
+    fn cv_qualifiers(&mut self) -> String {
+        let restrict = self.consume(b'r');
+        let volatile = self.consume(b'V');
+        let constant = self.consume(b'K');
+        let mut qualifiers = String::new();
+        for (present, qualifier) in [
+            (constant, " const"),
+            (volatile, " volatile"),
+            (restrict, " restrict"),
+        ] {
+            if present {
+                qualifiers.push_str(qualifier);
+            }
+        }
+        qualifiers
+    }
+
+    // <encoding> ::= <name> <bare-function-type> | <name> | <special-name>
+    fn parse_encoding(&mut self) -> Option<Rc<Node>> {
+        self.enter()?;
+        if matches!(self.peek()?, b'T' | b'G') {
+            let special = self.parse_special_name();
+            self.depth -= 1;
+            return special;
+        }
+        let mut state = NameState::default();
+        let name = self.parse_name(&mut state, true)?;
+        if self.at_end_of_encoding() {
+            self.depth -= 1;
+            return Some(name);
+        }
+        // template functions, other than constructors, destructors and conversions, mangle their return type
+        let ret = if state.ends_with_template_args && !state.is_ctor_dtor_conversion {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let mut params = vec![];
+        while !self.at_end_of_encoding() {
+            params.push(self.parse_type()?);
+        }
+        let params = Self::void_params(params)?;
+        self.depth -= 1;
+        Some(Rc::new(Node::Encoding(name, ret, params, state.qualifiers)))
+    }
+
+    // a lone void parameter means no parameters
+    fn void_params(params: Vec<Rc<Node>>) -> Option<Vec<Rc<Node>>> {
+        match params.as_slice() {
+            [] => None,
+            [param] if matches!(&**param, Node::Name(s) if s == "void") => Some(vec![]),
+            _ => Some(params),
+        }
+    }
+
+    fn parse_special_name(&mut self) -> Option<Rc<Node>> {
+        let special = |prefix, node| Some(Rc::new(Node::Special(prefix, node)));
+        if self.consume_str("TV") {
+            return special("vtable for ", self.parse_type()?);
+        }
+        if self.consume_str("TT") {
+            return special("VTT for ", self.parse_type()?);
+        }
+        if self.consume_str("TI") {
+            return special("typeinfo for ", self.parse_type()?);
+        }
+        if self.consume_str("TS") {
+            return special("typeinfo name for ", self.parse_type()?);
+        }
+        if self.consume_str("TH") {
+            return special(
+                "TLS init function for ",
+                self.parse_name(&mut NameState::default(), true)?,
+            );
+        }
+        if self.consume_str("TW") {
+            return special(
+                "TLS wrapper function for ",
+                self.parse_name(&mut NameState::default(), true)?,
+            );
+        }
+        if self.consume_str("Tc") {
+            self.call_offset()?;
+            self.call_offset()?;
+            return special("covariant return thunk to ", self.parse_encoding()?);
+        }
+        if self.consume(b'T') {
+            let prefix = match self.peek()? {
+                b'h' => "non-virtual thunk to ",
+                b'v' => "virtual thunk to ",
+                _ => return None,
+            };
+            self.call_offset()?;
+            return special(prefix, self.parse_encoding()?);
+        }
+        if self.consume_str("GV") {
+            return special(
+                "guard variable for ",
+                self.parse_name(&mut NameState::default(), true)?,
+            );
+        }
+        if self.consume_str("GTt") {
+            return special("transaction clone for ", self.parse_encoding()?);
+        }
+        None
+    }
+
+    // <call-offset> ::= h <nv-offset> _ | v <v-offset> _ <virtual offset> _
+    fn call_offset(&mut self) -> Option<()> {
+        if self.consume(b'h') {
+            self.signed_number()?;
+            return self.expect(b'_');
+        }
+        self.expect(b'v')?;
+        self.signed_number()?;
+        self.expect(b'_')?;
+        self.signed_number()?;
+        self.expect(b'_')
+    }
+
+    // <name> ::= <nested-name> | <local-name> | <unscoped-name> | <unscoped-template-name> <template-args>
+    // the template args of the names of encodings, i.e., `tag_templates`, are the ones T_ refers to
+    fn parse_name(&mut self, state: &mut NameState, tag_templates: bool) -> Option<Rc<Node>> {
+        self.enter()?;
+        let name = match self.peek()? {
+            b'N' => self.parse_nested_name(state, tag_templates)?,
+            b'Z' => self.parse_local_name(state, tag_templates)?,
+            b'S' if self.peek_at(1) != Some(b't') => {
+                // only a template name can be substituted here
+                let name = self.parse_substitution()?;
+                let args = self.parse_template_args(tag_templates)?;
+                state.ends_with_template_args = true;
+                Rc::new(Node::Template(name, args))
+            }
+            _ => {
+                let start = self.pos;
+                let name = if self.consume_str("St") {
+                    Rc::new(Node::Nested(
+                        name("std"),
+                        self.parse_unqualified_name(state, None)?,
+                    ))
+                } else {
+                    // internal linkage
+                    self.consume(b'L');
+                    self.parse_unqualified_name(state, None)?
+                };
+                if self.peek() == Some(b'I') {
+                    self.push_sub(name.clone(), start, None);
+                    let args = self.parse_template_args(tag_templates)?;
+                    state.ends_with_template_args = true;
+                    Rc::new(Node::Template(name, args))
+                } else {
+                    name
+                }
+            }
+        };
+        self.depth -= 1;
+        Some(name)
+    }
+
+    // <nested-name> ::= N [<CV-qualifiers>] [<ref-qualifier>] <prefix> <unqualified-name> E
+    //               ::= N [<CV-qualifiers>] [<ref-qualifier>] <template-prefix> <template-args> E
+    fn parse_nested_name(
+        &mut self,
+        state: &mut NameState,
+        tag_templates: bool,
+    ) -> Option<Rc<Node>> {
+        self.expect(b'N')?;
+        let start = self.pos;
+        state.qualifiers = self.cv_qualifiers();
+        if self.consume(b'R') {
+            state.qualifiers.push_str(" &");
+        } else if self.consume(b'O') {
+            state.qualifiers.push_str(" &&");
+        }
+        let mut current = self.consume_str("St").then(|| name("std"));
+        let join = |current: Option<Rc<Node>>, node: Rc<Node>| match current {
+            Some(scope) => Rc::new(Node::Nested(scope, node)),
+            None => node,
+        };
+        while !self.consume(b'E') {
+            self.consume(b'L');
+            state.ends_with_template_args = false;
+            // template args belong to the name before them, e.g., a template constructor
+            if self.peek() != Some(b'I') {
+                state.is_ctor_dtor_conversion = false;
+            }
+            match (self.peek()?, self.peek_at(1)) {
+                // the prefix of a lambda in a data member initializer
+                (b'M', _) => {
+                    current.as_ref()?;
+                    self.pos += 1;
+                    continue;
+                }
+                (b'T', _) => {
+                    let (_, param) = self.parse_template_param()?;
+                    current = Some(join(current, param));
+                }
+                (b'I', _) => {
+                    let args = self.parse_template_args(tag_templates)?;
+                    current = Some(Rc::new(Node::Template(current?, args)));
+                    state.ends_with_template_args = true;
+                }
+                (b'S', next) if next != Some(b't') => {
+                    let sub = self.parse_substitution()?;
+                    // a substitution is not added again on its own
+                    match current {
+                        Some(scope) => current = Some(Rc::new(Node::Nested(scope, sub))),
+                        None => {
+                            current = Some(sub);
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    let unqualified = self.parse_unqualified_name(state, current.as_ref())?;
+                    current = Some(join(current, unqualified));
+                }
+            }
+            self.push_sub(current.clone()?, start, None);
+        }
+        // the whole name is not a substitution candidate, unless it is used as a type
+        let current = current?;
+        if self
+            .subs
+            .last()
+            .is_some_and(|last| Rc::ptr_eq(last, &current))
+        {
+            self.pop_sub();
+        }
+        Some(current)
+    }
+
+    // <local-name> ::= Z <encoding> E <entity name> [<discriminator>] | Z <encoding> E s [<discriminator>]
+    fn parse_local_name(&mut self, state: &mut NameState, tag_templates: bool) -> Option<Rc<Node>> {
+        self.expect(b'Z')?;
+        let mut encoding = self.parse_encoding()?;
+        self.expect(b'E')?;
+        // the function a name is local to is printed without its return type
+        if let Node::Encoding(name, Some(_), params, qualifiers) = &*encoding {
+            encoding = Rc::new(Node::Encoding(
+                name.clone(),
+                None,
+                params.clone(),
+                qualifiers.clone(),
+            ));
+        }
+        let entity = if self.consume(b's') {
+            name("string literal")
+        } else {
+            if self.consume(b'd') {
+                // a default argument, numbered from the last parameter
+                if self.peek() != Some(b'_') {
+                    self.number()?;
+                }
+                self.expect(b'_')?;
+            }
+            self.parse_name(state, tag_templates)?
+        };
+        // <discriminator> ::= _ <digit> | __ <number> _
+        if self.consume(b'_') {
+            if self.consume(b'_') {
+                self.number()?;
+                self.expect(b'_')?;
+            } else {
+                self.take_while(|c| c.is_ascii_digit());
+            }
+        }
+        Some(Rc::new(Node::Nested(encoding, entity)))
+    }
+
+    // <unqualified-name> ::= <operator-name> | <ctor-dtor-name> | <source-name> | <unnamed-type-name>
+    // followed by any ABI tags
+    fn parse_unqualified_name(
+        &mut self,
+        state: &mut NameState,
+        scope: Option<&Rc<Node>>,
+    ) -> Option<Rc<Node>> {
+        let c = self.peek()?;
+        let next = self.peek_at(1);
+        let mut node = match c {
+            b'0'..=b'9' => name(self.source_name()?),
+            b'C' => {
+                self.pos += 1;
+                // inheriting constructors name the base class
+                let inheriting = self.consume(b'I');
+                if !matches!(self.peek()?, b'1'..=b'5') {
+                    return None;
+                }
+                self.pos += 1;
+                if inheriting {
+                    self.parse_type()?;
+                }
+                state.is_ctor_dtor_conversion = true;
+                name(base_name(scope?)?)
+            }
+            b'D' if matches!(next, Some(b'0'..=b'5')) => {
+                self.pos += 2;
+                state.is_ctor_dtor_conversion = true;
+                name(format!("~{}", base_name(scope?)?))
+            }
+            b'D' if next == Some(b'C') => {
+                // a structured binding
+                self.pos += 2;
+                let mut names = vec![];
+                while !self.consume(b'E') {
+                    names.push(self.source_name()?);
+                }
+                name(format!("[{}]", names.join(", ")))
+            }
+            b'U' if next == Some(b't') => {
+                self.pos += 2;
+                name(format!("{{unnamed type#{}}}", self.discriminator_number()?))
+            }
+            b'U' if next == Some(b'l') => {
+                self.pos += 2;
+                let mut params = vec![];
+                while !self.consume(b'E') {
+                    params.push(self.parse_type()?);
+                }
+                let params = Self::void_params(params)?;
+                let number = self.discriminator_number()?;
+                let mut printer = Printer::default();
+                printer.out.push_str("{lambda(");
+                printer.list(&params);
+                printer.out.push_str(&format!(")#{}}}", number));
+                name(printer.out)
+            }
+            b'a'..=b'z' => self.parse_operator_name(state)?,
+            _ => return None,
+        };
+        while self.consume(b'B') {
+            let tag = self.source_name()?;
+            node = name(format!("{}[abi:{}]", to_string(&node), tag));
+        }
+        Some(node)
+    }
+
+    fn parse_operator_name(&mut self, state: &mut NameState) -> Option<Rc<Node>> {
+        if self.consume_str("cv") {
+            state.is_ctor_dtor_conversion = true;
+            let ty = self.parse_type()?;
+            return Some(name(format!("operator {}", to_string(&ty))));
+        }
+        if self.consume_str("li") {
+            return Some(name(format!("operator\"\" {}", self.source_name()?)));
+        }
+        if self.consume(b'v') {
+            self.take_while(|c| c.is_ascii_digit());
+            return Some(name(format!("operator {}", self.source_name()?)));
+        }
+        let code = self.input.get(self.pos..self.pos + 2)?;
+        let &(_, op, _) = OPERATORS.iter().find(|(c, _, _)| c.as_bytes() == code)?;
+        self.pos += 2;
+        // alphabetic operators are separated by a space, e.g., operator new
+        let separator = if op.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            " "
+        } else {
+            ""
+        };
+        Some(name(format!("operator{}{}", separator, op)))
+    }
+
+    // <template-args> ::= I <template-arg>+ E
+    fn parse_template_args(&mut self, tag_templates: bool) -> Option<Vec<Rc<Node>>> {
+        self.expect(b'I')?;
+        // the arguments may contain encodings with template args of their own
+        let mut params = vec![];
+        let mut args = vec![];
+        while !self.consume(b'E') {
+            let arg = self.parse_template_arg()?;
+            if tag_templates {
+                let param = match &*arg {
+                    Node::ArgPack(elements) => Rc::new(Node::Pack(elements.clone())),
+                    _ => arg.clone(),
+                };
+                params.push(param);
+            }
+            args.push(arg);
+        }
+        if tag_templates {
+            self.params_history.push(params.clone());
+            self.template_params = params;
+        }
+        Some(args)
+    }
+
+    // <template-arg> ::= <type> | X <expression> E | <expr-primary> | J <template-arg>* E
+    fn parse_template_arg(&mut self) -> Option<Rc<Node>> {
+        match self.peek()? {
+            b'X' => {
+                self.pos += 1;
+                let expression = self.parse_expression()?;
+                self.expect(b'E')?;
+                Some(expression)
+            }
+            b'L' => self.parse_expr_primary(),
+            b'J' => {
+                self.pos += 1;
+                let mut args = vec![];
+                while !self.consume(b'E') {
+                    args.push(self.parse_template_arg()?);
+                }
+                Some(Rc::new(Node::ArgPack(args)))
+            }
+            _ => self.parse_type(),
+        }
+    }
+
+    // <template-param> ::= T_ | T <number> _
+    // returns the index of the param and the argument it refers to
+    fn parse_template_param(&mut self) -> Option<(usize, Rc<Node>)> {
+        self.expect(b'T')?;
+        let index = match self.consume(b'_') {
+            true => 0,
+            false => {
+                let number = self.number()? as usize;
+                self.expect(b'_')?;
+                number + 1
+            }
+        };
+        Some((index, self.template_param(index)?))
+    }
+
+    // the argument of the current template args at the given index, recording the reference
+    fn template_param(&mut self, index: usize) -> Option<Rc<Node>> {
+        let generation = self.params_history.len() - 1;
+        self.param_refs.push((self.pos, generation, index));
+        self.template_params.get(index).cloned()
+    }
+
+    // <substitution> ::= S_ | S <seq-id> _ | Sa | Sb | Ss | Si | So | Sd
+    fn parse_substitution(&mut self) -> Option<Rc<Node>> {
+        self.expect(b'S')?;
+        // c++filt prints the char specializations in full, e.g., Ss as std::basic_string<char, ...>
+        let char_template = |template: &str, allocator: bool| {
+            let char_type = || name("char");
+            let mut args = vec![
+                char_type(),
+                Rc::new(Node::Template(std_name("char_traits"), vec![char_type()])),
+            ];
+            if allocator {
+                args.push(Rc::new(Node::Template(
+                    std_name("allocator"),
+                    vec![char_type()],
+                )));
+            }
+            Rc::new(Node::Template(std_name(template), args))
+        };
+        let abbreviation = match self.peek()? {
+            b'a' => Some(std_name("allocator")),
+            b'b' => Some(std_name("basic_string")),
+            b's' => Some(char_template("basic_string", true)),
+            b'i' => Some(char_template("basic_istream", false)),
+            b'o' => Some(char_template("basic_ostream", false)),
+            b'd' => Some(char_template("basic_iostream", false)),
+            _ => None,
+        };
+        if abbreviation.is_some() {
+            self.pos += 1;
+            return abbreviation;
+        }
+        let mut index = 0usize;
+        if !self.consume(b'_') {
+            // base 36 with digits and upper case letters
+            loop {
+                let digit = match self.peek()? {
+                    c @ b'0'..=b'9' => c - b'0',
+                    c @ b'A'..=b'Z' => c - b'A' + 10,
+                    b'_' => break,
+                    _ => return None,
+                };
+                self.pos += 1;
+                index = index.checked_mul(36)?.checked_add(digit as usize)?;
+            }
+            self.pos += 1;
+            index += 1;
+        }
+        let sub = self.subs.get(index).cloned()?;
+        // the template params it contains may belong to other template args, e.g., of the function a lambda in
+        // template args is local to, c++filt prints them as the params of the current template args instead
+        let current = self.params_history.len() - 1;
+        if self.sub_params[index].iter().any(|&(g, _)| g != current) {
+            if let Some(param) = self.sub_param[index] {
+                return self.template_param(param);
+            }
+            // printed as parsed only if every param it contains means the same in the current template args
+            let unchanged = self.sub_params[index].iter().all(|&(g, i)| {
+                match (self.params_history[g].get(i), self.template_params.get(i)) {
+                    (Some(parsed), Some(now)) => to_string(parsed) == to_string(now),
+                    _ => false,
+                }
+            });
+            if !unchanged {
+                return None;
+            }
+        }
+        let pos = self.pos;
+        let params = self.sub_params[index].clone();
+        self.param_refs
+            .extend(params.into_iter().map(|(g, i)| (pos, g, i)));
+        Some(sub)
+    }
+
+    fn parse_type(&mut self) -> Option<Rc<Node>> {
+        self.enter()?;
+        let start = self.pos;
+        let mut param_index = None;
+        let c = self.peek()?;
+        let builtin = match c {
+            b'v' => Some("void"),
+            b'w' => Some("wchar_t"),
+            b'b' => Some("bool"),
+            b'c' => Some("char"),
+            b'a' => Some("signed char"),
+            b'h' => Some("unsigned char"),
+            b's' => Some("short"),
+            b't' => Some("unsigned short"),
+            b'i' => Some("int"),
+            b'j' => Some("unsigned int"),
+            b'l' => Some("long"),
+            b'm' => Some("unsigned long"),
+            b'x' => Some("long long"),
+            b'y' => Some("unsigned long long"),
+            b'n' => Some("__int128"),
+            b'o' => Some("unsigned __int128"),
+            b'f' => Some("float"),
+            b'd' => Some("double"),
+            b'e' => Some("long double"),
+            b'g' => Some("__float128"),
+            b'z' => Some("..."),
+            _ => None,
+        };
+        if let Some(builtin) = builtin {
+            self.pos += 1;
+            self.depth -= 1;
+            return Some(name(builtin));
+        }
+        let node = match c {
+            b'u' => {
+                self.pos += 1;
+                name(self.source_name()?)
+            }
+            b'r' | b'V' | b'K' => {
+                let qualifiers = self.cv_qualifiers();
+                // a qualified function type is a single substitution candidate
+                let ty = if self.peek() == Some(b'F') {
+                    self.parse_function_type()?
+                } else {
+                    self.parse_type()?
+                };
+                match &*ty {
+                    // qualifiers of member function types follow the parameters
+                    Node::Function(ret, params, function_qualifiers) => Rc::new(Node::Function(
+                        ret.clone(),
+                        params.clone(),
+                        format!("{}{}", function_qualifiers, qualifiers),
+                    )),
+                    // e.g., a template parameter substituted with an array or qualified type
+                    Node::Array(element, dimension) => Rc::new(Node::Array(
+                        Rc::new(Node::Qualified(element.clone(), qualifiers)),
+                        dimension.clone(),
+                    )),
+                    Node::Qualified(inner, inner_qualifiers)
+                        if inner_qualifiers.starts_with(" const")
+                            || inner_qualifiers.starts_with(" volatile")
+                            || inner_qualifiers.starts_with(" restrict") =>
+                    {
+                        let merged = [" const", " volatile", " restrict"]
+                            .into_iter()
+                            .filter(|q| qualifiers.contains(q) || inner_qualifiers.contains(q))
+                            .collect();
+                        Rc::new(Node::Qualified(inner.clone(), merged))
+                    }
+                    _ => Rc::new(Node::Qualified(ty, qualifiers)),
+                }
+            }
+            b'U' => {
+                self.pos += 1;
+                let qualifier = self.source_name()?;
+                if self.peek() == Some(b'I') {
+                    self.parse_template_args(false)?;
+                }
+                Rc::new(Node::Qualified(
+                    self.parse_type()?,
+                    format!(" {}", qualifier),
+                ))
+            }
+            b'P' | b'R' | b'O' => {
+                self.pos += 1;
+                let declarator = match c {
+                    b'P' => "*",
+                    b'R' => "&",
+                    _ => "&&",
+                };
+                Rc::new(Node::Pointer(self.parse_type()?, declarator))
+            }
+            b'C' | b'G' => {
+                self.pos += 1;
+                let qualifier = if c == b'C' {
+                    " _Complex"
+                } else {
+                    " _Imaginary"
+                };
+                Rc::new(Node::Qualified(self.parse_type()?, qualifier.to_string()))
+            }
+            b'F' => self.parse_function_type()?,
+            b'A' => {
+                self.pos += 1;
+                let dimension = match self.peek()? {
+                    b'_' => String::new(),
+                    b'0'..=b'9' => self.number()?.to_string(),
+                    _ => to_string(&self.parse_expression()?),
+                };
+                self.expect(b'_')?;
+                Rc::new(Node::Array(self.parse_type()?, dimension))
+            }
+            b'M' => {
+                self.pos += 1;
+                let class = self.parse_type()?;
+                Rc::new(Node::MemberPointer(class, self.parse_type()?))
+            }
+            b'T' => {
+                let (index, param) = self.parse_template_param()?;
+                if self.peek() == Some(b'I') {
+                    // a template template parameter with its arguments
+                    self.push_sub(param.clone(), start, Some(index));
+                    let args = self.parse_template_args(false)?;
+                    Rc::new(Node::Template(param, args))
+                } else {
+                    param_index = Some(index);
+                    param
+                }
+            }
+            b'S' if self.peek_at(1) != Some(b't') => {
+                let sub = self.parse_substitution()?;
+                if self.peek() != Some(b'I') {
+                    self.depth -= 1;
+                    return Some(sub);
+                }
+                let args = self.parse_template_args(false)?;
+                Rc::new(Node::Template(sub, args))
+            }
+            b'D' => {
+                let next = self.peek_at(1)?;
+                self.pos += 2;
+                let builtin = match next {
+                    b'n' => Some("decltype(nullptr)"),
+                    b'a' => Some("auto"),
+                    b'c' => Some("decltype(auto)"),
+                    b'd' => Some("decimal64"),
+                    b'e' => Some("decimal128"),
+                    b'f' => Some("decimal32"),
+                    b'h' => Some("half"),
+                    b'i' => Some("char32_t"),
+                    b's' => Some("char16_t"),
+                    b'u' => Some("char8_t"),
+                    _ => None,
+                };
+                if let Some(builtin) = builtin {
+                    self.depth -= 1;
+                    return Some(name(builtin));
+                }
+                match next {
+                    b'p' => Rc::new(Node::Expansion(self.parse_type()?)),
+                    b'F' => {
+                        let bits = self.number()?;
+                        self.expect(b'_')?;
+                        self.depth -= 1;
+                        return Some(name(format!("_Float{}", bits)));
+                    }
+                    b'v' => {
+                        let size = self.number()?;
+                        self.expect(b'_')?;
+                        let ty = self.parse_type()?;
+                        name(format!("{} __vector({})", to_string(&ty), size))
+                    }
+                    // decltype and exception specifications
+                    _ => return None,
+                }
+            }
+            b'N' | b'Z' | b'S' | b'0'..=b'9' => {
+                self.parse_name(&mut NameState::default(), false)?
+            }
+            _ => return None,
+        };
+        self.push_sub(node.clone(), start, param_index);
+        self.depth -= 1;
+        Some(node)
+    }
+
+    // <function-type> ::= F [Y] <return type> <parameter types> [<ref-qualifier>] E
+    fn parse_function_type(&mut self) -> Option<Rc<Node>> {
+        self.expect(b'F')?;
+        self.consume(b'Y');
+        let ret = self.parse_type()?;
+        let mut params = vec![];
+        let mut qualifiers = String::new();
+        loop {
+            if self.consume_str("RE") {
+                qualifiers.push_str(" &");
+                break;
+            }
+            if self.consume_str("OE") {
+                qualifiers.push_str(" &&");
+                break;
+            }
+            if self.consume(b'E') {
+                break;
+            }
+            params.push(self.parse_type()?);
+        }
+        let params = Self::void_params(params)?;
+        Some(Rc::new(Node::Function(ret, params, qualifiers)))
+    }
+
+    // <simple-id> ::= <source-name> [<template-args>]
+    fn parse_simple_id(&mut self) -> Option<Rc<Node>> {
+        let id = name(self.source_name()?);
+        if self.peek() != Some(b'I') {
+            return Some(id);
+        }
+        let args = self.parse_template_args(false)?;
+        Some(Rc::new(Node::Template(id, args)))
+    }
+
+    // <base-unresolved-name> ::= <simple-id> | on <operator-name> [<template-args>] | dn <destructor-name>
+    fn parse_base_unresolved_name(&mut self) -> Option<Rc<Node>> {
+        if self.consume_str("on") {
+            let operator = self.parse_operator_name(&mut NameState::default())?;
+            if self.peek() != Some(b'I') {
+                return Some(operator);
+            }
+            let args = self.parse_template_args(false)?;
+            return Some(Rc::new(Node::Template(operator, args)));
+        }
+        if self.consume_str("dn") {
+            return Some(name(format!("~{}", to_string(&self.parse_simple_id()?))));
+        }
+        self.parse_simple_id()
+    }
+
+    // <expr-primary> ::= L <type> <value number> E | L <mangled-name> E
+    fn parse_expr_primary(&mut self) -> Option<Rc<Node>> {
+        self.expect(b'L')?;
+        if self.consume_str("_Z") || self.consume(b'Z') {
+            let encoding = self.parse_encoding()?;
+            self.expect(b'E')?;
+            return Some(encoding);
+        }
+        let ty = to_string(&self.parse_type()?);
+        let start = self.pos;
+        self.take_while(|c| c != b'E');
+        let mut value = std::str::from_utf8(&self.input[start..self.pos])
+            .ok()?
+            .to_string();
+        self.expect(b'E')?;
+        if let Some(negative) = value.strip_prefix('n') {
+            value = format!("-{}", negative);
+        }
+        let literal = match (ty.as_str(), value.as_str()) {
+            ("bool", "0") => "false".to_string(),
+            ("bool", "1") => "true".to_string(),
+            ("decltype(nullptr)", "") => "nullptr".to_string(),
+            ("int", _) => value,
+            ("unsigned int", _) => format!("{}u", value),
+            ("long", _) => format!("{}l", value),
+            ("unsigned long", _) => format!("{}ul", value),
+            ("long long", _) => format!("{}ll", value),
+            ("unsigned long long", _) => format!("{}ull", value),
+            // floating point values are mangled as their bits in hex
+            ("float" | "double" | "long double", _) => format!("({})[{}]", ty, value),
+            _ => format!("({}){}", ty, value),
+        };
+        Some(name(literal))
+    }
+
+    // the expressions found in template arguments and array dimensions
+    fn parse_expression(&mut self) -> Option<Rc<Node>> {
+        self.enter()?;
+        let expression = match (self.peek()?, self.peek_at(1)) {
+            (b'L', _) => self.parse_expr_primary()?,
+            (b'T', _) => self.parse_template_param()?.1,
+            (b'f', Some(b'p')) => {
+                self.pos += 2;
+                self.cv_qualifiers();
+                let number = self.discriminator_number()?;
+                name(format!("{{parm#{}}}", number))
+            }
+            (b's', Some(b'Z')) => {
+                self.pos += 2;
+                let pack = self.parse_template_arg()?;
+                name(format!("sizeof...({})", to_string(&pack)))
+            }
+            (b's' | b'a', Some(b't')) => {
+                let op = if self.peek()? == b's' {
+                    "sizeof"
+                } else {
+                    "alignof"
+                };
+                self.pos += 2;
+                name(format!("{} ({})", op, to_string(&self.parse_type()?)))
+            }
+            (b's' | b'a', Some(b'z')) => {
+                let op = if self.peek()? == b's' {
+                    "sizeof"
+                } else {
+                    "alignof"
+                };
+                self.pos += 2;
+                name(format!("{} ({})", op, to_string(&self.parse_expression()?)))
+            }
+            (b'c', Some(b'v')) => {
+                self.pos += 2;
+                let ty = to_string(&self.parse_type()?);
+                if self.consume(b'_') {
+                    let mut values = vec![];
+                    while !self.consume(b'E') {
+                        values.push(to_string(&self.parse_expression()?));
+                    }
+                    name(format!("({})({})", ty, values.join(", ")))
+                } else {
+                    let value = self.parse_expression()?;
+                    name(format!("({}){}", ty, subexpression(&value)))
+                }
+            }
+            (b's', Some(b'r')) => {
+                // sr <unresolved-type> <base-unresolved-name>
+                // srN <unresolved-type> <unresolved-qualifier-level>+ E <base-unresolved-name>
+                // sr <unresolved-qualifier-level>+ E <base-unresolved-name>
+                self.pos += 2;
+                let nested = self.consume(b'N');
+                let (mut scope, has_levels) = match self.peek()? {
+                    b'T' | b'S' | b'D' => (self.parse_type()?, nested),
+                    _ if nested => return None,
+                    _ => (self.parse_simple_id()?, true),
+                };
+                if has_levels {
+                    while !self.consume(b'E') {
+                        scope = Rc::new(Node::Nested(scope, self.parse_simple_id()?));
+                    }
+                }
+                Rc::new(Node::Nested(scope, self.parse_base_unresolved_name()?))
+            }
+            (b'0'..=b'9', _) | (b'o' | b'd', Some(b'n')) => self.parse_base_unresolved_name()?,
+            (first, Some(second)) => {
+                let &(_, op, arity) = OPERATORS
+                    .iter()
+                    .find(|(c, _, _)| c.as_bytes() == [first, second])?;
+                self.pos += 2;
+                let mut operands = vec![];
+                for _ in 0..arity {
+                    operands.push(subexpression(&self.parse_expression()?));
+                }
+                match operands.as_slice() {
+                    [operand] => name(format!("{}{}", op, operand)),
+                    [left, right] => name(format!("{}{}{}", left, op, right)),
+                    [condition, then, otherwise] => {
+                        name(format!("{}?{} : {}", condition, then, otherwise))
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        self.depth -= 1;
+        Some(expression)
+    }
+}
+
+// operands are parenthesized, unless they are names or function parameters
+fn subexpression(node: &Rc<Node>) -> String {
+    let printed = to_string(node);
+    let is_name = printed.starts_with("{parm#")
+        || (printed.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && printed
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b':'));
+    match &**node {
+        Node::Nested(..) | Node::Template(..) => printed,
+        Node::Name(_) if is_name => printed,
+        _ => format!("({})", printed),
+    }
+}
+
+// declarators like pointers wrap what they point to in parentheses if it prints on the right, i.e.,
+// functions and arrays, e.g., void (*)(int)
+fn is_function_or_array(node: &Node) -> bool {
+    matches!(node, Node::Function(..) | Node::Array(..))
+}
+
+// whether the node prints anything after the name it declares, e.g., the parameters of a function type
+fn has_right(node: &Node) -> bool {
+    match node {
+        Node::Function(..) | Node::Array(..) => true,
+        Node::Pointer(inner, _) | Node::Qualified(inner, _) | Node::MemberPointer(_, inner) => {
+            has_right(inner)
+        }
+        _ => false,
+    }
+}
+
+// the number of elements of the first pack in the node, which a pack expansion is expanded to
+fn pack_len(node: &Node) -> Option<usize> {
+    match node {
+        Node::Pack(elements) => Some(elements.len()),
+        Node::Name(_) | Node::Expansion(_) => None,
+        Node::Nested(a, b) | Node::MemberPointer(a, b) => pack_len(a).or_else(|| pack_len(b)),
+        Node::Qualified(inner, _) | Node::Pointer(inner, _) | Node::Array(inner, _) => {
+            pack_len(inner)
+        }
+        Node::Special(_, inner) => pack_len(inner),
+        Node::Template(inner, children) | Node::Function(inner, children, _) => {
+            pack_len(inner).or_else(|| children.iter().find_map(|c| pack_len(c)))
+        }
+        Node::ArgPack(children) => children.iter().find_map(|c| pack_len(c)),
+        Node::Encoding(..) => None,
+    }
+}
+
+// prints a node in two parts around the declared name, e.g., `void (*` and `)(int)`
+#[derive(Default)]
+struct Printer {
+    out: String,
+    // the element of the packs to print, while printing a pack expansion
+    pack_index: Option<usize>,
+    depth: usize,
+    failed: bool,
+    // whether the separator before the last item of a list was removed, as the item printed nothing
+    elided_separator: bool,
+}
+
+impl Printer {
+    fn print(&mut self, node: &Node) {
+        self.left(node);
+        self.right(node);
+    }
+
+    fn left(&mut self, node: &Node) {
+        if self.depth > MAX_DEPTH || self.out.len() > MAX_OUTPUT {
+            self.failed = true;
+            return;
+        }
+        self.depth += 1;
+        match self.resolve(node) {
+            Node::Name(s) => self.out.push_str(s),
+            Node::Nested(scope, name) => {
+                self.print(scope);
+                self.out.push_str("::");
+                self.print(name);
+            }
+            Node::Template(name, args) => {
+                self.print(name);
+                self.template_args(args);
+            }
+            Node::Qualified(inner, qualifiers) => {
+                self.left(inner);
+                self.out.push_str(qualifiers);
+            }
+            Node::Pointer(inner, declarator) => {
+                let (inner, declarator) = self.collapse(inner, declarator);
+                self.left(inner);
+                if matches!(inner, Node::Array(..)) {
+                    self.out.push(' ');
+                }
+                if is_function_or_array(inner) {
+                    self.out.push('(');
+                }
+                self.out.push_str(declarator);
+            }
+            Node::MemberPointer(class, member) => {
+                let member = self.resolve(member);
+                self.left(member);
+                if is_function_or_array(member) {
+                    self.out.push('(');
+                } else {
+                    self.out.push(' ');
+                }
+                self.print(class);
+                self.out.push_str("::*");
+            }
+            Node::Function(ret, ..) => {
+                self.left(ret);
+                self.out.push(' ');
+            }
+            Node::Encoding(name, ret, ..) => {
+                if let Some(ret) = ret {
+                    self.left(ret);
+                    if !has_right(ret) {
+                        self.out.push(' ');
+                    }
+                }
+                self.print(name);
+            }
+            Node::Array(element, _) => self.left(element),
+            // a pack being expanded is resolved to one of its elements, unless it has too few
+            Node::Pack(elements) => {
+                if self.pack_index.is_none() {
+                    self.list(elements);
+                }
+            }
+            Node::ArgPack(elements) => self.list(elements),
+            Node::Expansion(inner) => match pack_len(inner) {
+                Some(len) => {
+                    let pack_index = self.pack_index;
+                    let mut first = true;
+                    for index in 0..len {
+                        self.pack_index = Some(index);
+                        self.separated(inner, &mut first);
+                    }
+                    self.pack_index = pack_index;
+                }
+                None => {
+                    self.print(inner);
+                    self.out.push_str("...");
+                }
+            },
+            Node::Special(prefix, inner) => {
+                self.out.push_str(prefix);
+                self.print(inner);
+            }
+        }
+        self.depth -= 1;
+    }
+
+    fn right(&mut self, node: &Node) {
+        if self.failed {
+            return;
+        }
+        match self.resolve(node) {
+            Node::Qualified(inner, _) => self.right(inner),
+            Node::Pointer(inner, declarator) => {
+                let (inner, _) = self.collapse(inner, declarator);
+                if is_function_or_array(inner) {
+                    self.out.push(')');
+                }
+                self.right(inner);
+            }
+            Node::MemberPointer(_, member) => {
+                let member = self.resolve(member);
+                if is_function_or_array(member) {
+                    self.out.push(')');
+                }
+                self.right(member);
+            }
+            Node::Function(ret, params, qualifiers) => {
+                self.parameters(params);
+                self.right(ret);
+                self.out.push_str(qualifiers);
+            }
+            Node::Encoding(_, ret, params, qualifiers) => {
+                self.parameters(params);
+                if let Some(ret) = ret {
+                    self.right(ret);
+                }
+                self.out.push_str(qualifiers);
+            }
+            Node::Array(element, dimension) => {
+                if !self.out.ends_with(']') {
+                    self.out.push(' ');
+                }
+                self.out.push('[');
+                self.out.push_str(dimension);
+                self.out.push(']');
+                self.right(element);
+            }
+            _ => {}
+        }
+    }
+
+    // the element a pack stands for while expanding it
+    fn resolve<'a>(&self, node: &'a Node) -> &'a Node {
+        match (node, self.pack_index) {
+            (Node::Pack(elements), Some(index)) => elements
+                .get(index)
+                .map_or(node, |element| self.resolve(element)),
+            _ => node,
+        }
+    }
+
+    // references to references collapse to an lvalue reference, unless both are rvalue references
+    fn collapse<'a>(&self, inner: &'a Node, declarator: &'static str) -> (&'a Node, &'static str) {
+        let mut inner = self.resolve(inner);
+        let mut declarator = declarator;
+        if declarator == "*" {
+            return (inner, declarator);
+        }
+        while let Node::Pointer(next, next_declarator) = inner {
+            match *next_declarator {
+                "*" => break,
+                "&" => declarator = "&",
+                _ => {}
+            }
+            inner = self.resolve(next);
+        }
+        (inner, declarator)
+    }
+
+    fn parameters(&mut self, params: &[Rc<Node>]) {
+        self.out.push('(');
+        self.list(params);
+        self.out.push(')');
+    }
+
+    fn template_args(&mut self, args: &[Rc<Node>]) {
+        // avoid printing the << and >> operators
+        if self.out.ends_with('<') {
+            self.out.push(' ');
+        }
+        self.out.push('<');
+        self.elided_separator = false;
+        self.list(args);
+        // like c++filt, which misses the > before an empty pack at the end
+        if self.out.ends_with('>') && !self.elided_separator {
+            self.out.push(' ');
+        }
+        self.out.push('>');
+    }
+
+    // print the nodes separated by commas, skipping the ones that print nothing, e.g., empty packs
+    fn list(&mut self, nodes: &[Rc<Node>]) {
+        let mut first = true;
+        for node in nodes {
+            self.separated(node, &mut first);
+        }
+    }
+
+    fn separated(&mut self, node: &Node, first: &mut bool) {
+        let mark = self.out.len();
+        if !*first {
+            self.out.push_str(", ");
+        }
+        let start = self.out.len();
+        self.print(node);
+        if self.out.len() == start {
+            self.out.truncate(mark);
+            self.elided_separator = !*first;
+        } else {
+            *first = false;
+            self.elided_separator = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // expected outputs are the ones of GNU c++filt
+    fn check(cases: &[(&str, &str)]) {
+        for (mangled, demangled) in cases {
+            assert_eq!(
+                demangle(mangled).as_deref(),
+                Some(*demangled),
+                "{}",
+                mangled
+            );
+        }
+    }
+
+    #[test]
+    fn test_demangle_functions() {
+        check(&[
+            (
+                "_Z12matrixMulGPUPiS_S_iii",
+                "matrixMulGPU(int*, int*, int*, int, int, int)",
+            ),
+            (
+                "_Z16setScalarItemGPUiPiii",
+                "setScalarItemGPU(int, int*, int, int)",
+            ),
+            ("_Z3foov", "foo()"),
+            (
+                "_ZN2ns3fooEPKcRVdOf",
+                "ns::foo(char const*, double volatile&, float&&)",
+            ),
+            ("_ZNK3Foo3barEv", "Foo::bar() const"),
+            ("_ZN3FooC2ERKS_", "Foo::Foo(Foo const&)"),
+            ("_ZN3FooD0Ev", "Foo::~Foo()"),
+            (
+                "_ZN12_GLOBAL__N_16kernelEPf",
+                "(anonymous namespace)::kernel(float*)",
+            ),
+            ("_ZL6staticv", "static()"),
+            (
+                "_Z1fPFviEPA3_iM1AFvvEM1Ai",
+                "f(void (*)(int), int (*) [3], void (A::*)(), int A::*)",
+            ),
+            (
+                "_ZNSt6vectorIiSaIiEE9push_backERKi",
+                "std::vector<int, std::allocator<int> >::push_back(int const&)",
+            ),
+            ("_ZN3FooplERKS_", "Foo::operator+(Foo const&)"),
+            ("_ZN3FoocvbEv", "Foo::operator bool()"),
+            ("_Z1fIiEvT_", "void f<int>(int)"),
+            ("_Z3maxIiET_S0_S0_", "int max<int>(int, int)"),
+            (
+                "_Z5saxpyILi256EEvfPKfPf.constprop.0",
+                "void saxpy<256>(float, float const*, float*) [clone .constprop.0]",
+            ),
+            ("_ZZ4mainE5count", "main::count"),
+            (
+                "_ZZ4mainENKUliE_clEi",
+                "main::{lambda(int)#1}::operator()(int) const",
+            ),
+            ("_ZTV3Foo", "vtable for Foo"),
+            ("_ZThn8_N3Foo3barEv", "non-virtual thunk to Foo::bar()"),
+            ("_ZGVZ4mainE1x", "guard variable for main::x"),
+        ]);
+    }
+
+    #[test]
+    fn test_demangle_kernel_templates() {
+        check(&[
+            (
+                "_ZN7cutlass6KernelINS_4gemm6kernel4GemmINS1_11threadblock12MmaPipelinedINS1_9GemmShapeILi128ELi128ELi8EEEEELb1EEEEEvNT_6ParamsE",
+                "void cutlass::Kernel<cutlass::gemm::kernel::Gemm<cutlass::gemm::threadblock::MmaPipelined<cutlass::gemm::GemmShape<128, 128, 8> >, true> >(cutlass::gemm::kernel::Gemm<cutlass::gemm::threadblock::MmaPipelined<cutlass::gemm::GemmShape<128, 128, 8> >, true>::Params)",
+            ),
+            (
+                "_ZN2at6native29vectorized_elementwise_kernelILi4ENS0_11FillFunctorIfEENS_6detail5ArrayIPcLi1EEEEEviT0_T1_",
+                "void at::native::vectorized_elementwise_kernel<4, at::native::FillFunctor<float>, at::detail::Array<char*, 1> >(int, at::native::FillFunctor<float>, at::detail::Array<char*, 1>)",
+            ),
+            ("_Z6reduceIJifEEvDpPT_", "void reduce<int, float>(int*, float*)"),
+            ("_Z1fIJEEvDpT_", "void f<>()"),
+            ("_Z1fILj4ELm8ELl2ELin1ELc65ELb0EEvv", "void f<4u, 8ul, 2l, -1, (char)65, false>()"),
+            ("_Z1fILi4EEvRAplT_Li1E_i", "void f<4>(int (&) [(4)+(1)])"),
+            ("_Z6kernelIN3c104HalfEEvPT_S3_", "void kernel<c10::Half>(c10::Half*, c10::Half*)"),
+            ("_Z6kernelI6float4Lj128EEvPKT_PS1_", "void kernel<float4, 128u>(float4 const*, float4*)"),
+        ]);
+    }
+
+    #[test]
+    fn test_demangle_library_names() {
+        check(&[
+            (
+                "_ZN4llvm10make_errorINS_11StringErrorEJRA19_KcSt10error_codeEEENS_5ErrorEDpOT0_",
+                "llvm::Error llvm::make_error<llvm::StringError, char const (&) [19], std::error_code>(char const (&) [19], std::error_code&&)",
+            ),
+            ("_ZNKSs6lengthEv", "std::basic_string<char, std::char_traits<char>, std::allocator<char> >::length() const"),
+            (
+                "_ZNSsC1IPcEET_S1_RKSaIcE",
+                "std::basic_string<char, std::char_traits<char>, std::allocator<char> >::basic_string<char*>(char*, char*, std::allocator<char> const&)",
+            ),
+            (
+                "_ZN4llvm11PassManagerINS_6ModuleENS_15AnalysisManagerIS1_JEEEJEE10isRequiredEv",
+                "llvm::PassManager<llvm::Module, llvm::AnalysisManager<llvm::Module>>::isRequired()",
+            ),
+            ("_ZNSt8ios_base7failureB5cxx11D0Ev", "std::ios_base::failure[abi:cxx11]::~failure()"),
+            (
+                "_ZN4llvm10checkedAddIiEENSt9enable_ifIXsr3std9is_signedIT_EE5valueENS_8OptionalIS2_EEE4typeES2_S2_",
+                "std::enable_if<std::is_signed<int>::value, llvm::Optional<int> >::type llvm::checkedAdd<int>(int, int)",
+            ),
+            (
+                "_ZN4llvm25ComputeMappedEditDistanceIcZNS_19ComputeEditDistanceIcEEjNS_8ArrayRefIT_EES4_bjEUlRKcE_EEjS4_S4_T0_bj",
+                "unsigned int llvm::ComputeMappedEditDistance<char, llvm::ComputeEditDistance<char>(llvm::ArrayRef<char>, llvm::ArrayRef<char>, bool, unsigned int)::{lambda(char const&)#1}>(llvm::ArrayRef<char>, llvm::ArrayRef<char>, llvm::ComputeEditDistance<char>(llvm::ArrayRef<char>, llvm::ArrayRef<char>, bool, unsigned int)::{lambda(char const&)#1}, bool, unsigned int)",
+            ),
+            // the T_ of the function the lambda is local to is substituted in the parameters of the outer template
+            (
+                "_ZSt16__insertion_sortIPN4llvm3cfg6UpdateIPNS0_10BasicBlockEEEN9__gnu_cxx5__ops15_Iter_comp_iterIZNS1_15LegalizeUpdatesIS4_EEvNS0_8ArrayRefINS2_IT_EEEERNS0_15SmallVectorImplISD_EEbbEUlRKS5_SJ_E_EEEvSC_SC_T0_",
+                "void std::__insertion_sort<llvm::cfg::Update<llvm::BasicBlock*>*, __gnu_cxx::__ops::_Iter_comp_iter<llvm::cfg::LegalizeUpdates<llvm::BasicBlock*>(llvm::ArrayRef<llvm::cfg::Update<llvm::BasicBlock*> >, llvm::SmallVectorImpl<llvm::cfg::Update<llvm::BasicBlock*> >&, bool, bool)::{lambda(llvm::cfg::Update<llvm::BasicBlock*> const&, llvm::cfg::Update<llvm::BasicBlock*> const&)#1}> >(llvm::cfg::Update<llvm::BasicBlock*>*, llvm::cfg::Update<llvm::BasicBlock*>*, __gnu_cxx::__ops::_Iter_comp_iter<llvm::cfg::LegalizeUpdates<llvm::BasicBlock*>(llvm::ArrayRef<llvm::cfg::Update<llvm::BasicBlock*> >, llvm::SmallVectorImpl<llvm::cfg::Update<llvm::BasicBlock*> >&, bool, bool)::{lambda(llvm::cfg::Update<llvm::BasicBlock*> const&, llvm::cfg::Update<llvm::BasicBlock*> const&)#1}>)",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_not_demangled() {
+        for name in [
+            "matrixMulGPU",
+            "_Z",
+            "_Z3fo",
+            "_Z1fv.",
+            "_Z1fIiEvT0_",
+            "_Z1fS_",
+            "_Z1fDTfp_E",
+            "_ZN2ns3fooE!",
+        ] {
+            assert_eq!(demangle(name), None, "{}", name);
+        }
+        // deep nesting is rejected instead of overflowing the stack
+        let deep = format!("_Z1f{}i", "P".repeat(100_000));
+        assert_eq!(demangle(&deep), None);
+        // substitutions referring to each other do not blow up the output
+        let mut exponential = "_Z1fPFviE".to_string();
+        for i in 0..40 {
+            exponential.push_str(&format!("FvS{}_S{}_E", i, i));
+        }
+        assert!(demangle(&exponential).is_none());
+    }
+
+    #[test]
+    fn test_kernel_names() {
+        let names = kernel_names(&[
+            "_Z16setScalarItemGPUiPiii".to_string(),
+            "extern_c_kernel".to_string(),
+        ]);
+        assert_eq!(names[0].mangled, "_Z16setScalarItemGPUiPiii");
+        assert_eq!(
+            names[0].to_string(),
+            "setScalarItemGPU(int, int*, int, int)"
+        );
+        assert_eq!(names[1].demangled, None);
+        assert_eq!(names[1].to_string(), "extern_c_kernel");
+        assert_eq!(
+            serde_json::to_value(&names[1]).unwrap(),
+            serde_json::json!({"mangled": "extern_c_kernel", "demangled": null})
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use memmap2::{Mmap, MmapMut};
+use std::fs::{File, OpenOptions};
+use std::ops::{Deref, DerefMut};
+
+/// A read-only memory mapping of a whole file
+///
+/// Libraries are mapped instead of read so that parsing a multi-GB library only pages in what is touched,
+/// e.g., the ELF headers, the dynamic section and the fatbins.
+pub struct MappedFile {
+    mmap: Mmap,
+}
+
+impl MappedFile {
+    /// Map the file at the given path
+    pub fn open(path: &str) -> std::io::Result<MappedFile> {
+        let file = File::open(path)?;
+        // safety: nothing may truncate or rewrite the file while it is mapped, accessing a page past a truncated
+        // end raises SIGBUS; the identity checks only catch changes made before the file was opened
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MappedFile { mmap })
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+/// A writable, shared memory mapping of a whole file, changes are written back to the file
+pub struct MappedFileMut {
+    mmap: MmapMut,
+    file: File,
+}
+
+impl MappedFileMut {
+    /// Map the file at the given path for writing
+    pub fn open(path: &str) -> std::io::Result<MappedFileMut> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // safety: nothing else may truncate or rewrite the file while it is mapped, it is a private copy made
+        // by the reconstructor
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+        Ok(MappedFileMut { mmap, file })
+    }
+
+    /// Write the changes back to the file
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.mmap.flush()
+    }
+
+    /// Write the changes back and cut the file to the given length, the mapping is gone before the file shrinks
+    pub fn truncate(self, len: u64) -> std::io::Result<()> {
+        self.mmap.flush()?;
+        let MappedFileMut { mmap, file } = self;
+        drop(mmap);
+        file.set_len(len)
+    }
+}
+
+impl Deref for MappedFileMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl DerefMut for MappedFileMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_mapped_file() {
+        let path = fixture("libdemo.so");
+        let mapped = MappedFile::open(&path).unwrap();
+        assert_eq!(&mapped[..], &std::fs::read(&path).unwrap()[..]);
+        assert!(MappedFile::open(&fixture("no_such_file.so")).is_err());
+
+        // empty files map to an empty slice
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty.so");
+        std::fs::write(&empty, b"").unwrap();
+        assert!(MappedFile::open(empty.to_str().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_mapped_file_mut() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("libsynth.64le.so");
+        std::fs::copy(fixture("libsynth.64le.so"), &path).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut mapped = MappedFileMut::open(path).unwrap();
+        mapped[0x400..0x410].fill(0x01);
+        mapped.flush().unwrap();
+        drop(mapped);
+
+        let data = std::fs::read(path).unwrap();
+        assert!(data[0x400..0x410].iter().all(|&b| b == 0x01));
+        assert_eq!(data[0x410], 0x90);
+
+        let mut mapped = MappedFileMut::open(path).unwrap();
+        mapped[0x3f0] = 0x02;
+        mapped.truncate(0x400).unwrap();
+        assert!(
+            std::fs::read(path).unwrap() == [&data[..0x3f0], &[0x02], &data[0x3f1..0x400]].concat()
+        );
+    }
+}
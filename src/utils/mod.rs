@@ -1 +1,4 @@
+pub mod demangle;
+pub mod mapped;
+pub mod report_names;
 pub mod utils;
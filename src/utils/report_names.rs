@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Name the report of each object uniquely, from its path and its preferred name, e.g., its file name.
+///
+/// Names are escaped so that they hold no path separator, and a name preferred by objects of different paths,
+/// e.g., libfoo.so of two wheels, gets the hash of each path appended, as in `libfoo.so.5d1f0c3a9e2b4f67`.
+/// Returns the name of each object in order, None for an object listed again or whose name is still taken.
+pub fn report_names<'a, I>(objects: I) -> Vec<Option<String>>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let objects: Vec<(&str, String)> = objects
+        .into_iter()
+        .map(|(path, name)| (path, escape(name)))
+        .collect();
+    let mut paths_by_name: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (path, name) in objects.iter() {
+        paths_by_name.entry(name).or_default().insert(path);
+    }
+
+    let mut seen_paths = HashSet::new();
+    let mut taken = HashSet::new();
+    let mut names = vec![];
+    for (path, name) in objects.iter() {
+        if !seen_paths.insert(*path) {
+            names.push(None);
+            continue;
+        }
+        let name = if paths_by_name[name.as_str()].len() > 1 {
+            format!("{}.{}", name, path_hash(path))
+        } else {
+            name.clone()
+        };
+        names.push(taken.insert(name.clone()).then_some(name));
+    }
+    names
+}
+
+/// Get the hash of an object path, which tells apart objects of the same name, as 16 hex digits.
+pub fn path_hash(path: &str) -> String {
+    format!("{:016x}", xxh3_64(path.as_bytes()))
+}
+
+// escape a name into a single path component, different names stay different
+fn escape(name: &str) -> String {
+    match name {
+        "" => "%".to_string(),
+        "." | ".." => name.replace('.', "%2E"),
+        _ => name.replace('%', "%25").replace('/', "%2F"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_names() {
+        let names = report_names([
+            ("/usr/lib/libdemo.so", "libdemo.so"),
+            ("a.whl!/x/libfoo.so", "libfoo.so"),
+            ("b.whl!/y/libfoo.so", "libfoo.so"),
+            ("lib.a(foo.o)", "lib.a(foo.o)"),
+            ("/cache/a/b_c.cubin", "a/b_c.cubin"),
+            ("/cache/a_b/c.cubin", "a_b/c.cubin"),
+            ("/usr/lib/libdemo.so", "libdemo.so"),
+            ("/opt/..", ".."),
+        ]);
+        assert_eq!(names[0].as_deref(), Some("libdemo.so"));
+        assert_eq!(
+            names[1],
+            Some(format!("libfoo.so.{:016x}", xxh3_64(b"a.whl!/x/libfoo.so")))
+        );
+        assert_ne!(names[1], names[2]);
+        assert_eq!(names[3].as_deref(), Some("lib.a(foo.o)"));
+        assert_eq!(names[4].as_deref(), Some("a%2Fb_c.cubin"));
+        assert_eq!(names[5].as_deref(), Some("a_b%2Fc.cubin"));
+        // listed again, e.g., both traced and passed as input
+        assert_eq!(names[6], None);
+        assert_eq!(names[7].as_deref(), Some("%2E%2E"));
+
+        // a name already taken by another object is not reused
+        let hashed = format!("k.cubin.{:016x}", xxh3_64(b"/x/k.cubin"));
+        let names = report_names([
+            ("/x/k.cubin", "k.cubin"),
+            ("/y/k.cubin", "k.cubin"),
+            ("/z/file", hashed.as_str()),
+        ]);
+        assert_eq!(names[0].as_deref(), Some(hashed.as_str()));
+        assert!(names[1].is_some());
+        assert_eq!(names[2], None);
+    }
+}
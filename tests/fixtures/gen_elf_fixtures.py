@@ -0,0 +1,821 @@
+#!/usr/bin/env python3
+"""Generate the synthetic ELF fixtures used by the unit tests.
+
+The same small shared object is emitted for every ELF class and byte order:
+
+    libsynth.64le.so  ELFCLASS64, little endian, EM_X86_64
+    libsynth.32le.so  ELFCLASS32, little endian, EM_386
+    libsynth.64be.so  ELFCLASS64, big endian,    EM_PPC64
+    libsynth.32be.so  ELFCLASS32, big endian,    EM_PPC
+
+Each one carries exported .text and .data symbols plus a local-only symbol,
+a .nv_fatbin section copied from the first fatbin region of libdemo.so, both
+.dynsym and .symtab tables, SysV (.hash) and GNU (.gnu.hash) hash tables, and
+a .dynamic section. They also define fixture_versioned twice, as the hidden
+fixture_versioned@VERS_1 and the default fixture_versioned@@VERS_2, through
+.gnu.version and .gnu.version_d, and carry the NT_GNU_BUILD_ID 10 11 .. 23 in a
+.note.gnu.build-id section covered by PT_NOTE.
+
+A small dependency tree for the static dependency resolver is emitted under
+deps/:
+
+    deps/bin/app                  RPATH $ORIGIN/../lib, needs libfirst.so
+    deps/lib/libfirst.so          needs libsecond.so and libmissing.so.1
+    deps/lib/libsecond.so         RUNPATH $ORIGIN/private, needs libthird.so
+    deps/lib/private/libthird.so  no dependencies
+    deps/lib32/libsecond.so       ELFCLASS32, must be skipped for 64-bit requesters
+
+libfatbins.so (ELFCLASS64, little endian) embeds the same fatbin region four
+times, once in each way the device code discovery has to handle:
+
+    .nv_fatbin        found by section name, also wrapped by __fatbinwrap #0
+    __nv_relfatbin    found by section name
+    .rodata + 0x10    wrapped by __fatbinwrap #1, whose data pointer is only
+                      set by an R_X86_64_RELATIVE relocation in .rela.dyn
+    .rodata + 0x788   neither named nor wrapped, found by the magic scan
+
+The same layout is emitted for the other little endian hosts CUDA supports,
+with the relocation type of each machine:
+
+    libfatbins.aarch64.so  EM_AARCH64, R_AARCH64_RELATIVE
+    libfatbins.ppc64le.so  EM_PPC64,   R_PPC64_RELATIVE
+
+libunits.so has the same layout as libfatbins.so, with the fatbins attributed
+to compilation units the way nvcc does:
+
+    .nv_fatbin        fatbinData label, scoped under the STT_FILE link.stub
+    __nv_relfatbin    its first element names kernels/scale.cu as identifier
+    .rodata + 0x10    __fatbinwrap_* symbol, scoped under the STT_FILE of the
+                      matmul.cu intermediate file
+    .rodata + 0x788   no compilation unit
+
+libbloated.so (ELFCLASS64, little endian) concatenates eight copies of the
+fatbin region in .nv_fatbin, which spans several pages, in a read-only PT_LOAD
+segment of its own after the RW segment, so that shrinking .nv_fatbin cuts the
+end of the loaded content without splitting a segment.
+
+libcompressed.so (ELFCLASS64, little endian) carries the second fatbin region
+of libdemo.so, the one with kernels, in .nv_fatbin with both elements compressed, the way nvcc --compress-mode does:
+the sm_70 cubin as an LZ4 block, NVIDIA's LZ scheme, and the sm_75 cubin as a
+zstd frame of one raw block.
+
+calls.sm_70.cubin is a relocatable sm_70 cubin (EM_CUDA, ABI version 7) with
+device calls between its functions, for the device call graph:
+
+    _Z6launchv   kernel, launches _Z5childv through dynamic parallelism and
+                 references _Z16setScalarItemGPUiPiii, a kernel of
+                 libdemo.3.sm_70.cubin, both by relocations in
+                 .rel.text._Z6launchv
+    _Z5childv    kernel, calls _Z6squarei, an edge of .nv.callgraph
+    _Z6squarei   device function in its own .text section
+    _Z6unusedv   kernel nobody calls
+
+libsynth.64le.so and libfatbins.so are also packed into containers, to be analyzed without unpacking:
+
+    libsynth.a                 ar archive with a GNU long name table: synth.o
+                               (libsynth.64le.so), fatbins_with_long_name.o
+                               (libfatbins.so) and a non-ELF README member
+    synth-0.1-py3-none-any.whl zip with synth/lib/libsynth.so stored,
+                               synth/lib/libfatbins.so deflated and a
+                               non-ELF synth/__init__.py
+
+Usage: python3 gen_elf_fixtures.py (run from this directory)
+"""
+
+import os
+import struct
+import zipfile
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+PAGE = 0x1000
+# fixed so that symbol and section offsets do not depend on the table sizes
+TEXT_OFFSET = 0x400
+
+SHT_PROGBITS = 1
+SHT_SYMTAB = 2
+SHT_STRTAB = 3
+SHT_RELA = 4
+SHT_HASH = 5
+SHT_DYNAMIC = 6
+SHT_NOTE = 7
+SHT_NOBITS = 8
+SHT_GNU_VERDEF = 0x6FFFFFFD
+SHT_GNU_VERSYM = 0x6FFFFFFF
+SHT_REL = 9
+SHT_DYNSYM = 11
+SHT_GNU_HASH = 0x6FFFFFF6
+
+SHF_WRITE = 0x1
+SHF_ALLOC = 0x2
+SHF_EXECINSTR = 0x4
+
+PT_LOAD = 1
+PT_DYNAMIC = 2
+PT_INTERP = 3
+PT_NOTE = 4
+NT_GNU_BUILD_ID = 3
+BUILD_ID = bytes(range(0x10, 0x24))
+PF_X = 0x1
+PF_W = 0x2
+PF_R = 0x4
+
+STB_LOCAL = 0
+STB_GLOBAL = 1
+STT_NOTYPE = 0
+STT_OBJECT = 1
+STT_FUNC = 2
+STT_FILE = 4
+SHN_ABS = 0xFFF1
+
+DT_NULL = 0
+DT_NEEDED = 1
+DT_HASH = 4
+DT_STRTAB = 5
+DT_SYMTAB = 6
+DT_STRSZ = 10
+DT_SYMENT = 11
+DT_SONAME = 14
+DT_RPATH = 15
+DT_RUNPATH = 29
+DT_GNU_HASH = 0x6FFFFEF5
+DT_VERSYM = 0x6FFFFFF0
+DT_VERDEF = 0x6FFFFFFC
+DT_VERDEFNUM = 0x6FFFFFFD
+
+DT_RELA = 7
+DT_RELASZ = 8
+DT_RELAENT = 9
+R_X86_64_RELATIVE = 8
+R_AARCH64_RELATIVE = 1027
+R_PPC64_RELATIVE = 22
+FATBIN_WRAPPER_MAGIC = 0x466243B1
+
+VER_FLG_BASE = 0x1
+VER_NDX_GLOBAL = 1
+VERSYM_HIDDEN = 0x8000
+
+EM_386 = 3
+EM_PPC = 20
+EM_PPC64 = 21
+EM_X86_64 = 62
+EM_AARCH64 = 183
+EM_CUDA = 190
+
+ET_EXEC = 2
+ELFOSABI_CUDA = 0x33
+# cubin e_flags of ABI version 7: SM 70 and virtual arch 70, 64-bit addresses, unified texture mode
+CUBIN_ABI_VERSION = 7
+CUBIN_SM70_FLAGS = 0x460546
+SHT_CUDA_CALLGRAPH = 0x70000001
+# the ends of the .nv.callgraph list, written as callees of symbol 0
+CALLGRAPH_MARKERS = (0xFFFFFFFF, 0xFFFFFFFE, 0xFFFFFFFD, 0xFFFFFFFC)
+R_CUDA_32 = 1
+
+# the relocation type that adds the load base to the addend, per e_machine
+RELATIVE = {EM_X86_64: R_X86_64_RELATIVE, EM_AARCH64: R_AARCH64_RELATIVE, EM_PPC64: R_PPC64_RELATIVE}
+
+# (class bits, big endian, e_machine, file name)
+VARIANTS = [
+    (64, False, EM_X86_64, "libsynth.64le.so"),
+    (32, False, EM_386, "libsynth.32le.so"),
+    (64, True, EM_PPC64, "libsynth.64be.so"),
+    (32, True, EM_PPC, "libsynth.32be.so"),
+]
+
+
+def sysv_hash(name):
+    h = 0
+    for c in name.encode():
+        h = ((h << 4) + c) & 0xFFFFFFFF
+        g = h & 0xF0000000
+        if g:
+            h ^= g >> 24
+        h &= ~g & 0xFFFFFFFF
+    return h
+
+
+def gnu_hash(name):
+    h = 5381
+    for c in name.encode():
+        h = (h * 33 + c) & 0xFFFFFFFF
+    return h
+
+
+def align(value, alignment):
+    return (value + alignment - 1) // alignment * alignment
+
+
+class StrTab:
+    def __init__(self):
+        self.data = bytearray(b"\0")
+        self.offsets = {"": 0}
+
+    def add(self, name):
+        if name not in self.offsets:
+            self.offsets[name] = len(self.data)
+            self.data += name.encode() + b"\0"
+        return self.offsets[name]
+
+
+class Section:
+    def __init__(self, name, sh_type, flags=0, data=b"", addralign=1, entsize=0, nobits_size=0):
+        self.name = name
+        self.nobits_size = nobits_size
+        self.sh_type = sh_type
+        self.flags = flags
+        self.data = bytes(data)
+        self.addralign = addralign
+        self.entsize = entsize
+        self.link = 0
+        self.info = 0
+        self.offset = 0
+        self.addr = 0
+
+    @property
+    def size(self):
+        return self.nobits_size if self.sh_type == SHT_NOBITS else len(self.data)
+
+
+class Writer:
+    def __init__(self, bits, big_endian):
+        self.bits = bits
+        self.bo = ">" if big_endian else "<"
+
+    def pack(self, fmt, *values):
+        return struct.pack(self.bo + fmt, *values)
+
+    def ehdr_size(self):
+        return 64 if self.bits == 64 else 52
+
+    def phdr_size(self):
+        return 56 if self.bits == 64 else 32
+
+    def shdr_size(self):
+        return 64 if self.bits == 64 else 40
+
+    def sym_size(self):
+        return 24 if self.bits == 64 else 16
+
+    def word(self):
+        return "Q" if self.bits == 64 else "I"
+
+    def sysv_hash_table(self, names):
+        # names[0] is the null symbol
+        nbucket = 3
+        buckets = [0] * nbucket
+        chains = [0] * len(names)
+        for i in range(len(names) - 1, 0, -1):
+            b = sysv_hash(names[i]) % nbucket
+            chains[i] = buckets[b]
+            buckets[b] = i
+        return self.pack("II", nbucket, len(names)) + self.pack("I" * (nbucket + len(names)), *buckets, *chains)
+
+    def gnu_hash_table(self, names, nbucket):
+        # names[0] is the null symbol, names[1:] must already be sorted by bucket
+        bloom_bits = self.bits
+        bloom_shift = 6
+        bloom = 0
+        buckets = [0] * nbucket
+        chains = []
+        hashes = [gnu_hash(n) for n in names[1:]]
+        for i, h in enumerate(hashes):
+            bloom |= 1 << (h % bloom_bits)
+            bloom |= 1 << ((h >> bloom_shift) % bloom_bits)
+            b = h % nbucket
+            if buckets[b] == 0:
+                buckets[b] = i + 1
+            last = i + 1 == len(hashes) or hashes[i + 1] % nbucket != b
+            chains.append((h & ~1) | (1 if last else 0))
+        return (
+            self.pack("IIII", nbucket, 1, 1, bloom_shift)
+            + self.pack(self.word(), bloom)
+            + self.pack("I" * (nbucket + len(chains)), *buckets, *chains)
+        )
+
+    def verdefs(self, defs, dynstr_tab):
+        # defs: (flags, index, names), names[0] is the version, the rest are its parents
+        out = b""
+        for i, (flags, ndx, names) in enumerate(defs):
+            last = i + 1 == len(defs)
+            size = 20 + 8 * len(names)
+            out += self.pack("HHHHIII", 1, flags, ndx, len(names), sysv_hash(names[0]), 20, 0 if last else size)
+            for j, name in enumerate(names):
+                out += self.pack("II", dynstr_tab.offsets[name], 0 if j + 1 == len(names) else 8)
+        return out
+
+    def dyn(self, tag, value):
+        return self.pack(self.word() * 2, tag, value)
+
+    def sym(self, name, value, size, info, shndx):
+        if self.bits == 64:
+            return self.pack("IBBHQQ", name, info, 0, shndx, value, size)
+        return self.pack("IIIBBH", name, value, size, info, 0, shndx)
+
+    def phdr(self, p_type, flags, offset, vaddr, filesz, memsz, p_align):
+        if self.bits == 64:
+            return self.pack("IIQQQQQQ", p_type, flags, offset, vaddr, vaddr, filesz, memsz, p_align)
+        return self.pack("IIIIIIII", p_type, offset, vaddr, vaddr, filesz, memsz, flags, p_align)
+
+    def shdr(self, name, s):
+        if self.bits == 64:
+            return self.pack(
+                "IIQQQQIIQQ", name, s.sh_type, s.flags, s.addr, s.offset, s.size,
+                s.link, s.info, s.addralign, s.entsize,
+            )
+        return self.pack(
+            "IIIIIIIIII", name, s.sh_type, s.flags, s.addr, s.offset, s.size,
+            s.link, s.info, s.addralign, s.entsize,
+        )
+
+    def ehdr(self, machine, phoff, phnum, shoff, shnum, shstrndx):
+        ident = b"\x7fELF" + bytes([
+            2 if self.bits == 64 else 1,
+            2 if self.bo == ">" else 1,
+            1,
+        ]) + b"\0" * 9
+        return ident + self.pack(
+            "HHI" + self.word() * 3 + "IHHHHHH",
+            3, machine, 1, 0, phoff, shoff, 0,
+            self.ehdr_size(), self.phdr_size(), phnum, self.shdr_size(), shnum, shstrndx,
+        )
+
+
+def fatbin_region():
+    with open(os.path.join(HERE, "libdemo.so"), "rb") as f:
+        f.seek(0x948D0)
+        return f.read(0x770)
+
+
+def kernels_region():
+    # the second fatbin region of libdemo.so, whose cubins hold the kernels of demo.cu
+    with open(os.path.join(HERE, "libdemo.so"), "rb") as f:
+        f.seek(0x95040)
+        return f.read(0x5C70)
+
+
+def with_identifier(region, identifier):
+    # grow the header of the first element by the NUL-terminated identifier, padded to 8 bytes
+    name = identifier.encode() + b"\0"
+    name += b"\0" * (align(len(name), 8) - len(name))
+    fat_size = struct.unpack_from("<Q", region, 8)[0]
+    header_size = struct.unpack_from("<I", region, 20)[0]
+    header = bytearray(region[16:16 + header_size])
+    struct.pack_into("<I", header, 4, header_size + len(name))
+    struct.pack_into("<II", header, 32, header_size, len(identifier))
+    return (
+        region[:8] + struct.pack("<Q", fat_size + len(name))
+        + bytes(header) + name + region[16 + header_size:]
+    )
+
+
+def lz4_block(data):
+    # greedy LZ4 block compressor, the last 5 bytes are literals and the last match starts 12 bytes before the end
+    def length(n):
+        out = b""
+        while n >= 255:
+            out += b"\xff"
+            n -= 255
+        return out + bytes([n])
+
+    def sequence(literals, offset=None, match=0):
+        token = min(len(literals), 15) << 4 | (min(match - 4, 15) if offset else 0)
+        out = bytes([token]) + (length(len(literals) - 15) if len(literals) >= 15 else b"") + literals
+        if offset:
+            out += struct.pack("<H", offset) + (length(match - 4 - 15) if match - 4 >= 15 else b"")
+        return out
+
+    out, table, anchor, i = b"", {}, 0, 0
+    while i + 12 <= len(data):
+        candidate = table.get(data[i:i + 4])
+        table[data[i:i + 4]] = i
+        if candidate is not None and i - candidate <= 0xFFFF:
+            match = 4
+            while i + match < len(data) - 5 and data[candidate + match] == data[i + match]:
+                match += 1
+            out += sequence(data[anchor:i], i - candidate, match)
+            i += match
+            anchor = i
+        else:
+            i += 1
+    return out + sequence(data[anchor:])
+
+
+def zstd_frame(data):
+    # single segment frame with a 2-byte content size and one raw block, without checksum
+    assert 256 <= len(data) < 0x10000 + 256
+    block = len(data) << 3 | 1
+    return (
+        b"\x28\xb5\x2f\xfd" + bytes([0x60]) + struct.pack("<H", len(data) - 256)
+        + struct.pack("<I", block)[:3] + data
+    )
+
+
+def compressed_region(region):
+    # compress the payload of each element, growing or shrinking the region to the padded compressed sizes
+    fat_size = struct.unpack_from("<Q", region, 8)[0]
+    elements, offset = b"", 16
+    for compress in (lz4_block, zstd_frame):
+        header_size, size = struct.unpack_from("<IQ", region, offset + 4)
+        header = bytearray(region[offset:offset + header_size])
+        payload = region[offset + header_size:offset + header_size + size]
+        compressed = compress(payload)
+        flags = struct.unpack_from("<Q", header, 40)[0]
+        struct.pack_into("<QI", header, 8, align(len(compressed), 8), len(compressed))
+        struct.pack_into("<Q", header, 40, flags | 0x2000)
+        struct.pack_into("<Q", header, 56, len(payload))
+        elements += bytes(header) + compressed + b"\0" * (align(len(compressed), 8) - len(compressed))
+        offset += header_size + size
+    assert offset == 16 + fat_size
+    return region[:8] + struct.pack("<Q", len(elements)) + elements
+
+
+def build(
+    bits, big_endian, machine, needed=(), soname=None, rpath=None, runpath=None, interp=None,
+    versioned=False, build_id=None, fatbins=False, fatbin_copies=1, units=False, compressed=False,
+    fatbin_segment=False,
+):
+    w = Writer(bits, big_endian)
+    assert not fatbins or (bits == 64 and not big_endian)
+    assert not units or fatbins
+    assert not fatbin_segment or not fatbins
+
+    text = Section(".text", SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, b"\x90" * 0x40, 16)
+    fatbin_data = compressed_region(kernels_region()) if compressed else fatbin_region() * fatbin_copies
+    fatbin = Section(".nv_fatbin", SHT_PROGBITS, SHF_ALLOC, fatbin_data, 8)
+    data = Section(".data", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, struct.pack("<I", 7) + b"\0" * 12, 8)
+    bss = Section(".bss", SHT_NOBITS, SHF_ALLOC | SHF_WRITE, b"", 8, 0, 0x20)
+    interp_ = Section(".interp", SHT_PROGBITS, SHF_ALLOC, (interp or "").encode() + b"\0")
+    dynamic = Section(".dynamic", SHT_DYNAMIC, SHF_ALLOC | SHF_WRITE, b"", 8, 2 * (w.bits // 8))
+    dynstr_tab = StrTab()
+    strtab_tab = StrTab()
+    dynsym = Section(".dynsym", SHT_DYNSYM, SHF_ALLOC, b"", 8, w.sym_size())
+    dynstr = Section(".dynstr", SHT_STRTAB, SHF_ALLOC)
+    hash_ = Section(".hash", SHT_HASH, SHF_ALLOC, b"", 4, 4)
+    gnu_hash_ = Section(".gnu.hash", SHT_GNU_HASH, SHF_ALLOC, b"", 8)
+    note = Section(".note.gnu.build-id", SHT_NOTE, SHF_ALLOC, b"", 4)
+    if build_id is not None:
+        note.data = w.pack("III", 4, len(build_id), NT_GNU_BUILD_ID) + b"GNU\0" + build_id
+    versym = Section(".gnu.version", SHT_GNU_VERSYM, SHF_ALLOC, b"", 2, 2)
+    verdef = Section(".gnu.version_d", SHT_GNU_VERDEF, SHF_ALLOC, b"", 4)
+    region = fatbin_region()
+    relfatbin = Section(
+        "__nv_relfatbin", SHT_PROGBITS, SHF_ALLOC,
+        with_identifier(region, "kernels/scale.cu") if units else region, 8,
+    )
+    rodata = Section(".rodata", SHT_PROGBITS, SHF_ALLOC, b"\0" * 0x10 + region + b"\0" * 8 + region, 16)
+    rela = Section(".rela.dyn", SHT_RELA, SHF_ALLOC, b"\0" * 24, 8, 24)
+    wrappers = Section(".nvFatBinSegment", SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, b"\0" * 48, 8)
+    symtab = Section(".symtab", SHT_SYMTAB, 0, b"", 8, w.sym_size())
+    strtab = Section(".strtab", SHT_STRTAB)
+    shstrtab = Section(".shstrtab", SHT_STRTAB)
+
+    # index 0 is the null section
+    sections = [
+        None, interp_, note, dynsym, dynstr, hash_, gnu_hash_, versym, verdef, rela,
+        text, fatbin, relfatbin, rodata, data, wrappers, dynamic, bss, symtab, strtab, shstrtab,
+    ]
+    if not fatbins:
+        for s in (rela, relfatbin, rodata, wrappers):
+            sections.remove(s)
+    if interp is None:
+        sections.remove(interp_)
+    if build_id is None:
+        sections.remove(note)
+    if not versioned:
+        sections.remove(versym)
+        sections.remove(verdef)
+    index = {s.name: i for i, s in enumerate(sections) if s is not None}
+
+    # (name, section, value, size, binding, type, version); local symbols only go to .symtab
+    # version is None or (index, name, hidden), STT_FILE symbols have no section
+    symbols = [
+        ("fixture_local", text, 0x30, 0x10, STB_LOCAL, STT_FUNC, None),
+    ]
+    if units:
+        symbols += [
+            ("link.stub", None, 0, 0, STB_LOCAL, STT_FILE, None),
+            ("fatbinData", fatbin, 0x0, 0x0, STB_LOCAL, STT_NOTYPE, None),
+            ("tmpxft_00001a2b_00000000-6_matmul.cudafe1.cpp", None, 0, 0, STB_LOCAL, STT_FILE, None),
+            ("__fatbinwrap_38_tmpxft_00001a2b_00000000_6_matmul_cpp1_ii_1f2e3d4c", wrappers, 0x18, 0x18,
+             STB_LOCAL, STT_OBJECT, None),
+        ]
+    symbols += [
+        ("fixture_add", text, 0x10, 0x20, STB_GLOBAL, STT_FUNC, None),
+        ("fixture_mul", text, 0x20, 0x10, STB_GLOBAL, STT_FUNC, None),
+        ("fixture_counter", data, 0x0, 0x4, STB_GLOBAL, STT_OBJECT, None),
+        ("fixture_table", data, 0x8, 0x8, STB_GLOBAL, STT_OBJECT, None),
+        ("fixture_bss", bss, 0x0, 0x20, STB_GLOBAL, STT_OBJECT, None),
+    ]
+    if versioned:
+        symbols += [
+            ("fixture_versioned", text, 0x34, 0x4, STB_GLOBAL, STT_FUNC, (2, "VERS_1", True)),
+            ("fixture_versioned", text, 0x38, 0x4, STB_GLOBAL, STT_FUNC, (3, "VERS_2", False)),
+        ]
+
+    # .symtab spells versions out in the name, like binutils does
+    def symtab_name(sym):
+        version = sym[6]
+        if version is None:
+            return sym[0]
+        return sym[0] + ("@" if version[2] else "@@") + version[1]
+
+    gnu_nbucket = 2
+    exported = sorted(
+        (s for s in symbols if s[4] == STB_GLOBAL),
+        key=lambda s: gnu_hash(s[0]) % gnu_nbucket,
+    )
+    for name, *_ in exported:
+        dynstr_tab.add(name)
+    dyn_strings = [(DT_NEEDED, n) for n in needed]
+    for tag, value in ((DT_SONAME, soname), (DT_RPATH, rpath), (DT_RUNPATH, runpath)):
+        if value is not None:
+            dyn_strings.append((tag, value))
+    for _, value in dyn_strings:
+        dynstr_tab.add(value)
+    version_defs = [(VER_FLG_BASE, VER_NDX_GLOBAL, [soname or "base"]), (0, 2, ["VERS_1"]), (0, 3, ["VERS_2", "VERS_1"])]
+    if versioned:
+        for _, _, names in version_defs:
+            for name in names:
+                dynstr_tab.add(name)
+    for sym in symbols:
+        strtab_tab.add(symtab_name(sym))
+    dynstr.data = bytes(dynstr_tab.data)
+    strtab.data = bytes(strtab_tab.data)
+    dynsym.data = b"\0" * w.sym_size() * (len(exported) + 1)
+    dyn_names = [""] + [s[0] for s in exported]
+    hash_.data = w.sysv_hash_table(dyn_names)
+    gnu_hash_.data = w.gnu_hash_table(dyn_names, gnu_nbucket)
+    versym.data = w.pack("H" * len(dyn_names), 0, *(
+        VER_NDX_GLOBAL if s[6] is None else s[6][0] | (VERSYM_HIDDEN if s[6][2] else 0)
+        for s in exported
+    ))
+    if versioned:
+        verdef.data = w.verdefs(version_defs, dynstr_tab)
+
+    phnum = 3 + (interp is not None) + (build_id is not None) + fatbin_segment
+    offset = w.ehdr_size() + w.phdr_size() * phnum
+
+    # R|X segment: headers, dynamic symbols, text and device code, vaddr == offset
+    rx_sections = [s for s in sections if s in (interp_, note, dynsym, dynstr, hash_, gnu_hash_, versym, verdef, rela)]
+    rx_sections += [s for s in sections if s in (text, fatbin, relfatbin, rodata) and not (s is fatbin and fatbin_segment)]
+    for s in rx_sections:
+        offset = align(offset, s.addralign)
+        if s is text:
+            assert offset <= TEXT_OFFSET
+            offset = TEXT_OFFSET
+        s.offset = offset
+        s.addr = offset
+        offset += len(s.data)
+    rx_end = offset
+
+    # RW segment: one page further in memory than in the file, .bss only in memory
+    offset = align(offset, 16)
+    data.offset = offset
+    data.addr = offset + PAGE
+    offset += len(data.data)
+    if fatbins:
+        offset = align(offset, wrappers.addralign)
+        wrappers.offset = offset
+        wrappers.addr = offset + PAGE
+        offset += len(wrappers.data)
+        # the second wrapper's data pointer is left to the dynamic linker
+        wrappers.data = (
+            w.pack("IIQQ", FATBIN_WRAPPER_MAGIC, 1, fatbin.addr, 0)
+            + w.pack("IIQQ", FATBIN_WRAPPER_MAGIC, 2, 0, 0)
+        )
+        rela.data = w.pack("QQq", wrappers.addr + 24 + 8, RELATIVE[machine], rodata.addr + 0x10)
+    dyn_entries = [(tag, dynstr_tab.offsets[value]) for tag, value in dyn_strings] + [
+        (DT_HASH, hash_.addr),
+        (DT_GNU_HASH, gnu_hash_.addr),
+        (DT_STRTAB, dynstr.addr),
+        (DT_SYMTAB, dynsym.addr),
+        (DT_STRSZ, len(dynstr.data)),
+        (DT_SYMENT, w.sym_size()),
+    ]
+    if fatbins:
+        dyn_entries += [(DT_RELA, rela.addr), (DT_RELASZ, len(rela.data)), (DT_RELAENT, 24)]
+    if versioned:
+        dyn_entries += [(DT_VERSYM, versym.addr), (DT_VERDEF, verdef.addr), (DT_VERDEFNUM, len(version_defs))]
+    dyn_entries.append((DT_NULL, 0))
+    dynamic.data = b"".join(w.dyn(tag, value) for tag, value in dyn_entries)
+    offset = align(offset, dynamic.addralign)
+    dynamic.offset = offset
+    dynamic.addr = offset + PAGE
+    offset += len(dynamic.data)
+    bss.offset = offset
+    bss.addr = align(dynamic.addr + len(dynamic.data), bss.addralign)
+    rw_start = data.offset
+    rw_filesz = offset - rw_start
+    rw_memsz = bss.addr + bss.size - data.addr
+
+    # R segment: .nv_fatbin alone, on the page after the RW segment
+    if fatbin_segment:
+        offset = align(offset, fatbin.addralign)
+        fatbin.offset = offset
+        fatbin.addr = align(data.addr + rw_memsz, PAGE) + offset % PAGE
+        offset += len(fatbin.data)
+
+    def symbol_bytes(tab, syms, name_of):
+        out = w.sym(0, 0, 0, 0, 0)
+        for sym in syms:
+            _, section, value, size, binding, sym_type, _ = sym
+            if section is None:
+                out += w.sym(tab.offsets[name_of(sym)], value, size, (binding << 4) | sym_type, SHN_ABS)
+                continue
+            out += w.sym(
+                tab.offsets[name_of(sym)], section.addr + value, size,
+                (binding << 4) | sym_type, index[section.name],
+            )
+        return out
+
+    dynsym.data = symbol_bytes(dynstr_tab, exported, lambda sym: sym[0])
+    dynsym.link = index[".dynstr"]
+    rela.link = index[".dynsym"] if fatbins else 0
+    dynsym.info = 1
+    dynamic.link = index[".dynstr"]
+    hash_.link = index[".dynsym"]
+    gnu_hash_.link = index[".dynsym"]
+    versym.link = index[".dynsym"] if versioned else 0
+    verdef.link = index[".dynstr"] if versioned else 0
+    verdef.info = len(version_defs)
+    symtab.data = symbol_bytes(strtab_tab, symbols, symtab_name)
+    symtab.link = index[".strtab"]
+    symtab.info = 1 + sum(1 for s in symbols if s[4] == STB_LOCAL)
+
+    shstr = StrTab()
+    names = [0] + [shstr.add(s.name) for s in sections[1:]]
+    shstrtab.data = bytes(shstr.data)
+
+    for s in (symtab, strtab, shstrtab):
+        offset = align(offset, s.addralign)
+        s.offset = offset
+        offset += len(s.data)
+    shoff = align(offset, 8)
+
+    out = bytearray(shoff + w.shdr_size() * len(sections))
+    out[0:w.ehdr_size()] = w.ehdr(machine, w.ehdr_size(), phnum, shoff, len(sections), index[".shstrtab"])
+    phdrs = w.phdr(PT_LOAD, PF_R | PF_X, 0, 0, rx_end, rx_end, PAGE)
+    phdrs += w.phdr(PT_LOAD, PF_R | PF_W, rw_start, rw_start + PAGE, rw_filesz, rw_memsz, PAGE)
+    if fatbin_segment:
+        phdrs += w.phdr(
+            PT_LOAD, PF_R, fatbin.offset, fatbin.addr, len(fatbin.data), len(fatbin.data), PAGE,
+        )
+    phdrs += w.phdr(PT_DYNAMIC, PF_R | PF_W, dynamic.offset, dynamic.addr, len(dynamic.data), len(dynamic.data), 8)
+    if build_id is not None:
+        phdrs += w.phdr(PT_NOTE, PF_R, note.offset, note.addr, len(note.data), len(note.data), 4)
+    if interp is not None:
+        phdrs = w.phdr(PT_INTERP, PF_R, interp_.offset, interp_.addr, len(interp_.data), len(interp_.data), 1) + phdrs
+    out[w.ehdr_size():w.ehdr_size() + len(phdrs)] = phdrs
+    for s in sections[1:]:
+        if s.sh_type != SHT_NOBITS:
+            out[s.offset:s.offset + len(s.data)] = s.data
+    shdrs = bytes(w.shdr_size())
+    for name, s in zip(names[1:], sections[1:]):
+        shdrs += w.shdr(name, s)
+    out[shoff:] = shdrs
+    return bytes(out)
+
+
+def cubin():
+    w = Writer(64, False)
+    text = {
+        name: Section(".text." + name, SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, b"\0" * 0x80, 128)
+        for name in ("_Z6launchv", "_Z5childv", "_Z6squarei", "_Z6unusedv")
+    }
+    symtab = Section(".symtab", SHT_SYMTAB, 0, b"", 8, w.sym_size())
+    strtab = Section(".strtab", SHT_STRTAB)
+    shstrtab = Section(".shstrtab", SHT_STRTAB)
+    callgraph = Section(".nv.callgraph", SHT_CUDA_CALLGRAPH, 0, b"", 4, 8)
+    rel = Section(".rel.text._Z6launchv", SHT_REL, 0, b"", 8, 16)
+    sections = [None, shstrtab, strtab, symtab, callgraph, rel] + list(text.values())
+    index = {s.name: i for i, s in enumerate(sections) if s is not None}
+
+    # (name, section), all global functions, the last one defined in another cubin
+    symbols = [(name, text[name]) for name in text] + [("_Z16setScalarItemGPUiPiii", None)]
+    sym_index = {name: i + 1 for i, (name, _) in enumerate(symbols)}
+    strtab_tab = StrTab()
+    symtab.data = w.sym(0, 0, 0, 0, 0)
+    for name, section in symbols:
+        size = len(section.data) if section else 0
+        shndx = index[section.name] if section else 0
+        symtab.data += w.sym(strtab_tab.add(name), 0, size, (STB_GLOBAL << 4) | STT_FUNC, shndx)
+    strtab.data = bytes(strtab_tab.data)
+    symtab.link = index[".strtab"]
+    symtab.info = 1
+
+    callgraph.data = b"".join(w.pack("II", 0, marker) for marker in CALLGRAPH_MARKERS)
+    callgraph.data += w.pack("II", sym_index["_Z5childv"], sym_index["_Z6squarei"])
+    callgraph.link = index[".symtab"]
+    rel.data = b"".join(
+        w.pack("QQ", offset, sym_index[name] << 32 | R_CUDA_32)
+        for offset, name in ((0x10, "_Z5childv"), (0x20, "_Z16setScalarItemGPUiPiii"))
+    )
+    rel.link = index[".symtab"]
+    rel.info = index[".text._Z6launchv"]
+
+    shstr = StrTab()
+    names = [0] + [shstr.add(s.name) for s in sections[1:]]
+    shstrtab.data = bytes(shstr.data)
+    offset = w.ehdr_size()
+    for s in sections[1:]:
+        offset = align(offset, s.addralign)
+        s.offset = offset
+        offset += len(s.data)
+    shoff = align(offset, 8)
+
+    out = bytearray(shoff + w.shdr_size() * len(sections))
+    ehdr = bytearray(w.ehdr(EM_CUDA, 0, 0, shoff, len(sections), index[".shstrtab"]))
+    ehdr[7:9] = bytes([ELFOSABI_CUDA, CUBIN_ABI_VERSION])
+    struct.pack_into("<HI", ehdr, 16, ET_EXEC, EM_CUDA)
+    struct.pack_into("<I", ehdr, 48, CUBIN_SM70_FLAGS)
+    out[0:w.ehdr_size()] = ehdr
+    for s in sections[1:]:
+        out[s.offset:s.offset + len(s.data)] = s.data
+    shdrs = bytes(w.shdr_size())
+    for name, s in zip(names[1:], sections[1:]):
+        shdrs += w.shdr(name, s)
+    out[shoff:] = shdrs
+    return bytes(out)
+
+
+def ar_archive(members):
+    # GNU format: names longer than 15 bytes go to the // table and are referenced as /offset
+    def header(name, size):
+        return b"%-16s%-12d%-6d%-6d%-8o%-10d`\n" % (name, 0, 0, 0, 0o644, size)
+
+    long_names = b""
+    names = []
+    for name, _ in members:
+        if len(name) > 15:
+            names.append(b"/%d" % len(long_names))
+            long_names += name.encode() + b"/\n"
+        else:
+            names.append(name.encode() + b"/")
+    out = b"!<arch>\n"
+    if long_names:
+        out += header(b"//", len(long_names)) + long_names + b"\n" * (len(long_names) % 2)
+    for name, (_, data) in zip(names, members):
+        out += header(name, len(data)) + data + b"\n" * (len(data) % 2)
+    return out
+
+
+def write(path, data):
+    path = os.path.join(HERE, path)
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    with open(path, "wb") as f:
+        f.write(data)
+
+
+def main():
+    for bits, big_endian, machine, name in VARIANTS:
+        write(name, build(bits, big_endian, machine, soname=name, versioned=True, build_id=BUILD_ID))
+
+    fatbins = build(64, False, EM_X86_64, soname="libfatbins.so", fatbins=True)
+    write("libfatbins.so", fatbins)
+    write("libfatbins.aarch64.so", build(64, False, EM_AARCH64, soname="libfatbins.so", fatbins=True))
+    write("libfatbins.ppc64le.so", build(64, False, EM_PPC64, soname="libfatbins.so", fatbins=True))
+
+    write("libunits.so", build(64, False, EM_X86_64, soname="libunits.so", fatbins=True, units=True))
+
+    write("libcompressed.so", build(64, False, EM_X86_64, soname="libcompressed.so", compressed=True))
+
+    write("libbloated.so", build(
+        64, False, EM_X86_64, soname="libbloated.so", build_id=BUILD_ID, fatbin_copies=8,
+        fatbin_segment=True,
+    ))
+
+    write("calls.sm_70.cubin", cubin())
+
+    with open(os.path.join(HERE, "libsynth.64le.so"), "rb") as f:
+        synth = f.read()
+    write("libsynth.a", ar_archive([
+        ("synth.o", synth),
+        ("fatbins_with_long_name.o", fatbins),
+        ("README", b"not an object\n"),
+    ]))
+    with zipfile.ZipFile(os.path.join(HERE, "synth-0.1-py3-none-any.whl"), "w") as whl:
+        # fixed timestamps keep the output reproducible
+        def add(name, data, compress_type):
+            whl.writestr(zipfile.ZipInfo(name, (2020, 1, 1, 0, 0, 0)), data, compress_type)
+
+        add("synth/__init__.py", b"", zipfile.ZIP_STORED)
+        add("synth/lib/libsynth.so", synth, zipfile.ZIP_STORED)
+        add("synth/lib/libfatbins.so", fatbins, zipfile.ZIP_DEFLATED)
+
+    write("deps/bin/app", build(
+        64, False, EM_X86_64, needed=["libfirst.so"], rpath="$ORIGIN/../lib",
+        interp="/lib64/ld-linux-x86-64.so.2",
+    ))
+    write("deps/lib/libfirst.so", build(
+        64, False, EM_X86_64, needed=["libsecond.so", "libmissing.so.1"], soname="libfirst.so",
+    ))
+    write("deps/lib/libsecond.so", build(
+        64, False, EM_X86_64, needed=["libthird.so"], soname="libsecond.so", runpath="$ORIGIN/private",
+    ))
+    write("deps/lib/private/libthird.so", build(64, False, EM_X86_64, soname="libthird.so"))
+    write("deps/lib32/libsecond.so", build(32, False, EM_386, soname="libsecond.so"))
+
+
+if __name__ == "__main__":
+    main()